@@ -0,0 +1,54 @@
+// Plays a few episodes of a game by pressing random keys, as a starting point for training a real agent:
+//
+//   cargo run --release --example random_agent -- <path_to_rom>
+use chip8::gym::{pixel, Env};
+use std::time::Instant;
+
+const EPISODES: u32 = 5;
+const MAX_STEPS: u64 = 60 * 60; // One minute of game time per episode
+
+fn main() {
+    let Some(rom_path) = std::env::args().nth(1) else {
+        println!("Usage: cargo run --example random_agent -- <path_to_rom>");
+        std::process::exit(1);
+    };
+    let rom = std::fs::read(&rom_path).unwrap_or_else(|e| {
+        println!("Error reading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+
+    let mut env = Env::new(&rom, 500, 1234).unwrap_or_else(|e| {
+        println!("Error loading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+    env.max_steps = Some(MAX_STEPS);
+
+    for episode in 1..=EPISODES {
+        let started = Instant::now();
+        env.reset();
+        let display = loop {
+            // Hold one random key (or none) each frame
+            let mut keys = [false; 16];
+            if let Some(key) = (rand::random::<u8>() % 17).checked_sub(1) {
+                keys[key as usize] = true;
+            }
+
+            let (display, done) = env.step(keys);
+            if done {
+                break display;
+            }
+        };
+
+        let lit = (0..32)
+            .flat_map(|y| (0..64).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&display, x, y))
+            .count();
+        println!(
+            "Episode {}: {} steps in {:.2?}, {} pixels lit at the end",
+            episode,
+            env.steps(),
+            started.elapsed(),
+            lit
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// Runs many instances of a game at once with random input (Monte-Carlo style), spread over all CPU cores:
+//
+//   cargo run --release --example random_pool -- <path_to_rom> [instances]
+use chip8::pool::Chip8Pool;
+use std::time::Instant;
+
+const FRAMES: u64 = 60 * 60; // One minute of game time per instance
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(rom_path) = args.next() else {
+        println!("Usage: cargo run --example random_pool -- <path_to_rom> [instances]");
+        std::process::exit(1);
+    };
+    let instances = args.next().and_then(|n| n.parse().ok()).unwrap_or(64);
+    let rom = std::fs::read(&rom_path).unwrap_or_else(|e| {
+        println!("Error reading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+
+    let mut pool = Chip8Pool::new(&rom, 500, 1, instances).unwrap_or_else(|e| {
+        println!("Error loading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+    let started = Instant::now();
+    let frames = pool.run(FRAMES, |_, _| {
+        let mut keys = [false; 16];
+        if let Some(key) = (rand::random::<u8>() % 17).checked_sub(1) {
+            keys[key as usize] = true;
+        }
+        keys
+    });
+
+    let halted = frames.iter().filter(|&&frames| frames < FRAMES).count();
+    let total: u64 = frames.iter().sum();
+    println!(
+        "{} instances ran {} frames in {:.2?}; {} halted before the time was up",
+        pool.len(),
+        total,
+        started.elapsed(),
+        halted
+    );
+}
@@ -0,0 +1,184 @@
+// Drives a 128x64 SSD1306 OLED over I2C, the way the emulator would run on a microcontroller board.
+//
+// The driver below only uses embedded-hal traits, so it works with any board's HAL. On a board, depend on the
+// core without std (`chip8 = { path = "...", default-features = false }`), set up a global allocator, and call
+// `run` from the HAL's entry point with its I2C bus, a delay and a function that reads the buttons.
+//
+// To try it on a PC, this runs the same code for a few seconds against a fake OLED that keeps the display's
+// memory, and then prints what the OLED would be showing:
+//
+//   cargo run --example ssd1306 -- <path_to_rom>
+use chip8::{Chip8, Screen};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{I2c, Operation};
+
+const CPU_HZ: u32 = 500;
+const ADDRESS: u8 = 0x3C; // The usual I2C address of SSD1306 modules (some are wired for 0x3D)
+
+// Commands (after the 0x00 control byte) that turn the display on in horizontal addressing mode
+const INIT: [u8; 26] = [
+    0x00, // Commands follow
+    0xAE, // Display off while it's set up
+    0xD5, 0x80, // Clock divide ratio/oscillator frequency
+    0xA8, 0x3F, // Multiplex ratio: 64 rows
+    0xD3, 0x00, // No display offset
+    0x40, // Start line 0
+    0x8D, 0x14, // Charge pump on
+    0x20, 0x00, // Horizontal addressing mode, so a frame can be sent in one go
+    0xA1, // Column 127 is mapped to SEG0 (i.e. not mirrored on most modules)
+    0xC8, // Scan rows from the bottom up (not upside down on most modules)
+    0xDA, 0x12, // COM pins configuration for 128x64
+    0x81, 0xCF, // Contrast
+    0xD9, 0xF1, // Pre-charge period
+    0xDB, 0x40, // VCOMH deselect level
+    0xA4, // Show the contents of memory
+    0xA6, // Not inverted
+    0xAF, // Display on
+];
+
+struct Ssd1306<I2C> {
+    i2c: I2C,
+    frame: [u8; 1 + 1024], // Data control byte, then 8 pages of 128 columns; each byte is 8 rows, top row in bit 0
+}
+
+impl<I2C: I2c> Ssd1306<I2C> {
+    fn new(mut i2c: I2C) -> Result<Self, I2C::Error> {
+        i2c.write(ADDRESS, &INIT)?;
+        let mut frame = [0; 1 + 1024];
+        frame[0] = 0x40; // Data follows
+        Ok(Ssd1306 { i2c, frame })
+    }
+}
+
+impl<I2C: I2c> Screen for Ssd1306<I2C> {
+    type Error = I2C::Error;
+
+    // Each CHIP-8 pixel becomes a 2x2 block, which fills the 128x64 screen exactly
+    // (an ETI-660's 48 rows wouldn't fit, so only 64x32 programs are shown whole)
+    fn show(&mut self, display: &[u64]) -> Result<(), Self::Error> {
+        for (index, byte) in self.frame[1..].iter_mut().enumerate() {
+            let (page, column) = (index / 128, index % 128);
+            let mask = 0x8000_0000_0000_0000 >> (column / 2);
+            *byte = (0..8).fold(0, |byte, bit| {
+                let lit = display[page * 4 + bit / 2] & mask != 0;
+                byte | (lit as u8) << bit
+            });
+        }
+
+        // Write the whole screen, starting from the top-left corner
+        self.i2c.write(ADDRESS, &[0x00, 0x21, 0, 127, 0x22, 0, 7])?;
+        self.i2c.write(ADDRESS, &self.frame)
+    }
+}
+
+// The main loop; a board would run this forever rather than for a set number of frames
+fn run<S: Screen>(
+    chip8: &mut Chip8,
+    screen: &mut S,
+    delay: &mut impl DelayNs,
+    mut read_keys: impl FnMut() -> [bool; 16],
+    frames: u32,
+) -> Result<(), S::Error> {
+    for _ in 0..frames {
+        chip8.keypad = read_keys();
+        for _ in 0..CPU_HZ / 60 {
+            chip8.emulate_cycle();
+        }
+        let _beeping = chip8.update_timers(); // Drive a buzzer pin from this if the board has one
+        screen.show(chip8.display_rows())?;
+        delay.delay_ms(16);
+    }
+    Ok(())
+}
+
+// Stand-in for the OLED on a PC: an I2C bus that writes data into a copy of the display's memory
+struct FakeOled {
+    memory: [u8; 1024],
+    position: usize, // Next byte of memory to be written
+}
+
+impl embedded_hal::i2c::ErrorType for FakeOled {
+    type Error = core::convert::Infallible;
+}
+
+impl I2c for FakeOled {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            if let Operation::Write(bytes) = operation {
+                match bytes.split_first() {
+                    // The only commands sent after setup reset the address to the top-left corner
+                    Some((0x00, _)) => self.position = 0,
+                    Some((0x40, data)) => {
+                        for &byte in data {
+                            self.memory[self.position] = byte;
+                            self.position = (self.position + 1) % 1024;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FakeOled {
+    // Two rows of OLED pixels per line of text
+    fn print(&self) {
+        let lit = |x: usize, y: usize| self.memory[y / 8 * 128 + x] & (1 << (y % 8)) != 0;
+        for y in (0..64).step_by(2) {
+            let line: String = (0..128)
+                .map(|x| match (lit(x, y), lit(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect();
+            println!("{}", line);
+        }
+    }
+}
+
+struct SleepDelay;
+
+impl DelayNs for SleepDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        std::thread::sleep(std::time::Duration::from_nanos(ns as u64));
+    }
+}
+
+fn main() {
+    let Some(rom_path) = std::env::args().nth(1) else {
+        println!("Usage: cargo run --example ssd1306 -- <path_to_rom>");
+        std::process::exit(1);
+    };
+    let rom = std::fs::read(&rom_path).unwrap_or_else(|e| {
+        println!("Error reading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+
+    let mut chip8 = Chip8::new();
+    chip8.load_bytes(&rom).unwrap_or_else(|e| {
+        println!("Error loading {}: {}", rom_path, e);
+        std::process::exit(1);
+    });
+
+    let bus = FakeOled {
+        memory: [0; 1024],
+        position: 0,
+    };
+    let Ok(mut oled) = Ssd1306::new(bus);
+    let Ok(()) = run(
+        &mut chip8,
+        &mut oled,
+        &mut SleepDelay,
+        || [false; 16],
+        3 * 60,
+    );
+    oled.i2c.print();
+}
@@ -0,0 +1,54 @@
+// Achievements: conditions on memory and registers that pop up a message the first time they are met,
+// a bit like RetroAchievements. They are defined in a TOML file given with --achievements, one section each:
+//
+//   [century]
+//   name = "Century"                  # Shown when unlocked (defaults to the section name)
+//   condition = "mem[0x3A0] >= 100"   # See expr.rs for what conditions can contain
+use chip8::Chip8;
+use crate::config::Config;
+use chip8::expr::Expr;
+use std::path::Path;
+
+pub struct Achievements {
+    list: Vec<Achievement>,
+}
+
+struct Achievement {
+    name: String,
+    condition: Expr,
+    unlocked: bool,
+}
+
+impl Achievements {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let config = Config::load(path)?;
+        let mut list = Vec::new();
+
+        for section in config.section_names() {
+            let condition = config
+                .get(section, "condition")
+                .ok_or_else(|| format!("Achievement [{}] in {} has no condition", section, path.display()))?;
+            let condition = Expr::parse(condition)
+                .map_err(|e| format!("Achievement [{}] in {}: {}", section, path.display(), e))?;
+            list.push(Achievement {
+                name: config.get(section, "name").unwrap_or(section).to_string(),
+                condition,
+                unlocked: false,
+            });
+        }
+
+        Ok(Achievements { list })
+    }
+
+    // Check the conditions that haven't been met yet; returns the names of the newly unlocked achievements
+    pub fn check(&mut self, chip8: &Chip8) -> Vec<&str> {
+        let mut unlocked = Vec::new();
+        for achievement in &mut self.list {
+            if !achievement.unlocked && achievement.condition.is_true(chip8) {
+                achievement.unlocked = true;
+                unlocked.push(achievement.name.as_str());
+            }
+        }
+        unlocked
+    }
+}
@@ -0,0 +1,161 @@
+// Announcements (--announce speech|notification): game events read out loud or sent as desktop notifications, for
+// players who can't easily follow everything on screen. Achievements are always announced; the rest is picked in
+// the [announce] section of the config file:
+//
+//   [announce]
+//   method = "speech"        # Or "notification"; --announce overrides it
+//   beep = true              # Say "beep" each time the sound timer starts
+//   faults = true            # Say what went wrong when the game hits a fault
+//   watches = "score lives"  # Watched values (see watch.rs) to read out whenever they change
+//
+// Speech uses the system's own voice (`say` on macOS, PowerShell on Windows, spd-say or espeak elsewhere) and
+// notifications use notify-send, or osascript on macOS. Announcements are made one at a time, so a burst of events
+// doesn't talk over itself; only the most recent few are kept waiting.
+use crate::config::Config;
+use crate::watch::Watches;
+use chip8::{Chip8, Fault};
+use std::collections::VecDeque;
+use std::process::{Child, Command, Stdio};
+
+const QUEUED: usize = 4; // Announcements kept waiting while one is being made
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Method {
+    Speech,
+    Notification,
+}
+
+impl Method {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "speech" | "tts" => Some(Method::Speech),
+            "notification" | "notify" => Some(Method::Notification),
+            _ => None,
+        }
+    }
+}
+
+pub struct Announcer {
+    method: Method,
+    beep: bool,                          // Whether to announce the sound timer starting
+    faults: bool,                        // Whether to announce faults
+    watches: Vec<(String, Option<i64>)>, // Watches to announce changes in, with their last values
+    beeping: bool,                       // Whether the sound timer was running as of the last tick
+    queue: VecDeque<String>,             // Announcements waiting to be made, oldest first
+    current: Option<Child>,              // The program making the current announcement
+    failed: bool,                        // Whether announcing has failed, so the error is only shown once
+}
+
+impl Announcer {
+    // The announcer set up in the config file, unless announcements are off (no method given anywhere)
+    pub fn from_config(method: Option<&str>, config: &Config) -> Result<Option<Self>, String> {
+        let Some(name) = method.or(config.get("announce", "method")) else {
+            return Ok(None);
+        };
+        let method = Method::from_name(name)
+            .ok_or_else(|| format!("Unknown announcement method '{}'; expected speech or notification", name))?;
+        if method == Method::Notification && cfg!(windows) {
+            return Err("Notifications aren't supported on Windows; use --announce speech instead".into());
+        }
+
+        let watches = config.get("announce", "watches").unwrap_or_default();
+        Ok(Some(Announcer {
+            method,
+            beep: config.get_or("announce", "beep", false),
+            faults: config.get_or("announce", "faults", true),
+            watches: watches.split_whitespace().map(|name| (name.to_string(), None)).collect(),
+            beeping: false,
+            queue: VecDeque::with_capacity(QUEUED),
+            current: None,
+            failed: false,
+        }))
+    }
+
+    pub fn say(&mut self, message: &str) {
+        if self.queue.iter().any(|queued| queued == message) {
+            return;
+        }
+        if self.queue.len() == QUEUED {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(message.to_string());
+    }
+
+    // Queue announcements for whatever changed in the last tick
+    pub fn observe(&mut self, chip8: &Chip8, beeping: bool, watches: &Watches) {
+        if self.beep && beeping && !self.beeping {
+            self.say("beep");
+        }
+        self.beeping = beeping;
+
+        let mut changes = Vec::new();
+        for (name, last) in &mut self.watches {
+            let value = watches.value(name, chip8);
+            // The first value seen is where things start from, not a change
+            if last.is_some() && value != *last {
+                if let Some(value) = value {
+                    changes.push(format!("{} {}", name, value));
+                }
+            }
+            *last = value;
+        }
+        for change in changes {
+            self.say(&change);
+        }
+    }
+
+    pub fn fault(&mut self, fault: &Fault) {
+        if self.faults {
+            // Just what kind of fault it was; the addresses aren't much use read out
+            let fault = fault.to_string();
+            let kind = fault.split(':').next().unwrap_or_default().to_string();
+            self.say(&kind);
+        }
+    }
+
+    // Start the next announcement once the last one is done; call this every frame
+    pub fn poll(&mut self) {
+        if let Some(current) = &mut self.current {
+            if let Ok(None) = current.try_wait() {
+                return;
+            }
+            self.current = None;
+        }
+        let Some(message) = self.queue.pop_front() else {
+            return;
+        };
+        match announce(self.method, &message) {
+            Ok(child) => self.current = Some(child),
+            Err(e) if !self.failed => {
+                println!("{}", e);
+                self.failed = true;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+// Start a program that makes an announcement
+fn announce(method: Method, message: &str) -> Result<Child, String> {
+    let spawn = |command: &mut Command| command.stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    let result = match method {
+        Method::Speech if cfg!(target_os = "macos") => spawn(Command::new("say").arg(message)),
+        Method::Speech if cfg!(windows) => {
+            let script = format!(
+                "Add-Type -AssemblyName System.Speech; {}.Speak('{}')",
+                "(New-Object System.Speech.Synthesis.SpeechSynthesizer)",
+                message.replace('\'', "''")
+            );
+            spawn(Command::new("powershell").args(["-NoProfile", "-Command", &script]))
+        }
+        // spd-say returns straight away unless told to wait, which would have announcements talk over each other
+        Method::Speech => spawn(Command::new("spd-say").args(["--wait", message]))
+            .or_else(|_| spawn(Command::new("espeak").arg(message))),
+        Method::Notification if cfg!(target_os = "macos") => {
+            let script = format!("display notification \"{}\" with title \"CHIP-8\"", message.replace('"', "'"));
+            spawn(Command::new("osascript").args(["-e", &script]))
+        }
+        Method::Notification => spawn(Command::new("notify-send").args(["CHIP-8", message])),
+    };
+    result.map_err(|e| format!("Error making an announcement (is a speech or notification program installed?): {}", e))
+}
@@ -0,0 +1,224 @@
+// HTTP API (enabled with `cargo run --features api -- <rom> --api <address>`): a small JSON interface for looking
+// at and driving the machine from scripts and other tools, without the debugger.
+//
+//   GET  /registers                  {"pc": 514, "i": 554, "v": [...], "sp": 0, "stack": [], "dt": 0, "st": 0,
+//                                     "paused": false}
+//   GET  /memory?start=200&length=40 {"start": 512, "bytes": [...]}; start and length are hex, like the debugger's
+//                                    addresses (all 4KB by default)
+//   GET  /screen.png?scale=8         the display in the current palette, scale pixels per CHIP-8 pixel (8 by default)
+//   POST /keys/<key>/down            hold a keypad key (0-F), as if it was pressed on the keyboard...
+//   POST /keys/<key>/up              ...and let it go
+//   POST /pause, /resume             stop and restart the game
+//
+// e.g. `curl http://127.0.0.1:9185/registers` or `curl -X POST http://127.0.0.1:9185/keys/5/down`.
+//
+// Requests are answered from a background thread with the machine as of the last frame, and the POSTs are carried
+// out at the start of the next one.
+use chip8::Chip8;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Requests are answered one at a time, so a client that connects and then sends nothing (or stops reading) is
+// dropped after this long rather than holding up everyone else
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Command {
+    Key(usize, bool),
+    Pause(bool),
+}
+
+// What the API serves, copied out of the machine every frame
+#[derive(Clone)]
+struct Machine {
+    memory: Vec<u8>,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>, // Return addresses, outermost call first
+    delay_timer: u8,
+    sound_timer: u8,
+    display: Vec<u64>, // The rows in use, top first
+    colors: [u32; 2], // Background and foreground, for the PNG
+    paused: bool,
+}
+
+pub struct Api {
+    machine: Arc<Mutex<Machine>>,
+    commands: Receiver<Command>,
+    pub keys: [bool; 16], // Keypad keys held down through the API
+    pub paused: bool,     // Whether the game has been paused through the API
+}
+
+impl Api {
+    // Start serving from a background thread
+    pub fn serve(address: &str) -> Result<Self, String> {
+        let listener =
+            TcpListener::bind(address).map_err(|e| format!("Error starting the HTTP API on {}: {}", address, e))?;
+        status!("Serving the HTTP API on http://{}/", address);
+
+        let machine = Arc::new(Mutex::new(Machine {
+            memory: vec![0; 4096],
+            v: [0; 16],
+            i: 0,
+            pc: 0,
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            display: vec![0; 32],
+            colors: [0, 0xFFFFFF],
+            paused: false,
+        }));
+        let (sender, commands) = mpsc::channel();
+        let shared = machine.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, &shared, &sender);
+            }
+        });
+
+        Ok(Api { machine, commands, keys: [false; 16], paused: false })
+    }
+
+    // Carry out the POSTs that came in since the last frame
+    pub fn poll(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::Key(key, down) => self.keys[key] = down,
+                Command::Pause(paused) => self.paused = paused,
+            }
+        }
+    }
+
+    // Make the machine as it is now the one the API serves
+    pub fn publish(&self, chip8: &Chip8, colors: [u32; 2]) {
+        let mut machine = self.machine.lock().unwrap();
+        for (address, byte) in machine.memory.iter_mut().enumerate() {
+            *byte = chip8.peek(address as u16);
+        }
+        machine.v = std::array::from_fn(|x| chip8.v(x));
+        machine.i = chip8.i();
+        machine.pc = chip8.pc();
+        machine.stack = (0..chip8.sp() as usize).map(|level| chip8.stack(level)).collect();
+        machine.delay_timer = chip8.delay_timer();
+        machine.sound_timer = chip8.sound_timer();
+        machine.display.clear();
+        machine.display.extend_from_slice(chip8.display_rows());
+        machine.colors = colors;
+        machine.paused = self.paused;
+    }
+}
+
+fn respond(mut stream: TcpStream, machine: &Mutex<Machine>, commands: &Sender<Command>) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+
+    // Only the request line matters; nothing takes a body
+    let mut request = [0; 1024];
+    let length = stream.read(&mut request).unwrap_or(0);
+    let request = String::from_utf8_lossy(&request[..length]);
+    let mut words = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, target) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let machine = machine.lock().unwrap().clone();
+    let response = match (method, path.trim_end_matches('/').split('/').collect::<Vec<_>>().as_slice()) {
+        ("GET", ["", "registers"]) => Ok(json(registers(&machine))),
+        ("GET", ["", "memory"]) => memory(&machine, query).map(json),
+        ("GET", ["", "screen.png"]) => screen(&machine, query).map(|png| ("image/png", png)),
+        ("POST", ["", "keys", key, action @ ("down" | "up")]) => match u8::from_str_radix(key, 16) {
+            Ok(key) if key < 16 => {
+                let _ = commands.send(Command::Key(key as usize, *action == "down"));
+                Ok(json("{}".into()))
+            }
+            _ => Err(("400 Bad Request", format!("Expected a keypad key from 0 to F, found `{}`", key))),
+        },
+        ("POST", ["", "pause"]) => {
+            let _ = commands.send(Command::Pause(true));
+            Ok(json("{}".into()))
+        }
+        ("POST", ["", "resume"]) => {
+            let _ = commands.send(Command::Pause(false));
+            Ok(json("{}".into()))
+        }
+        _ => Err(("404 Not Found", format!("No such endpoint: {} {}", method, path))),
+    };
+
+    let (status, (content_type, body)) = match response {
+        Ok(response) => ("200 OK", response),
+        Err((status, message)) => (status, json(format!("{{\"error\": \"{}\"}}", message.replace('"', "'")))),
+    };
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let _ = stream.write_all(header.as_bytes()).and_then(|()| stream.write_all(&body));
+}
+
+fn json(text: String) -> (&'static str, Vec<u8>) {
+    ("application/json", text.into_bytes())
+}
+
+fn registers(machine: &Machine) -> String {
+    let list = |values: &mut dyn Iterator<Item = u16>| values.map(|value| value.to_string()).collect::<Vec<_>>().join(", ");
+    format!(
+        "{{\"pc\": {}, \"i\": {}, \"v\": [{}], \"sp\": {}, \"stack\": [{}], \"dt\": {}, \"st\": {}, \"paused\": {}}}",
+        machine.pc,
+        machine.i,
+        list(&mut machine.v.iter().map(|&v| v as u16)),
+        machine.stack.len(),
+        list(&mut machine.stack.iter().copied()),
+        machine.delay_timer,
+        machine.sound_timer,
+        machine.paused
+    )
+}
+
+// A value from the query string, e.g. start in start=200&length=40
+fn parameter<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').filter_map(|pair| pair.split_once('=')).find(|&(key, _)| key == name).map(|(_, value)| value)
+}
+
+type Error = (&'static str, String);
+
+fn memory(machine: &Machine, query: &str) -> Result<String, Error> {
+    let hex = |name: &str, default: usize| match parameter(query, name) {
+        Some(text) => usize::from_str_radix(text.trim_start_matches("0x"), 16)
+            .map_err(|_| ("400 Bad Request", format!("Expected a hex number for {}, found `{}`", name, text))),
+        None => Ok(default),
+    };
+    let start = hex("start", 0)?.min(4096);
+    let length = hex("length", 4096)?.min(4096 - start);
+    let bytes: Vec<String> = machine.memory[start..start + length].iter().map(|byte| byte.to_string()).collect();
+    Ok(format!("{{\"start\": {}, \"bytes\": [{}]}}", start, bytes.join(", ")))
+}
+
+fn screen(machine: &Machine, query: &str) -> Result<Vec<u8>, Error> {
+    let scale = match parameter(query, "scale") {
+        Some(text) => text.parse().ok().filter(|scale| (1..=32).contains(scale)),
+        None => Some(8),
+    }
+    .ok_or(("400 Bad Request", "Expected a scale from 1 to 32".to_string()))?;
+
+    let (width, height) = (64 * scale, machine.display.len() * scale);
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let row = machine.display[y / scale];
+        for x in 0..width {
+            let color = machine.colors[(row >> (63 - x / scale) & 1) as usize];
+            pixels.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8]);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    let error = |e: png::EncodingError| ("500 Internal Server Error", format!("Error encoding PNG: {}", e));
+    encoder.write_header().and_then(|mut writer| writer.write_image_data(&pixels)).map_err(error)?;
+    Ok(png)
+}
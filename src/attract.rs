@@ -0,0 +1,74 @@
+// Attract mode (--attract), for arcade cabinets: cycles through a playlist of games, playing a recorded demo of
+// each (see replay.rs) for a while, until someone presses a key and takes over the game on screen.
+// The playlist lives in the config file, in the order the games should be shown:
+//
+//   [attract]
+//   seconds = 30                              # How long each demo runs (a shorter replay moves on when it ends)
+//
+//   [playlist]
+//   "roms/pong.ch8" = "demos/pong.replay"     # ROM = replay to play in attract mode
+//   "roms/tetris.ch8" = "demos/tetris.replay"
+//
+// Each demo plays under the quirk profile, quirks and load address it was recorded with, so the games can be
+// for different machines; the game keeps them when a player takes over.
+use crate::config::Config;
+use crate::replay::{Replay, Setup};
+use crate::rom::{self, RomFormat};
+use chip8::quirks::QuirkProfile;
+use std::path::Path;
+
+pub struct Attract {
+    playlist: Vec<(String, Replay)>, // ROM path and demo replay for each game
+    pub seconds: u32,                // How long each demo is shown for
+    current: usize,                  // Index of the game on screen
+    profile: QuirkProfile,           // For demos recorded before replays said which profile they were recorded with
+}
+
+impl Attract {
+    pub fn load(config: &Config, profile: QuirkProfile) -> Result<Self, String> {
+        let mut playlist = Vec::new();
+        for (rom, replay) in config.section("playlist") {
+            let replay = Replay::load(Path::new(replay))?;
+            replay.recorded_setup(profile).map_err(|e| format!("Can't play the demo of {}: {}", rom, e))?;
+            playlist.push((rom.clone(), replay));
+        }
+        if playlist.is_empty() {
+            return Err("Attract mode needs a [playlist] section in the config file".to_string());
+        }
+
+        Ok(Attract {
+            playlist,
+            seconds: config.get_or("attract", "seconds", 30),
+            current: 0,
+            profile,
+        })
+    }
+
+    // Make sure every demo plays back on its ROM the way it was recorded (see Replay::check); this only fails for
+    // what setting the demo's own profile, quirks and load address can't fix, like a different ROM
+    pub fn check(&self, format: Option<RomFormat>) -> Result<(), String> {
+        for (path, replay) in &self.playlist {
+            let rom = rom::read(path, format)?;
+            let (_, setup) = replay.recorded_setup(self.profile)?;
+            replay.check(&setup, &rom).map_err(|e| format!("Can't play the demo of {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+
+    // The quirk profile and setup to play the game on screen under
+    pub fn setup(&self) -> (QuirkProfile, Setup<'_>) {
+        let (_, replay) = &self.playlist[self.current];
+        replay.recorded_setup(self.profile).expect("load checked every demo's setup")
+    }
+
+    // ROM path and demo of the game on screen
+    pub fn current(&self) -> (&str, &Replay) {
+        let (rom, replay) = &self.playlist[self.current];
+        (rom, replay)
+    }
+
+    // Move on to the next game, going back to the first after the last
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.playlist.len();
+    }
+}
@@ -0,0 +1,276 @@
+// Sound output for the beeper
+//
+// The output device can be picked by name with --audio-device or in the config file ([audio] device = "...";
+// --list-audio-devices shows the names). If that device can't be opened we fall back to the default one, and if
+// there is no working device at all the emulator carries on silently (with a warning) rather than failing.
+//
+// Rather than starting and stopping a sound for every beep, one stream plays the whole time. Each 60Hz tick the
+// emulation generates that tick's samples (the tone or silence) into a ring buffer, which the stream plays from.
+// The ring buffer holds at most buffer_ms of sound, so beeps always start and stop within that much time.
+use rodio::cpal::traits::HostTrait;
+use rodio::{DeviceTrait, OutputStream, OutputStreamHandle, Source};
+use std::f32::consts::TAU;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_TICK: usize = SAMPLE_RATE as usize / 60;
+const TONE_HZ: f32 = 440.0; // Standard A note
+const CHUNK: usize = 64; // Samples the stream takes from the ring buffer at a time
+const SPEAKER_CUTOFF_HZ: f32 = 50.0; // Low end of the emulated VIP speaker (see Waveform::VipSpeaker)
+
+// What the beeper sounds like
+#[derive(Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine, // A clean tone
+    // The COSMAC VIP's speaker: a square wave switched on and off, with the click the speaker makes as it
+    // jumps to and from rest. The square's edges are band-limited so it doesn't alias, but the on/off steps
+    // are left sharp; they go through the speaker's high-pass response, which turns them into the clicks
+    VipSpeaker,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sine" => Some(Waveform::Sine),
+            "vip" => Some(Waveform::VipSpeaker),
+            _ => None,
+        }
+    }
+}
+
+pub struct Audio {
+    stream: Option<(OutputStream, OutputStreamHandle)>, // None when there is no working output device
+    buffer: Arc<Mutex<RingBuffer>>, // Samples waiting to be played
+    pub muted: bool,
+    pub volume: u8, // Percent of full scale
+    pub waveform: Waveform,
+    beeper: Beeper,
+}
+
+impl Audio {
+    // Open the named output device (any device whose name contains `device_name`, ignoring case), or the default
+    pub fn open(device_name: Option<&str>, buffer_ms: u32) -> Self {
+        let stream = match device_name {
+            Some(name) => open_named_device(name).or_else(|| {
+                eprintln!("Warning: couldn't open audio device '{}'; using the default device", name);
+                open_default_device()
+            }),
+            None => open_default_device(),
+        };
+
+        // Room for at least two ticks, as a frame usually produces one tick and sometimes two
+        let capacity = (SAMPLE_RATE * buffer_ms / 1000).max(SAMPLES_PER_TICK as u32 * 2) as usize;
+        let buffer = Arc::new(Mutex::new(RingBuffer::new(capacity)));
+        if let Some((_, stream_handle)) = &stream {
+            let source = BeeperSource {
+                buffer: Arc::clone(&buffer),
+                chunk: Vec::with_capacity(CHUNK),
+                position: 0,
+            };
+            if let Err(e) = stream_handle.play_raw(source) {
+                eprintln!("Warning: couldn't start audio playback ({}); continuing without sound", e);
+            }
+        }
+
+        Audio {
+            stream,
+            buffer,
+            muted: false,
+            volume: 10,
+            waveform: Waveform::Sine,
+            beeper: Beeper::new(),
+        }
+    }
+
+    // Generate one 60Hz tick's worth of sound; call this every tick
+    pub fn tick(&mut self, beeping: bool) {
+        if self.stream.is_none() {
+            return;
+        }
+
+        let amplitude = if self.muted { 0.0 } else { self.volume as f32 / 100.0 };
+        let mut buffer = self.buffer.lock().unwrap();
+        for _ in 0..SAMPLES_PER_TICK {
+            buffer.push(self.beeper.sample(self.waveform, beeping) * amplitude);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn underruns(&self) -> u64 {
+        self.buffer.lock().unwrap().underruns
+    }
+
+    // Drop any sound that hasn't played yet, e.g. when pausing
+    pub fn clear(&mut self) {
+        self.buffer.lock().unwrap().clear();
+    }
+}
+
+// Generates the beeper's sound at full scale, one sample at a time at SAMPLE_RATE; shared with the WAV recorder
+// (recording.rs) so recordings sound like what's played
+pub struct Beeper {
+    phase: f32,          // Position in the tone's cycle (0 to 1), carried between ticks so the wave stays smooth
+    speaker: (f32, f32), // Last input and output of the speaker's high-pass filter
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Beeper { phase: 0.0, speaker: (0.0, 0.0) }
+    }
+
+    pub fn sample(&mut self, waveform: Waveform, beeping: bool) -> f32 {
+        let step = TONE_HZ / SAMPLE_RATE as f32;
+        let sample = match waveform {
+            Waveform::Sine => (self.phase * TAU).sin(), // The phase stays at 0 (silence) between beeps
+            Waveform::VipSpeaker => {
+                // The speaker is driven between 0 and 1 (off and on), and the high-pass filter removes
+                // the DC, leaving a decaying kick at each start and stop
+                let drive = if beeping { band_limited_square(self.phase, step) * 0.5 + 0.5 } else { 0.0 };
+                let (last_drive, last_output) = self.speaker;
+                let rc = 1.0 / (TAU * SPEAKER_CUTOFF_HZ);
+                let output = rc / (rc + 1.0 / SAMPLE_RATE as f32) * (last_output + drive - last_drive);
+                self.speaker = (drive, output);
+                output
+            }
+        };
+        self.phase = if beeping { (self.phase + step).fract() } else { 0.0 };
+        sample
+    }
+}
+
+// Square wave between -1 and 1 at `phase` (0 to 1), with its edges smoothed by PolyBLEP so it doesn't alias;
+// `step` is the phase advanced per sample
+fn band_limited_square(phase: f32, step: f32) -> f32 {
+    let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+    naive + poly_blep(phase, step) - poly_blep((phase + 0.5).fract(), step)
+}
+
+// Correction for a step discontinuity at phase 0, spread over the sample either side of it
+fn poly_blep(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        let t = phase / step;
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - step {
+        let t = (phase - 1.0) / step;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// Fixed-size ring buffer of samples; when it is full the oldest samples are overwritten, which keeps the latency
+// bounded if the emulation runs ahead of the sound card
+struct RingBuffer {
+    samples: Vec<f32>,
+    start: usize, // Index of the oldest sample
+    len: usize,
+    #[cfg(feature = "metrics")]
+    underruns: u64, // Times the stream ran out of samples in the middle of a sound
+}
+
+impl RingBuffer {
+    fn new(capacity: usize) -> Self {
+        RingBuffer {
+            samples: vec![0.0; capacity],
+            start: 0,
+            len: 0,
+            #[cfg(feature = "metrics")]
+            underruns: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let capacity = self.samples.len();
+        self.samples[(self.start + self.len) % capacity] = sample;
+        if self.len == capacity {
+            self.start = (self.start + 1) % capacity;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<f32> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.samples[self.start];
+        self.start = (self.start + 1) % self.samples.len();
+        self.len -= 1;
+        Some(sample)
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+// The stream that plays the whole time, taking samples from the ring buffer a chunk at a time
+// (and playing silence whenever the emulation hasn't kept up)
+struct BeeperSource {
+    buffer: Arc<Mutex<RingBuffer>>,
+    chunk: Vec<f32>,
+    position: usize, // Next sample to play from chunk
+}
+
+impl Iterator for BeeperSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.chunk.len() {
+            let mut buffer = self.buffer.lock().unwrap();
+            #[cfg(feature = "metrics")]
+            if buffer.len < CHUNK && self.chunk.last().is_some_and(|&sample| sample != 0.0) {
+                buffer.underruns += 1; // Ran out in the middle of a sound, rather than just being silent
+            }
+            self.chunk.clear();
+            self.chunk.extend((0..CHUNK).map(|_| buffer.pop().unwrap_or(0.0)));
+            self.position = 0;
+        }
+        self.position += 1;
+        Some(self.chunk[self.position - 1])
+    }
+}
+
+impl Source for BeeperSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Names of the available output devices
+pub fn list_devices() -> Vec<String> {
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn open_named_device(name: &str) -> Option<(OutputStream, OutputStreamHandle)> {
+    let name = name.to_lowercase();
+    let mut devices = rodio::cpal::default_host().output_devices().ok()?;
+    let device = devices.find(|device| device.name().is_ok_and(|n| n.to_lowercase().contains(&name)))?;
+    OutputStream::try_from_device(&device).ok()
+}
+
+fn open_default_device() -> Option<(OutputStream, OutputStreamHandle)> {
+    match OutputStream::try_default() {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            eprintln!("Warning: no audio output available ({}); continuing without sound", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,95 @@
+// Quirk profile detection (--auto-quirks): runs the first few seconds of a ROM under every profile at once,
+// headlessly and with no keys pressed, and picks the one the ROM seems happiest with before the game starts.
+//
+// Profiles are ranked by, in order:
+//   1. faults (unknown opcodes, stack trouble, running off the end of memory...), fewest first; a ROM running
+//      under the wrong quirks often goes off the rails and ends up executing data
+//   2. whether anything is on screen at the end; a blank screen usually means the program crashed or hung
+//   3. how much the display churns from frame to frame, least first; garbage from e.g. the wrong FX55/FX65
+//      behavior tends to be redrawn all over the place, where a working title screen or game settles down
+// Ties go to the profile that would have been used anyway, so this only changes it on clear evidence.
+// It's a heuristic: a game that needs a key press to get going looks the same under every profile.
+use chip8::quirks::QuirkProfile;
+use chip8::Chip8;
+use std::thread;
+
+const SECONDS: u32 = 3; // Emulated time each profile gets
+const SEED: u64 = 0; // CXNN's seed, the same for every profile so they all see the same random numbers
+
+const PROFILES: [QuirkProfile; 4] =
+    [QuirkProfile::Modern, QuirkProfile::Vip, QuirkProfile::Schip, QuirkProfile::XoChip];
+
+struct Trial {
+    profile: QuirkProfile,
+    faults: usize,
+    blank: bool, // Nothing on screen at the end
+    churn: u64,  // Pixels changed between consecutive frames, summed over the run
+}
+
+// Pick a profile for `rom`, loaded and started at the given (load address, entry point), falling back to `default`
+// unless another one does clearly better
+pub fn detect(rom: &[u8], cpu_hz: u32, layout: (u16, u16), default: QuirkProfile) -> QuirkProfile {
+    let (best, trials) = rank(rom, cpu_hz, layout, default);
+    status!("Quirk profiles after {} seconds:", SECONDS);
+    for trial in &trials {
+        status!(
+            "  {:<7} {:>5} faults, {:>7} pixels changed{}{}",
+            trial.profile.name(),
+            trial.faults,
+            trial.churn,
+            if trial.blank { ", blank screen" } else { "" },
+            if trial.profile == best { "  <- picked" } else { "" }
+        );
+    }
+    best
+}
+
+// The same as detect, without printing how each profile did
+pub fn guess(rom: &[u8], cpu_hz: u32, layout: (u16, u16), default: QuirkProfile) -> QuirkProfile {
+    rank(rom, cpu_hz, layout, default).0
+}
+
+// The best profile, and how each of them did
+fn rank(rom: &[u8], cpu_hz: u32, layout: (u16, u16), default: QuirkProfile) -> (QuirkProfile, Vec<Trial>) {
+    let mut trials: Vec<Trial> = thread::scope(|scope| {
+        let runs: Vec<_> =
+            PROFILES.iter().map(|&profile| scope.spawn(move || run(rom, cpu_hz, layout, profile))).collect();
+        runs.into_iter().map(|run| run.join().unwrap()).collect()
+    });
+
+    // The default goes first so it wins ties (min_by_key keeps the first of equals)
+    trials.sort_by_key(|trial| trial.profile != default);
+    let best = trials.iter().min_by_key(|trial| (trial.faults, trial.blank, trial.churn)).unwrap().profile;
+    (best, trials)
+}
+
+fn run(rom: &[u8], cpu_hz: u32, (load_address, entry_point): (u16, u16), profile: QuirkProfile) -> Trial {
+    let mut chip8 = Chip8::new();
+    chip8.quirks = profile.quirks();
+    chip8.load_address = load_address;
+    chip8.entry_point = entry_point;
+    chip8.display_height = profile.display_height();
+    chip8.reset();
+    chip8.seed_rng(SEED);
+    chip8.load_bytes(&rom[..rom.len().min(4096 - load_address as usize)]).expect("cut down to fit");
+
+    let mut faults = 0;
+    let mut churn = 0;
+    let mut cycle_units = 0;
+    let mut previous = chip8.display;
+    for _ in 0..SECONDS * 60 {
+        // The same cycle accounting as the tick loop in main.rs
+        cycle_units += cpu_hz;
+        for _ in 0..cycle_units / 60 {
+            chip8.emulate_cycle();
+        }
+        cycle_units %= 60;
+        chip8.update_timers();
+
+        faults += chip8.take_faults().len();
+        churn += previous.iter().zip(&chip8.display).map(|(a, b)| (a ^ b).count_ones() as u64).sum::<u64>();
+        previous = chip8.display;
+    }
+
+    Trial { profile, faults, blank: chip8.display.iter().all(|&row| row == 0), churn }
+}
@@ -0,0 +1,61 @@
+// Where frames are shown and keys come from: a minifb window by default, or (on Linux) the kernel framebuffer
+// and evdev input devices, for running full-screen on a Raspberry Pi console without X11 (--backend drm).
+//
+// The methods mirror minifb's Window so the rest of the emulator doesn't care which one it is talking to.
+use minifb::{Key, KeyRepeat, Window};
+
+pub trait Backend {
+    fn is_open(&self) -> bool;
+    fn is_key_down(&self, key: Key) -> bool;
+    fn is_key_pressed(&self, key: Key, repeat: KeyRepeat) -> bool;
+    fn get_keys(&self) -> Vec<Key>;
+    fn update_with_buffer(&mut self, buffer: &[u32], width: usize, height: usize) -> Result<(), String>;
+    fn update(&mut self); // Like update_with_buffer, but leaving what's on screen as it is
+    fn set_target_fps(&mut self, fps: usize);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Window,      // A desktop window (minifb)
+    Framebuffer, // /dev/fb0 for video and /dev/input/event* for keys
+}
+
+impl BackendKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "window" | "minifb" => Some(BackendKind::Window),
+            "drm" | "fbdev" | "framebuffer" => Some(BackendKind::Framebuffer),
+            _ => None,
+        }
+    }
+}
+
+impl Backend for Window {
+    fn is_open(&self) -> bool {
+        Window::is_open(self)
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        Window::is_key_down(self, key)
+    }
+
+    fn is_key_pressed(&self, key: Key, repeat: KeyRepeat) -> bool {
+        Window::is_key_pressed(self, key, repeat)
+    }
+
+    fn get_keys(&self) -> Vec<Key> {
+        Window::get_keys(self)
+    }
+
+    fn update_with_buffer(&mut self, buffer: &[u32], width: usize, height: usize) -> Result<(), String> {
+        Window::update_with_buffer(self, buffer, width, height).map_err(|e| e.to_string())
+    }
+
+    fn update(&mut self) {
+        Window::update(self)
+    }
+
+    fn set_target_fps(&mut self, fps: usize) {
+        Window::set_target_fps(self, fps)
+    }
+}
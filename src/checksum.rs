@@ -0,0 +1,31 @@
+// ROM checksums, printed when a ROM is loaded so players can tell whether they're running the same file, e.g. for
+// a tournament or speedrun category. `--verify <hash>` takes either one and refuses to start the ROM if it doesn't
+// match. SHA-1 is the one to compare when it matters; CRC32 is shorter to read out and is what most ROM databases
+// list.
+pub struct Checksums {
+    pub sha1: String,  // 40 lowercase hex digits
+    pub crc32: String, // 8 lowercase hex digits
+}
+
+impl Checksums {
+    pub fn of(rom: &[u8]) -> Self {
+        Checksums {
+            sha1: sha1_smol::Sha1::from(rom).digest().to_string(),
+            crc32: format!("{:08x}", crc32fast::hash(rom)),
+        }
+    }
+
+    // Check the ROM against an expected SHA-1 or CRC32, telling them apart by length
+    pub fn verify(&self, expected: &str) -> Result<(), String> {
+        let expected = expected.trim().trim_start_matches("0x").to_lowercase();
+        let (name, actual) = match expected.len() {
+            40 => ("SHA-1", &self.sha1),
+            8 => ("CRC32", &self.crc32),
+            _ => return Err(format!("Can't verify against '{}'; expected a SHA-1 or CRC32 in hex", expected)),
+        };
+        if *actual != expected {
+            return Err(format!("ROM doesn't match: its {} is {}, not {}", name, actual, expected));
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,19 @@
-use crate::interface::Interface;
+use crate::coverage::Coverage;
+use crate::devices::Devices;
+use crate::heatmap::HeatMap;
+use crate::quirks::{MemoryIncrement, Quirks};
+use alloc::vec::Vec;
+use core::fmt;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+#[cfg(feature = "std")]
 use std::{fs::read, path::Path};
 
+#[cfg(feature = "jit")]
+mod jit;
+#[cfg(feature = "jit")]
+pub use jit::Jit;
+
 // Memory layout according to http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
 // 0x000-0x1FF: CHIP-8 interpreter memory
 //              (in our software emulator we will just never read/write to this area)
@@ -17,7 +30,90 @@ pub struct Chip8 {
     sp: u8,                   // Stack pointer
     delay_timer: u8,          // Delay timer
     sound_timer: u8,          // Sound timer
-    pub interface: Interface, // See interface.rs for rendering display and receiving input
+    rng: ChaCha12Rng,         // Source of CXNN's random numbers (as StdRng); seeded so replays are deterministic
+    draws: u64,               // Number of DXYN instructions run since power-on
+    pub display: [u64; MAX_DISPLAY_HEIGHT], // One u64 per display row; the most significant bit is the leftmost pixel
+    pub display_height: usize, // Rows of the display in use (see QuirkProfile::display_height); the rest stay blank
+    pub quirks: Quirks,       // See quirks.rs for the behaviors that differ between interpreters
+    pub heat_map: Option<HeatMap>, // Memory access tracking for the heat map debug view (None when disabled)
+    pub coverage: Option<Coverage>, // Instruction counts for the opcode coverage report (None when disabled)
+    pub devices: Option<Devices>, // Nonstandard memory-mapped devices (see devices.rs; None unless turned on)
+    pub keypad: [bool; 16],   // Keys held on the hex keypad, as seen by EX9E/EXA1/FX0A; set by the front end
+    pub debug_mode: bool,     // Print additional information while running
+    pub trap_reserved_writes: bool, // Treat writes to the interpreter/font area (0x000-0x1FF) as faults
+    pub load_address: u16,    // Where load_bytes puts the program (0x200 unless the ROM is for another machine)
+    pub entry_point: u16,     // Where execution starts after a reset
+    faults: Vec<Fault>,       // Faults since the front end last checked (see take_faults)
+    collided: bool,           // Whether a sprite has collided since the front end last checked (see take_collision)
+}
+
+// Things a program can do that real hardware wouldn't survive, which usually mean the program has gone wrong.
+// Rather than crashing, the emulator carries on as described for each one and records the fault for the front end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    UnknownOpcode { addr: u16, opcode: u16 },      // Skipped
+    StackOverflow { addr: u16 },                   // CALL with all 16 stack entries in use; the call is skipped
+    StackUnderflow { addr: u16 },                  // RET with nothing on the stack; skipped
+    MemoryOutOfBounds { addr: u16, target: u16 },  // Data access past 0xFFF; wraps around to the start of memory
+    PcOutOfBounds { pc: u16 },                     // Execution ran off the end of memory; wraps around too
+    ReservedWrite { addr: u16, target: u16 },      // Write to 0x000-0x1FF (only with trap_reserved_writes); allowed
+}
+
+impl Fault {
+    // Short description of the kind of fault, without the details
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fault::UnknownOpcode { .. } => "unknown opcode",
+            Fault::StackOverflow { .. } => "stack overflow",
+            Fault::StackUnderflow { .. } => "stack underflow",
+            Fault::MemoryOutOfBounds { .. } => "out of bounds memory access",
+            Fault::PcOutOfBounds { .. } => "program counter out of bounds",
+            Fault::ReservedWrite { .. } => "write to reserved memory",
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::UnknownOpcode { addr, opcode } => write!(f, "Unknown opcode: {:X} at {:03X}", opcode, addr),
+            Fault::StackOverflow { addr } => write!(f, "Stack overflow: CALL at {:03X} with a full stack", addr),
+            Fault::StackUnderflow { addr } => write!(f, "Stack underflow: RET at {:03X} with an empty stack", addr),
+            Fault::MemoryOutOfBounds { addr, target } => {
+                write!(f, "Out of bounds memory access: {:X} by the instruction at {:03X}", target, addr)
+            }
+            Fault::PcOutOfBounds { pc } => write!(f, "Program counter out of bounds: {:X}", pc),
+            Fault::ReservedWrite { addr, target } => {
+                write!(f, "Write to reserved memory: {:03X} by the instruction at {:03X}", target, addr)
+            }
+        }
+    }
+}
+
+// Where programs are loaded and start running, unless told otherwise
+pub const PROGRAM_START: u16 = 0x200;
+
+// Rows of the tallest display there is, the ETI-660's 64x48; everything else is 64x32
+pub const MAX_DISPLAY_HEIGHT: usize = 48;
+
+// Faults kept until the front end takes them; a program stuck faulting shouldn't eat all the memory
+const MAX_PENDING_FAULTS: usize = 64;
+
+// Everything needed to put the machine back exactly where it was (see snapshot and restore, and state.rs for how
+// states are saved to disk)
+#[derive(Clone)]
+pub struct MachineState {
+    pub(crate) memory: [u8; 4096],
+    pub(crate) v: [u8; 16],
+    pub(crate) i: u16,
+    pub(crate) pc: u16,
+    pub(crate) stack: [u16; 16],
+    pub(crate) sp: u8,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) rng: ChaCha12Rng,
+    pub(crate) draws: u64,
+    pub display: [u64; MAX_DISPLAY_HEIGHT],
 }
 
 const FONTSET: [u8; 80] = [
@@ -39,18 +135,49 @@ const FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+#[cfg(feature = "std")]
+fn new_rng() -> ChaCha12Rng {
+    ChaCha12Rng::from_os_rng()
+}
+
+// Without std there's no OS randomness to draw on, so boards should call seed_rng with something like a timer value
+#[cfg(not(feature = "std"))]
+fn new_rng() -> ChaCha12Rng {
+    ChaCha12Rng::seed_from_u64(0)
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
+}
+
 impl Chip8 {
-    pub fn new(interface: Interface) -> Self {
+    pub fn new() -> Self {
         let mut chip8 = Chip8 {
             memory: [0; 4096],
             v: [0; 16],
             i: 0,
-            pc: 0x200, // Programs typically start at memory address 0x200
+            pc: PROGRAM_START, // Programs typically start at memory address 0x200
             stack: [0; 16],
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
-            interface: interface,
+            rng: new_rng(),
+            draws: 0,
+            display: [0; MAX_DISPLAY_HEIGHT],
+            display_height: 32,
+            quirks: Quirks::default(),
+            heat_map: None,
+            coverage: None,
+            devices: None,
+            keypad: [false; 16],
+            debug_mode: false,
+            trap_reserved_writes: false,
+            load_address: PROGRAM_START,
+            entry_point: PROGRAM_START,
+            faults: Vec::new(),
+            collided: false,
         };
 
         // Load fontset into memory
@@ -59,17 +186,70 @@ impl Chip8 {
         chip8
     }
 
-    pub fn load_program(&mut self, program: &Path) {
-        // We load program into memory starting at 0x200
-        let start = 0x200;
+    // Put the machine back in its power-on state (keeping the keypad, quirk, load and display height settings)
+    pub fn reset(&mut self) {
+        self.memory = [0; 4096];
+        self.memory[0..80].copy_from_slice(&FONTSET);
+        self.v = [0; 16];
+        self.i = 0;
+        self.pc = self.entry_point;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.draws = 0;
+        self.display = [0; MAX_DISPLAY_HEIGHT];
+        if let Some(devices) = &mut self.devices {
+            devices.reset();
+        }
+    }
 
+    // Make CXNN's random numbers repeatable, e.g. for replays
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            memory: self.memory,
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            rng: self.rng.clone(),
+            draws: self.draws,
+            display: self.display,
+        }
+    }
+
+    pub fn restore(&mut self, state: &MachineState) {
+        self.memory = state.memory;
+        self.v = state.v;
+        self.i = state.i;
+        self.pc = state.pc;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.rng = state.rng.clone();
+        self.draws = state.draws;
+        self.display = state.display;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_program(&mut self, program: &Path) {
         // Read binary data from Path object
         match read(program) {
             Ok(contents) => {
                 // `contents` is a Vec<u8> containing the binary data of the file
                 // You can now process the binary data as needed
                 println!("File read successfully. Size: {} bytes", contents.len());
-                self.memory[start..(start + contents.len())].copy_from_slice(&contents);
+                if let Err(e) = self.load_bytes(&contents) {
+                    eprintln!("Error loading file: {}", e);
+                }
             }
             Err(e) => {
                 eprintln!("Error reading file: {}", e);
@@ -77,28 +257,115 @@ impl Chip8 {
         }
     }
 
+    // Fails, leaving memory untouched, if the program runs past the end of memory
+    pub fn load_bytes(&mut self, program: &[u8]) -> Result<(), &'static str> {
+        // We load program into memory starting at 0x200 (or wherever load_address says)
+        let start = self.load_address as usize;
+        let destination = self.memory.get_mut(start..start + program.len()).ok_or("The program doesn't fit in memory")?;
+        destination.copy_from_slice(program);
+        Ok(())
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Read-only access to the rest of the machine state for tools like expr.rs (peeks don't show on the heat map)
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize & 0xFFF]
+    }
+
+    pub fn v(&self, x: usize) -> u8 {
+        self.v[x]
+    }
+
+    // Set a register, e.g. to follow along with another emulator's random numbers
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    // Return address at a level of the stack (0 is the outermost call)
+    pub fn stack(&self, level: usize) -> u16 {
+        self.stack[level % 16]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    // The rows of the display in use, top first
+    pub fn display_rows(&self) -> &[u64] {
+        &self.display[..self.display_height]
+    }
+
+    // Overwrite a byte from outside the program (e.g. for fault injection)
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.memory[addr as usize & 0xFFF] = value;
+    }
+
+    // Faults that happened since the last call, oldest first
+    pub fn take_faults(&mut self) -> Vec<Fault> {
+        core::mem::take(&mut self.faults)
+    }
+
+    // Whether DXYN drew over a lit pixel (setting VF) since the last call, e.g. for rumble on hits
+    pub fn take_collision(&mut self) -> bool {
+        core::mem::take(&mut self.collided)
+    }
+
+    // Whether the program is held at FX0A, waiting for a key press; the front end can use this to show the game
+    // isn't frozen. True from when FX0A is reached until a key comes in
+    pub fn waiting_for_key(&self) -> bool {
+        let opcode = u16::from_be_bytes([self.peek(self.pc), self.peek(self.pc + 1)]);
+        opcode & 0xF0FF == 0xF00A
+    }
+
     pub fn emulate_cycle(&mut self) {
         // 1. Fetch instruction
         let instruction = self.fetch_instruction();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(instruction);
+        }
 
         // 2. Execute instruction
         self.execute_instruction(instruction);
 
-        // 3. Update timers
-        self.update_timers();
+        // (Timers run separately at 60Hz regardless of CPU speed; see update_timers)
     }
 
     fn fetch_instruction(&mut self) -> u16 {
         // An instruction is two bytes but memory is addressed as a single byte,
         // so when we fetch an instruction from memory we need to fetch a byte from PC
         // and a byte from PC+1 and connect them into a single value;
+        if self.pc > 0xFFE {
+            self.fault(Fault::PcOutOfBounds { pc: self.pc });
+            self.pc &= 0xFFE;
+        }
         let high_byte = self.memory[self.pc as usize] as u16;
         let low_byte = self.memory[(self.pc + 1) as usize] as u16;
+        if let Some(heat_map) = &mut self.heat_map {
+            heat_map.execute(self.pc as usize);
+            heat_map.execute(self.pc as usize + 1);
+        }
 
         // Read instruction from top 4 bits
-        let instruction = (high_byte << 8) | low_byte;
-
-        instruction
+        (high_byte << 8) | low_byte
     }
 
     fn execute_instruction(&mut self, opcode: u16) {
@@ -114,7 +381,7 @@ impl Chip8 {
                 match opcode & 0x00FF {
                     0x00E0 => self.cls(), // CLS: Clear the display
                     0x00EE => self.ret(), // RET: Return from subroutine
-                    _ => println!("Unknown opcode: {:X}", opcode),
+                    _ => self.unknown_opcode(opcode),
                 }
             }
             0x1000 => self.jp(opcode),   // JP: Jump to address NNN
@@ -189,7 +456,7 @@ impl Chip8 {
                         let x = ((opcode & 0x0F00) >> 8) as usize; // x
                         self.shl_vx(x); // SHL Vx: Set Vx = Vx SHL 1
                     }
-                    _ => println!("Unknown opcode: {:X}", opcode),
+                    _ => self.unknown_opcode(opcode),
                 }
             }
             0x9000 => {
@@ -203,7 +470,12 @@ impl Chip8 {
             }
             0xB000 => {
                 let nnn = opcode & 0x0FFF;
-                self.jp_v0(nnn); // JP V0, addr: Jump to location nnn + V0
+                if self.quirks.jump_vx {
+                    let x = ((opcode & 0x0F00) >> 8) as usize; // x
+                    self.jp_vx(x, nnn); // JP Vx, addr: Jump to location xnn + Vx (CHIP-48/SUPER-CHIP)
+                } else {
+                    self.jp_v0(nnn); // JP V0, addr: Jump to location nnn + V0
+                }
             }
             0xC000 => {
                 let x = ((opcode & 0x0F00) >> 8) as usize; // x
@@ -226,7 +498,7 @@ impl Chip8 {
                         let x = ((opcode & 0x0F00) >> 8) as usize; // x
                         self.sknp(x); // SKNP Vx: Skip next instruction if key with the value of Vx is not pressed
                     }
-                    _ => println!("Unknown opcode: {:X}", opcode),
+                    _ => self.unknown_opcode(opcode),
                 }
             }
             0xF000 => {
@@ -267,33 +539,87 @@ impl Chip8 {
                         let x = ((opcode & 0x0F00) >> 8) as usize; // x
                         self.ld_vx_i(x); // LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
                     }
-                    _ => println!("Unknown opcode: {:X}", opcode),
+                    _ => self.unknown_opcode(opcode),
                 }
             }
-            _ => println!("Unknown opcode: {:X}", opcode),
+            _ => self.unknown_opcode(opcode),
         }
     }
 
-    fn update_timers(&mut self) {
+    // Count the delay and sound timers down; call this at 60Hz. Returns whether the beeper is on for this tick
+    pub fn update_timers(&mut self) -> bool {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
-        if self.sound_timer > 0 {
-            self.interface.set_beep(true);
+        let beeping = self.sound_timer > 0;
+        if beeping {
             self.sound_timer -= 1;
-        } else {
-            self.interface.set_beep(false);
         }
+
+        if let Some(devices) = &mut self.devices {
+            devices.tick();
+        }
+
+        beeping
+    }
+
+    // Data reads and writes made by instructions go through these (instruction fetches don't)
+    fn read_byte(&mut self, addr: u16) -> u8 {
+        let addr = self.check_bounds(addr);
+        if let Some(heat_map) = &mut self.heat_map {
+            heat_map.read(addr as usize);
+        }
+        if let Some(value) = self.devices.as_ref().and_then(|devices| devices.read(addr)) {
+            return value;
+        }
+        self.memory[addr as usize]
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        let addr = self.check_bounds(addr);
+        if self.trap_reserved_writes && addr < 0x200 {
+            // Usually a bug in the ROM or a bad I value; the font lives here, so digits would get corrupted
+            self.fault(Fault::ReservedWrite { addr: self.pc - 2, target: addr });
+        }
+        if let Some(heat_map) = &mut self.heat_map {
+            heat_map.write(addr as usize);
+        }
+        if self.devices.as_mut().is_some_and(|devices| devices.write(addr, value)) {
+            return;
+        }
+        self.memory[addr as usize] = value;
+    }
+
+    // Wrap data accesses past the end of memory back around to the start
+    fn check_bounds(&mut self, addr: u16) -> u16 {
+        if addr > 0xFFF {
+            self.fault(Fault::MemoryOutOfBounds { addr: self.pc - 2, target: addr });
+        }
+        addr & 0xFFF
+    }
+
+    fn fault(&mut self, fault: Fault) {
+        if self.faults.len() < MAX_PENDING_FAULTS {
+            self.faults.push(fault);
+        }
+    }
+
+    fn unknown_opcode(&mut self, opcode: u16) {
+        self.fault(Fault::UnknownOpcode { addr: self.pc - 2, opcode });
     }
 
     fn cls(&mut self) {
-        // Clear the screen buffer
-        self.interface.framebuffer.fill(0);
+        // Clear the display
+        self.display = [0; MAX_DISPLAY_HEIGHT];
     }
 
     fn ret(&mut self) {
         // Return from subroutine
+        if self.sp == 0 {
+            self.fault(Fault::StackUnderflow { addr: self.pc - 2 });
+            return;
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp as usize];
     }
@@ -307,6 +633,10 @@ impl Chip8 {
     fn call(&mut self, opcode: u16) {
         // Call subroutine at address NNN
         let nnn = opcode & 0x0FFF;
+        if self.sp as usize == self.stack.len() {
+            self.fault(Fault::StackOverflow { addr: self.pc - 2 });
+            return;
+        }
         self.stack[self.sp as usize] = self.pc;
         self.sp += 1;
         self.pc = nnn;
@@ -351,49 +681,68 @@ impl Chip8 {
     fn or_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vx OR Vy
         self.v[x] |= self.v[y];
+        self.reset_vf_after_logic_op();
     }
 
     fn and_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vx AND Vy
         self.v[x] &= self.v[y];
+        self.reset_vf_after_logic_op();
     }
 
     fn xor_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vx XOR Vy
         self.v[x] ^= self.v[y];
+        self.reset_vf_after_logic_op();
+    }
+
+    fn reset_vf_after_logic_op(&mut self) {
+        // The COSMAC VIP clobbered VF when doing logical operations (see Quirks::vf_reset)
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
     }
 
     fn add_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vx + Vy, set VF = carry
         let (result, overflow) = self.v[x].overflowing_add(self.v[y]);
-        self.v[x] = result;
-        self.v[0xF] = overflow as u8;
+        self.set_vx_and_vf(x, result, overflow as u8);
     }
 
     fn sub_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vx - Vy, set VF = NOT borrow
         let (result, overflow) = self.v[x].overflowing_sub(self.v[y]);
-        self.v[x] = result;
-        self.v[0xF] = !overflow as u8;
+        self.set_vx_and_vf(x, result, !overflow as u8);
     }
 
     fn shr_vx(&mut self, x: usize) {
         // Set Vx = Vx SHR 1
-        self.v[0xF] = self.v[x] & 0x1;
-        self.v[x] >>= 1;
+        let flag = self.v[x] & 0x1;
+        self.set_vx_and_vf(x, self.v[x] >> 1, flag);
     }
 
     fn subn_vx_vy(&mut self, x: usize, y: usize) {
         // Set Vx = Vy - Vx, set VF = NOT borrow
         let (result, overflow) = self.v[y].overflowing_sub(self.v[x]);
-        self.v[x] = result;
-        self.v[0xF] = !overflow as u8;
+        self.set_vx_and_vf(x, result, !overflow as u8);
     }
 
     fn shl_vx(&mut self, x: usize) {
         // Set Vx = Vx SHL 1
-        self.v[0xF] = (self.v[x] & 0x80) >> 7;
-        self.v[x] <<= 1;
+        let flag = (self.v[x] & 0x80) >> 7;
+        self.set_vx_and_vf(x, self.v[x] << 1, flag);
+    }
+
+    fn set_vx_and_vf(&mut self, x: usize, result: u8, flag: u8) {
+        // Both values are computed from the original operands before either is written,
+        // so the write order only matters when x is VF itself (see Quirks::vf_result_last)
+        if self.quirks.vf_result_last {
+            self.v[0xF] = flag;
+            self.v[x] = result;
+        } else {
+            self.v[x] = result;
+            self.v[0xF] = flag;
+        }
     }
 
     fn sne_vx_vy(&mut self, x: usize, y: usize) {
@@ -413,19 +762,26 @@ impl Chip8 {
         self.pc = nnn + self.v[0] as u16;
     }
 
+    fn jp_vx(&mut self, x: usize, xnn: u16) {
+        // Jump to location xnn + Vx, where x is also the top nibble of the address
+        self.pc = xnn + self.v[x] as u16;
+    }
+
     fn rnd(&mut self, x: usize, kk: u8) {
         // Set Vx = random byte AND kk
-        let random_byte: u8 = rand::random();
+        let random_byte: u8 = self.rng.random();
         self.v[x] = random_byte & kk;
     }
 
     fn drw(&mut self, x: usize, y: usize, n: u8) {
-        if self.interface.debug_mode {
+        #[cfg(feature = "std")]
+        if self.debug_mode {
             println!("Drawing {}-byte sprite at ({}, {})", n, self.v[x], self.v[y]);
         }
+        self.draws += 1;
 
         // Get the starting coordinates from the input registers
-        let start_x = self.v[x] as usize;
+        let start_x = self.v[x] as u32;
         let start_y = self.v[y] as usize;
 
         // Reset the collision flag (VF)
@@ -434,41 +790,37 @@ impl Chip8 {
         // Loop over each of the n rows of the sprite (one row per byte)
         for yline in 0..n {
             // Get the 8-bit sprite data for the current row from memory
-            let sprite_data = self.memory[(self.i + yline as u16) as usize];
-
-            // Loop over the 8 bits (pixels) of the current sprite row
-            for xline in 0..8 {
-                // Check if the current bit/pixel is set to 1
-                if (sprite_data & (0x80 >> xline)) != 0 {
-                    // Calculate the final screen coordinates for the pixel, applying wrapping
-                    let final_x = (start_x + xline) % 64;
-                    let final_y = (start_y + yline as usize) % 32;
-
-                    // Convert 2D coordinates to a 1D index for the screen buffer
-                    let index = final_y * 64 + final_x;
-
-                    // Check for collision: if the pixel on screen is already on, set the VF flag
-                    if self.interface.framebuffer[index] == 0xFFFFFFFF {
-                        self.v[0xF] = 1;
-                    }
+            let sprite_data = self.read_byte(self.i + yline as u16);
 
-                    // XOR the pixel onto the screen buffer
-                    self.interface.framebuffer[index] ^= 0xFFFFFFFF;
-                }
+            // Line the sprite byte up with the left edge of a row, then rotate it into place;
+            // rotating (rather than shifting) wraps pixels past the right edge back to the left
+            let sprite_row = ((sprite_data as u64) << 56).rotate_right(start_x % 64);
+
+            // Apply vertical wrapping to find the display row
+            let row = &mut self.display[(start_y + yline as usize) % self.display_height];
+
+            // Check for collision: if any sprite pixel lands on a pixel that is already on, set the VF flag
+            if *row & sprite_row != 0 {
+                self.v[0xF] = 1;
+                self.collided = true;
             }
+
+            // XOR the sprite row onto the display
+            *row ^= sprite_row;
         }
     }
 
     fn skp(&mut self, x: usize) {
-        // Skip next instruction if key with the value of Vx is pressed
-        if self.interface.keypad[self.v[x] as usize] {
+        // Skip next instruction if key with the value of Vx is pressed; only the low nibble of Vx picks the key,
+        // as on the COSMAC VIP, so values of 16 and up don't run off the end of the keypad
+        if self.keypad[(self.v[x] & 0xF) as usize] {
             self.pc += 2;
         }
     }
 
     fn sknp(&mut self, x: usize) {
         // Skip next instruction if key with the value of Vx is not pressed
-        if !self.interface.keypad[self.v[x] as usize] {
+        if !self.keypad[(self.v[x] & 0xF) as usize] {
             self.pc += 2;
         }
     }
@@ -481,7 +833,7 @@ impl Chip8 {
     fn ld_vx_k(&mut self, x: usize) {
         // Wait for a key press, store the value of the key in Vx
         for i in 0..16 {
-            if self.interface.keypad[i] {
+            if self.keypad[i] {
                 self.v[x] = i as u8;
                 return;
             }
@@ -500,8 +852,12 @@ impl Chip8 {
     }
 
     fn add_i_vx(&mut self, x: usize) {
-        // Set I = I + Vx
-        self.i += self.v[x] as u16;
+        // Set I = I + Vx, wrapping within the 4KB address space
+        let sum = self.i + self.v[x] as u16;
+        if self.quirks.i_overflow_flag {
+            self.v[0xF] = (sum > 0x0FFF) as u8;
+        }
+        self.i = sum & 0x0FFF;
     }
 
     fn ld_f_vx(&mut self, x: usize) {
@@ -512,22 +868,217 @@ impl Chip8 {
     fn ld_b_vx(&mut self, x: usize) {
         // Store BCD representation of Vx in memory locations I, I+1, and I+2
         let vx = self.v[x];
-        self.memory[self.i as usize] = vx / 100;
-        self.memory[(self.i + 1) as usize] = (vx / 10) % 10;
-        self.memory[(self.i + 2) as usize] = vx % 10;
+        self.write_byte(self.i, vx / 100);
+        self.write_byte(self.i + 1, (vx / 10) % 10);
+        self.write_byte(self.i + 2, vx % 10);
     }
 
     fn ld_i_vx(&mut self, x: usize) {
         // Store registers V0 through Vx in memory starting at location I
         for i in 0..=x {
-            self.memory[(self.i + i as u16) as usize] = self.v[i];
+            self.write_byte(self.i.wrapping_add(i as u16), self.v[i]);
         }
+        self.increment_i_after_memory_access(x);
     }
 
     fn ld_vx_i(&mut self, x: usize) {
         // Read registers V0 through Vx from memory starting at location I
         for i in 0..=x {
-            self.v[i] = self.memory[(self.i + i as u16) as usize];
+            self.v[i] = self.read_byte(self.i.wrapping_add(i as u16));
+        }
+        self.increment_i_after_memory_access(x);
+    }
+
+    fn increment_i_after_memory_access(&mut self, x: usize) {
+        // Older interpreters advanced I while storing/loading registers (see Quirks::memory_increment); like FX1E,
+        // I wraps around within the 4KB address space
+        match self.quirks.memory_increment {
+            MemoryIncrement::Unchanged => (),
+            MemoryIncrement::ByX => self.i = (self.i + x as u16) & 0x0FFF,
+            MemoryIncrement::ByXPlusOne => self.i = (self.i + x as u16 + 1) & 0x0FFF,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::QuirkProfile;
+
+    const PROFILES: [QuirkProfile; 6] = [
+        QuirkProfile::Modern,
+        QuirkProfile::Vip,
+        QuirkProfile::Schip,
+        QuirkProfile::XoChip,
+        QuirkProfile::Eti660,
+        QuirkProfile::Dream6800,
+    ];
+
+    // VF after running one instruction with VF as both operands, starting from the given value
+    fn vf_after(quirks: Quirks, opcode: u16, vf: u8) -> u8 {
+        let mut chip8 = Chip8::new();
+        chip8.quirks = quirks;
+        chip8.load_bytes(&opcode.to_be_bytes()).unwrap();
+        chip8.set_v(0xF, vf);
+        chip8.emulate_cycle();
+        chip8.v(0xF)
+    }
+
+    // The examples in the comment on Quirks::vf_result_last: (opcode, VF before, VF with the flag written last,
+    // VF with the result written last)
+    const CASES: [(u16, u8, u8, u8); 4] = [
+        (0x8FF4, 0x80, 0x01, 0x00),
+        (0x8FF5, 0x01, 0x01, 0x00),
+        (0x8FF6, 0x02, 0x00, 0x01),
+        (0x8FFE, 0xFF, 0x01, 0xFE),
+    ];
+
+    #[test]
+    fn vf_as_destination_under_each_profile() {
+        for profile in PROFILES {
+            for vf_result_last in [false, true] {
+                let mut quirks = profile.quirks();
+                quirks.vf_result_last = vf_result_last;
+                for (opcode, before, flag_last, result_last) in CASES {
+                    let expected = if vf_result_last { result_last } else { flag_last };
+                    assert_eq!(
+                        vf_after(quirks, opcode, before),
+                        expected,
+                        "{:04X} with VF = {:02X} under {} (vf_result_last = {})",
+                        opcode,
+                        before,
+                        profile.name(),
+                        vf_result_last
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn profiles_write_the_flag_last() {
+        for profile in PROFILES {
+            let quirks = profile.quirks();
+            for (opcode, before, flag_last, _) in CASES {
+                assert_eq!(vf_after(quirks, opcode, before), flag_last, "{:04X} under {}", opcode, profile.name());
+            }
+        }
+    }
+
+    // Draw a sprite at (x, y) by running LD I, 300 and DRW V0, V1, n from wherever PC is; returns VF
+    fn drw(chip8: &mut Chip8, x: u8, y: u8, sprite: &[u8]) -> u8 {
+        for (offset, &byte) in sprite.iter().enumerate() {
+            chip8.poke(0x300 + offset as u16, byte);
         }
+        let pc = chip8.pc();
+        let program = [0xA3, 0x00, 0xD0, 0x10 | sprite.len() as u8];
+        for (offset, &byte) in program.iter().enumerate() {
+            chip8.poke(pc + offset as u16, byte);
+        }
+        chip8.set_v(0, x);
+        chip8.set_v(1, y);
+        chip8.emulate_cycle();
+        chip8.emulate_cycle();
+        chip8.v(0xF)
+    }
+
+    // A display row with the given columns lit
+    fn row(columns: impl IntoIterator<Item = usize>) -> u64 {
+        columns.into_iter().fold(0, |row, x| row | 0x8000_0000_0000_0000 >> x)
+    }
+
+    #[test]
+    fn drw_xors_and_flags_collisions() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(drw(&mut chip8, 0, 0, &[0xFF]), 0);
+        assert_eq!(chip8.display[0], row(0..8));
+
+        // Turning lit pixels off is a collision; drawing beside them isn't
+        assert_eq!(drw(&mut chip8, 0, 0, &[0x0F]), 1);
+        assert_eq!(chip8.display[0], row(0..4));
+        assert_eq!(drw(&mut chip8, 4, 0, &[0xF0]), 0);
+        assert_eq!(chip8.display[0], row(0..8));
+
+        // Drawing the same sprite twice erases it
+        assert_eq!(drw(&mut chip8, 20, 5, &[0xA5, 0x5A]), 0);
+        assert_eq!(drw(&mut chip8, 20, 5, &[0xA5, 0x5A]), 1);
+        assert_eq!(chip8.display[5..7], [0, 0]);
+        assert_eq!(chip8.display[0], row(0..8));
+    }
+
+    #[test]
+    fn drw_wraps_around_the_edges() {
+        // Off the bottom-right corner: the right half of each row lands on the left, and the second row on the top
+        let mut chip8 = Chip8::new();
+        assert_eq!(drw(&mut chip8, 60, 31, &[0xFF, 0x81]), 0);
+        assert_eq!(chip8.display[31], row([60, 61, 62, 63, 0, 1, 2, 3]));
+        assert_eq!(chip8.display[0], row([60, 3]));
+        assert!(chip8.display[1..31].iter().all(|&row| row == 0));
+
+        // Coordinates past the edge start over from the other side
+        let mut chip8 = Chip8::new();
+        drw(&mut chip8, 64 + 10, 32 + 2, &[0x80]);
+        assert_eq!(chip8.display[2], row([10]));
+    }
+
+    #[test]
+    fn drw_rotates_sprites_past_the_right_edge() {
+        for x in 57..64 {
+            let mut chip8 = Chip8::new();
+            drw(&mut chip8, x, 0, &[0xC3]); // Columns 0, 1, 6 and 7 of the sprite
+            let expected = row([0, 1, 6, 7].map(|column| (x as usize + column) % 64));
+            assert_eq!(chip8.display[0], expected, "sprite at x = {}", x);
+        }
+    }
+
+    // The ETI-660's 64x48 display wraps sprites at row 48 instead of 32, and its lower rows survive a save state
+    #[test]
+    fn eti660_display_wraps_at_48_rows() {
+        #[rustfmt::skip]
+        let rom = [
+            0xA2, 0x08, // 200: LD I, 208
+            0x60, 0x00, // 202: LD V0, 00
+            0x61, 0x2E, // 204: LD V1, 2E (row 46)
+            0xD0, 0x14, // 206: DRW V0, V1, 4
+            0xF0, 0xF0, 0xF0, 0xF0,
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.display_height = QuirkProfile::Eti660.display_height();
+        chip8.load_bytes(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.emulate_cycle();
+        }
+        let lit: Vec<usize> = (0..MAX_DISPLAY_HEIGHT).filter(|&y| chip8.display[y] != 0).collect();
+        assert_eq!(lit, [0, 1, 46, 47]);
+        assert_eq!(chip8.display_rows().len(), 48);
+
+        let (state, _) = MachineState::from_bytes(&chip8.snapshot().to_bytes("eti660")).unwrap();
+        assert_eq!(state.display, chip8.display);
+    }
+
+    // A block that sets I and then writes over code that's already cached has to throw that code away
+    #[cfg(feature = "jit")]
+    #[test]
+    fn jit_sees_writes_through_an_i_set_in_the_same_block() {
+        #[rustfmt::skip]
+        let rom = [
+            0x22, 0x10, // 200: CALL 210 (caches the subroutine)
+            0xA2, 0x10, // 202: LD I, 210
+            0x60, 0x12, // 204: LD V0, 12
+            0x61, 0x16, // 206: LD V1, 16
+            0xF1, 0x55, // 208: LD [I], V1 (turns 210 into JP 216)
+            0x22, 0x10, // 20A: CALL 210
+            0x12, 0x0C, // 20C: JP 20C
+            0x00, 0x00,
+            0x63, 0x05, // 210: LD V3, 05
+            0x00, 0xEE, // 212: RET
+            0x00, 0x00,
+            0x63, 0x77, // 216: LD V3, 77
+            0x12, 0x18, // 218: JP 218
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(&rom).unwrap();
+        Jit::new().run(&mut chip8, 100);
+        assert_eq!(chip8.v(3), 0x77);
     }
 }
@@ -0,0 +1,230 @@
+// Experimental block translator (enabled with `cargo run --features jit`)
+//
+// Despite the feature's name this doesn't generate host machine code: it's a cache of
+// pre-decoded handlers. Instead of fetching and decoding every instruction on every cycle,
+// straight-line runs of instructions ("blocks") are decoded once into a list of handler
+// functions and cached by their start address. A block ends at anything that can change the
+// flow of execution (jumps, calls, returns, skips, FX0A) or that writes to memory (FX33, FX55),
+// so that self-modifying ROMs can be handled by throwing away any cached block that overlaps
+// a write.
+use super::Chip8;
+use std::collections::HashMap;
+
+// Longest block we will translate in one go
+const MAX_BLOCK_LEN: usize = 64;
+
+// A translated instruction; the handler pulls its operands back out of the opcode
+type Handler = fn(&mut Chip8, u16);
+
+struct Block {
+    ops: Vec<(Handler, u16)>, // Pre-decoded handlers paired with their opcodes
+    end: u16,                 // Address just past the last instruction of the block
+}
+
+pub struct Jit {
+    blocks: HashMap<u16, Block>, // Translated blocks keyed by start address
+    coverage: Vec<u8>,           // Number of cached blocks covering each byte of memory
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Jit::new()
+    }
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Jit {
+            blocks: HashMap::new(),
+            coverage: vec![0; 4096],
+        }
+    }
+
+    // Run up to `budget` instructions, returning how many were actually executed
+    pub fn run(&mut self, chip8: &mut Chip8, budget: usize) -> usize {
+        let mut executed = 0;
+
+        while executed < budget {
+            let start = chip8.pc;
+            if start > 0xFFE {
+                // Execution ran off the end of memory; the interpreter reports that and wraps around
+                chip8.emulate_cycle();
+                executed += 1;
+                continue;
+            }
+            if !self.blocks.contains_key(&start) {
+                self.translate(chip8, start);
+            }
+
+            let block = &self.blocks[&start];
+            if block.ops.is_empty() {
+                chip8.emulate_cycle();
+                executed += 1;
+                continue;
+            }
+
+            let count = block.ops.len().min(budget - executed);
+            let mut written = None;
+            for (index, &(handler, opcode)) in block.ops[..count].iter().enumerate() {
+                // Memory writes are always the last instruction of a block; the range they write is worked out
+                // just before they run, since earlier instructions in the block may have changed I
+                if index + 1 == count {
+                    written = written_range(chip8, opcode);
+                }
+                if let Some(heat_map) = &mut chip8.heat_map {
+                    heat_map.execute(chip8.pc as usize);
+                    heat_map.execute(chip8.pc as usize + 1);
+                }
+                if let Some(coverage) = &mut chip8.coverage {
+                    coverage.record(opcode);
+                }
+                chip8.pc += 2;
+                handler(chip8, opcode);
+            }
+            executed += count;
+
+            if let Some((from, to)) = written {
+                // Writes past the end of memory wrap around to the start (see Chip8::check_bounds)
+                self.invalidate(from, to.min(self.coverage.len()));
+                if to > self.coverage.len() {
+                    self.invalidate(0, to - self.coverage.len());
+                }
+            }
+        }
+
+        executed
+    }
+
+    fn translate(&mut self, chip8: &Chip8, start: u16) {
+        let mut ops = Vec::new();
+        let mut addr = start;
+
+        // Stop before running off the end of memory; the interpreter would panic there anyway
+        while ops.len() < MAX_BLOCK_LEN && (addr as usize) + 1 < chip8.memory.len() {
+            let opcode = (chip8.memory[addr as usize] as u16) << 8 | chip8.memory[addr as usize + 1] as u16;
+            let Some((handler, ends_block)) = decode(opcode) else {
+                break; // Leave anything we don't recognise to the interpreter
+            };
+            ops.push((handler, opcode));
+            addr += 2;
+
+            if ends_block {
+                break;
+            }
+        }
+
+        for byte in &mut self.coverage[start as usize..addr as usize] {
+            *byte += 1;
+        }
+        self.blocks.insert(start, Block { ops, end: addr });
+    }
+
+    // Throw away any block covering a byte written from outside the CPU (e.g. fuzz.rs's bit flips)
+    pub fn poked(&mut self, addr: u16) {
+        self.invalidate(addr as usize, addr as usize + 1);
+    }
+
+    fn invalidate(&mut self, from: usize, to: usize) {
+        // Most writes land in data rather than code, so avoid scanning the cache if we can
+        let to = to.min(self.coverage.len());
+        if self.coverage[from.min(to)..to].iter().all(|&count| count == 0) {
+            return;
+        }
+
+        let coverage = &mut self.coverage;
+        self.blocks.retain(|&start, block| {
+            let overlaps = (start as usize) < to && from < block.end as usize;
+            if overlaps {
+                for byte in &mut coverage[start as usize..block.end as usize] {
+                    *byte -= 1;
+                }
+            }
+            !overlaps
+        });
+    }
+}
+
+// Memory range [from, to) written by an instruction, if it writes memory at all
+fn written_range(chip8: &Chip8, opcode: u16) -> Option<(usize, usize)> {
+    let i = chip8.i as usize;
+    match opcode & 0xF0FF {
+        0xF033 => Some((i, i + 3)),
+        0xF055 => Some((i, i + ((opcode & 0x0F00) >> 8) as usize + 1)),
+        _ => None,
+    }
+}
+
+fn x(opcode: u16) -> usize {
+    ((opcode & 0x0F00) >> 8) as usize
+}
+
+fn y(opcode: u16) -> usize {
+    ((opcode & 0x00F0) >> 4) as usize
+}
+
+fn kk(opcode: u16) -> u8 {
+    (opcode & 0x00FF) as u8
+}
+
+// Mirrors the dispatch in `Chip8::execute_instruction`; returns the handler and whether it ends a block
+fn decode(opcode: u16) -> Option<(Handler, bool)> {
+    let decoded: (Handler, bool) = match opcode & 0xF000 {
+        0x0000 => match opcode & 0x00FF {
+            0x00E0 => (|c, _| c.cls(), false),
+            0x00EE => (|c, _| c.ret(), true),
+            _ => return None,
+        },
+        0x1000 => (|c, op| c.jp(op), true),
+        0x2000 => (|c, op| c.call(op), true),
+        0x3000 => (|c, op| c.se_vx(x(op), kk(op)), true),
+        0x4000 => (|c, op| c.sne_vx(x(op), kk(op)), true),
+        0x5000 => (|c, op| c.se_vx_vy(x(op), y(op)), true),
+        0x6000 => (|c, op| c.ld_vx(x(op), kk(op)), false),
+        0x7000 => (|c, op| c.add_vx(x(op), kk(op)), false),
+        0x8000 => match opcode & 0x000F {
+            0x0000 => (|c, op| c.ld_vx_vy(x(op), y(op)), false),
+            0x0001 => (|c, op| c.or_vx_vy(x(op), y(op)), false),
+            0x0002 => (|c, op| c.and_vx_vy(x(op), y(op)), false),
+            0x0003 => (|c, op| c.xor_vx_vy(x(op), y(op)), false),
+            0x0004 => (|c, op| c.add_vx_vy(x(op), y(op)), false),
+            0x0005 => (|c, op| c.sub_vx_vy(x(op), y(op)), false),
+            0x0006 => (|c, op| c.shr_vx(x(op)), false),
+            0x0007 => (|c, op| c.subn_vx_vy(x(op), y(op)), false),
+            0x000E => (|c, op| c.shl_vx(x(op)), false),
+            _ => return None,
+        },
+        0x9000 => (|c, op| c.sne_vx_vy(x(op), y(op)), true),
+        0xA000 => (|c, op| c.ld_i(op & 0x0FFF), false),
+        0xB000 => (
+            |c, op| {
+                if c.quirks.jump_vx {
+                    c.jp_vx(x(op), op & 0x0FFF)
+                } else {
+                    c.jp_v0(op & 0x0FFF)
+                }
+            },
+            true,
+        ),
+        0xC000 => (|c, op| c.rnd(x(op), kk(op)), false),
+        0xD000 => (|c, op| c.drw(x(op), y(op), (op & 0x000F) as u8), false),
+        0xE000 => match opcode & 0x00FF {
+            0x009E => (|c, op| c.skp(x(op)), true),
+            0x00A1 => (|c, op| c.sknp(x(op)), true),
+            _ => return None,
+        },
+        0xF000 => match opcode & 0x00FF {
+            0x0007 => (|c, op| c.ld_vx_dt(x(op)), false),
+            0x000A => (|c, op| c.ld_vx_k(x(op)), true),
+            0x0015 => (|c, op| c.ld_dt_vx(x(op)), false),
+            0x0018 => (|c, op| c.ld_st_vx(x(op)), false),
+            0x001E => (|c, op| c.add_i_vx(x(op)), false),
+            0x0029 => (|c, op| c.ld_f_vx(x(op)), false),
+            0x0033 => (|c, op| c.ld_b_vx(x(op)), true),
+            0x0055 => (|c, op| c.ld_i_vx(x(op)), true),
+            0x0065 => (|c, op| c.ld_vx_i(x(op)), false),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(decoded)
+}
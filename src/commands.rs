@@ -0,0 +1,342 @@
+// Subcommands: tools that work on ROMs without opening a window, run as `cargo run -- <command> ...`. Playing a ROM
+// is the `run` command, which is also what happens when the first argument isn't a command (see main.rs).
+//
+//   disassemble <rom>                 list the ROM as assembly, one instruction per line with its address and opcode
+//                                     in a comment; `assemble` turns the listing back into the same ROM
+//   assemble <in.asm> <out.ch8>       the other way: one instruction per line as disasm.rs writes them, `DB` for
+//                                     single bytes (`DB 0xF0, 0x90`), `name:` labels on lines of their own to use
+//                                     as addresses, and comments from ; to the end of the line
+//   info <rom>                        size, checksums, the ROM's settings in the config file and the quirk profile it
+//                                     seems to need (see auto_quirks.rs)
+//   scan <directory>                  a line of that for every ROM in a directory and the ones inside it
+//   bench <rom> [seconds]             how many instructions per second the ROM can be emulated at, flat out
+//   render-replay <in.replay> <out.gif> [rom]   see render.rs
+//
+// disassemble, assemble, info and bench take --load-addr <hex> (where the ROM goes; 0x200 by default), and the
+// ones that read ROMs take --format binary|hex as well. info and scan take --config <path>.
+use crate::auto_quirks;
+use crate::checksum::Checksums;
+use crate::config::{self, Config};
+use crate::render;
+use crate::rom::{self, RomFormat};
+use chip8::disasm::{assemble, disassemble};
+use chip8::quirks::QuirkProfile;
+use chip8::{Chip8, PROGRAM_START};
+#[cfg(feature = "jit")]
+use chip8::Jit;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const COMMANDS: [&str; 6] = ["disassemble", "assemble", "info", "scan", "bench", "render-replay"];
+
+const BENCH_SECONDS: u64 = 5; // How long bench runs for, unless told otherwise
+const ROM_EXTENSIONS: [&str; 6] = ["ch8", "c8", "sc8", "xo8", "rom", "hex"]; // Files scan looks at
+
+// What's left of the command line once the options are taken out
+struct Arguments {
+    positional: Vec<String>,
+    load_address: u16,
+    format: Option<RomFormat>,
+    config_path: Option<String>,
+}
+
+impl Arguments {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut arguments =
+            Arguments { positional: Vec::new(), load_address: PROGRAM_START, format: None, config_path: None };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--load-addr" => {
+                    let text = value()?;
+                    arguments.load_address = u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                        .ok()
+                        .filter(|&address| address < 0x1000)
+                        .ok_or_else(|| format!("Invalid address '{}' for --load-addr; expected 0x000-0xFFF", text))?;
+                }
+                "--format" => {
+                    let name = value()?;
+                    let format = RomFormat::from_name(name)
+                        .ok_or_else(|| format!("Unknown ROM format '{}'; expected binary or hex", name))?;
+                    arguments.format = Some(format);
+                }
+                "--config" => arguments.config_path = Some(value()?.clone()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => arguments.positional.push(arg.clone()),
+            }
+        }
+        Ok(arguments)
+    }
+
+    // The positional arguments, checking there are between `min` and `max` of them
+    fn expect(&self, min: usize, max: usize, usage: &str) -> Result<&[String], String> {
+        if (min..=max).contains(&self.positional.len()) {
+            Ok(&self.positional)
+        } else {
+            Err(format!("Usage: cargo run -- {}", usage))
+        }
+    }
+
+    // The config file given with --config, or the default one if there is one
+    fn config(&self) -> Result<Config, String> {
+        let path = self.config_path.as_deref().unwrap_or(config::DEFAULT_CONFIG_PATH);
+        if Path::new(path).exists() || self.config_path.is_some() {
+            Config::load(Path::new(path))
+        } else {
+            Ok(Config::new())
+        }
+    }
+
+    // Read a ROM, checking it fits in memory at the load address
+    fn rom(&self, path: &str) -> Result<Vec<u8>, String> {
+        let rom = rom::read(path, self.format)?;
+        if rom.len() > 4096 - self.load_address as usize {
+            return Err(format!("{} is too big to load at {:03X} ({} bytes)", path, self.load_address, rom.len()));
+        }
+        Ok(rom)
+    }
+}
+
+// Run a command (one of COMMANDS); cpu_hz is the speed ROMs run at unless the config file says otherwise
+pub fn run(command: &str, args: &[String], cpu_hz: u32) -> Result<(), String> {
+    let arguments = Arguments::parse(args)?;
+    match command {
+        "disassemble" => {
+            let paths = arguments.expect(1, 1, "disassemble <rom> [--load-addr <hex>] [--format binary|hex]")?;
+            print!("{}", listing(&arguments.rom(&paths[0])?, arguments.load_address));
+            Ok(())
+        }
+        "assemble" => {
+            let paths = arguments.expect(2, 2, "assemble <in.asm> <out.ch8> [--load-addr <hex>]")?;
+            let source = std::fs::read_to_string(&paths[0]).map_err(|e| format!("Error reading {}: {}", paths[0], e))?;
+            let rom = assemble_program(&source, arguments.load_address)?;
+            std::fs::write(&paths[1], &rom).map_err(|e| format!("Error writing {}: {}", paths[1], e))?;
+            println!("Assembled {} bytes into {}", rom.len(), paths[1]);
+            Ok(())
+        }
+        "info" => {
+            let usage = "info <rom> [--load-addr <hex>] [--format binary|hex] [--config <path>]";
+            let paths = arguments.expect(1, 1, usage)?;
+            info(&paths[0], &arguments, cpu_hz)
+        }
+        "scan" => {
+            let paths = arguments.expect(1, 1, "scan <directory> [--config <path>]")?;
+            scan(Path::new(&paths[0]), &arguments, cpu_hz)
+        }
+        "bench" => {
+            let usage = "bench <rom> [seconds] [--load-addr <hex>] [--format binary|hex]";
+            let paths = arguments.expect(1, 2, usage)?;
+            let seconds = match paths.get(1) {
+                Some(text) => text.parse().map_err(|_| format!("Expected a number of seconds, found '{}'", text))?,
+                None => BENCH_SECONDS,
+            };
+            bench(&arguments.rom(&paths[0])?, arguments.load_address, Duration::from_secs(seconds), cpu_hz);
+            Ok(())
+        }
+        "render-replay" => {
+            let paths = arguments.expect(2, 3, "render-replay <in.replay> <out.gif> [rom]")?;
+            render::render_replay(&paths[0], &paths[1], paths.get(2).map(String::as_str))?;
+            println!("Rendered {} to {}", paths[0], paths[1]);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command '{}'", command)),
+    }
+}
+
+// The ROM as assembly, e.g. `LD VA, 0x02            ; 200: 6A02`. Opcodes the assembler wouldn't give back
+// exactly (e.g. 5XY1, which runs as 5XY0) are written as data words so the listing reassembles to the same bytes.
+fn listing(rom: &[u8], load_address: u16) -> String {
+    let mut listing = String::new();
+    for (offset, bytes) in rom.chunks(2).enumerate() {
+        let address = load_address as usize + offset * 2;
+        let (text, code) = match *bytes {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([high, low]);
+                let text = disassemble(opcode);
+                let text = if assemble(&text) == Ok(opcode) { text } else { format!("DW 0x{:04X}", opcode) };
+                (text, format!("{:04X}", opcode))
+            }
+            [byte] => (format!("DB 0x{:02X}", byte), format!("{:02X}", byte)),
+            _ => unreachable!(),
+        };
+        listing += &format!("{:<24}; {:03X}: {}\n", text, address, code);
+    }
+    listing
+}
+
+// Assemble a whole program; labels are worked out in a first pass, since they can be used before they are defined
+fn assemble_program(source: &str, load_address: u16) -> Result<Vec<u8>, String> {
+    let lines: Vec<(usize, &str)> = source
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.split(';').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+
+    let mut labels = Vec::new();
+    let mut address = load_address as usize;
+    for &(_, line) in &lines {
+        match line.strip_suffix(':') {
+            Some(label) => labels.push((label.trim(), address)),
+            None => address += data_bytes(line).map_or(2, |bytes| bytes.split(',').count()),
+        }
+    }
+
+    let mut rom = Vec::new();
+    for (number, line) in lines {
+        if line.ends_with(':') {
+            continue;
+        }
+        let error = |e: String| format!("Line {}: {}", number, e);
+        // Labels stand in for the addresses they are at, wherever an operand is a label
+        let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands: Vec<String> = operands
+            .split(',')
+            .map(str::trim)
+            .map(|operand| match labels.iter().find(|(label, _)| *label == operand) {
+                Some((_, address)) => format!("0x{:03X}", address),
+                None => operand.to_string(),
+            })
+            .collect();
+        let line = format!("{} {}", mnemonic, operands.join(", "));
+
+        match data_bytes(&line) {
+            Some(bytes) => {
+                // The bytes are read as data words, so numbers are written the same way as everywhere else
+                for byte in bytes.split(',') {
+                    match assemble(&format!("DW {}", byte)).map_err(error)? {
+                        byte @ 0..=0xFF => rom.push(byte as u8),
+                        _ => return Err(error(format!("{} doesn't fit in a byte", byte.trim()))),
+                    }
+                }
+            }
+            None => rom.extend_from_slice(&assemble(&line).map_err(error)?.to_be_bytes()),
+        }
+    }
+    if rom.len() > 4096 - load_address as usize {
+        return Err(format!("The program is too big to load at {:03X} ({} bytes)", load_address, rom.len()));
+    }
+    Ok(rom)
+}
+
+// The operands of a DB line, if it is one
+fn data_bytes(line: &str) -> Option<&str> {
+    let (mnemonic, operands) = line.split_once(char::is_whitespace)?;
+    mnemonic.eq_ignore_ascii_case("DB").then_some(operands)
+}
+
+fn info(path: &str, arguments: &Arguments, cpu_hz: u32) -> Result<(), String> {
+    let rom = arguments.rom(path)?;
+    let config = arguments.config()?;
+    let checksums = Checksums::of(&rom);
+    let section = config::rom_section(path);
+    let end = arguments.load_address as usize + rom.len().max(1) - 1;
+
+    println!("{}", path);
+    println!("  Size:     {} bytes ({:03X}-{:03X})", rom.len(), arguments.load_address, end);
+    println!("  SHA-1:    {}", checksums.sha1);
+    println!("  CRC32:    {}", checksums.crc32);
+    let settings = config.section(&section).iter().map(|(key, value)| format!("{} = {}", key, value));
+    let settings: Vec<String> = settings.collect();
+    if settings.is_empty() {
+        println!("  Settings: none in [{}]", section);
+    } else {
+        println!("  Settings: {} (in [{}])", settings.join(", "), section);
+    }
+
+    let cpu_hz = config.get_or(&section, "cpu_hz", cpu_hz);
+    let layout = (arguments.load_address, arguments.load_address);
+    let profile = auto_quirks::detect(&rom, cpu_hz, layout, QuirkProfile::Modern);
+    println!("Suggested quirk profile: {} (--quirks {})", profile.name(), profile.name());
+    Ok(())
+}
+
+fn scan(directory: &Path, arguments: &Arguments, cpu_hz: u32) -> Result<(), String> {
+    let config = arguments.config()?;
+    let mut paths = Vec::new();
+    find_roms(directory, &mut paths)?;
+    paths.sort();
+    if paths.is_empty() {
+        println!("No ROMs found in {} (looking for .{})", directory.display(), ROM_EXTENSIONS.join(", ."));
+        return Ok(());
+    }
+
+    println!("{:<40} {:>6}  {:<8}  Quirks", "ROM", "Bytes", "CRC32");
+    for path in &paths {
+        let path = path.to_string_lossy();
+        let rom = match arguments.rom(&path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let cpu_hz = config.get_or(&config::rom_section(&path), "cpu_hz", cpu_hz);
+        let layout = (arguments.load_address, arguments.load_address);
+        let profile = auto_quirks::guess(&rom, cpu_hz, layout, QuirkProfile::Modern);
+        println!("{:<40} {:>6}  {}  {}", path, rom.len(), Checksums::of(&rom).crc32, profile.name());
+    }
+    println!("{} ROMs", paths.len());
+    Ok(())
+}
+
+// Files with one of the ROM_EXTENSIONS in a directory and the ones inside it
+fn find_roms(directory: &Path, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(directory).map_err(|e| format!("Error reading {}: {}", directory.display(), e))?;
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase);
+        if path.is_dir() {
+            find_roms(&path, paths)?;
+        } else if extension.is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.as_str())) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Emulate the ROM as fast as possible for a while, with no keys pressed, ticking the timers every 1/60 of a second
+// of emulated time at cpu_hz
+fn bench(rom: &[u8], load_address: u16, duration: Duration, cpu_hz: u32) {
+    // The ROM has already been checked to fit (see Arguments::rom)
+    let start = || {
+        let mut chip8 = Chip8::new();
+        chip8.load_address = load_address;
+        chip8.entry_point = load_address;
+        chip8.reset();
+        chip8.seed_rng(0);
+        chip8.load_bytes(rom).expect("the ROM fits");
+        chip8
+    };
+    let frame = (cpu_hz as usize / 60).max(1);
+    let report = |name: &str, instructions: usize, elapsed: Duration| {
+        let per_second = instructions as f64 / elapsed.as_secs_f64();
+        let speed = per_second / cpu_hz as f64;
+        let speed = format!("{:.0} times as fast as {} a second", speed, cpu_hz);
+        println!("{:<11} {:>12.0} instructions per second, {}", name, per_second, speed);
+    };
+
+    let mut chip8 = start();
+    let (began, mut instructions) = (Instant::now(), 0);
+    while began.elapsed() < duration {
+        for _ in 0..frame {
+            chip8.emulate_cycle();
+        }
+        chip8.update_timers();
+        chip8.take_faults();
+        instructions += frame;
+    }
+    report("Interpreter", instructions, began.elapsed());
+
+    #[cfg(feature = "jit")]
+    {
+        let (mut chip8, mut jit) = (start(), Jit::new());
+        let (began, mut instructions) = (Instant::now(), 0);
+        while began.elapsed() < duration {
+            instructions += jit.run(&mut chip8, frame);
+            chip8.update_timers();
+            chip8.take_faults();
+        }
+        report("Translator", instructions, began.elapsed());
+    }
+}
@@ -0,0 +1,185 @@
+// Reader for the emulator's config file, which uses a small subset of TOML:
+//
+//   # Comments start with a hash
+//   [section]
+//   key = "string value"
+//   other_key = 42
+//
+// Keys that appear before any [section] header end up in the "" section.
+use std::{fs, path::Path, str::FromStr};
+
+// Default location of the config file, relative to the working directory
+pub const DEFAULT_CONFIG_PATH: &str = "chip8.toml";
+
+pub struct Config {
+    sections: Vec<(String, Vec<(String, String)>)>, // Kept in file order so error messages and listings read naturally
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config { sections: Vec::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Error reading config file {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("Error in config file {}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::new();
+        let mut section = String::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`, found `{}`", number + 1, line));
+            };
+            let key = unquote(key.trim());
+            let value = unquote(value.trim());
+            config.set(&section, &key, &value);
+        }
+
+        Ok(config)
+    }
+
+    // Look up a single value
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // Look up a value and parse it, falling back to `default` if it is missing or can't be parsed
+    pub fn get_or<T: FromStr>(&self, section: &str, key: &str, default: T) -> T {
+        match self.get(section, key).map(str::parse) {
+            Some(Ok(value)) => value,
+            Some(Err(_)) => {
+                eprintln!("Ignoring invalid value for {} in [{}] section of config file", key, section);
+                default
+            }
+            None => default,
+        }
+    }
+
+    // All key/value pairs of a section, in file order (empty if the section doesn't exist)
+    pub fn section(&self, section: &str) -> &[(String, String)] {
+        self.sections
+            .iter()
+            .find(|(name, _)| name == section)
+            .map(|(_, entries)| entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Names of all sections, in file order
+    pub fn section_names(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let entries = match self.sections.iter().position(|(name, _)| name == section) {
+            Some(index) => &mut self.sections[index].1,
+            None => {
+                self.sections.push((section.to_string(), Vec::new()));
+                &mut self.sections.last_mut().unwrap().1
+            }
+        };
+
+        match entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => entries.push((key.to_string(), value.to_string())),
+        }
+    }
+}
+
+// Settings for a single ROM go in a section named after its file, e.g. [rom.pong] for roms/pong.ch8
+pub fn rom_section(rom_path: &str) -> String {
+    let name = Path::new(rom_path).file_stem().map_or("untitled".into(), |stem| stem.to_string_lossy());
+    format!("rom.{}", name)
+}
+
+// Change a single value in a config file, keeping everything else (including comments) as it is;
+// the file, section and key are created as needed
+pub fn save_value(path: &Path, section: &str, key: &str, value: &str) -> Result<(), String> {
+    let text = if path.exists() {
+        fs::read_to_string(path).map_err(|e| format!("Error reading config file {}: {}", path.display(), e))?
+    } else {
+        String::new()
+    };
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let new_line = format!("{} = {}", key, value);
+
+    // Find the section's lines (keys before any header are in the "" section)
+    let mut current = String::new();
+    let mut section_end = None; // Index just past the last line of the section
+    let mut existing = None;    // Index of the key's line
+    for (index, line) in lines.iter().enumerate() {
+        let line = strip_comment(line).trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            current = name.trim().to_string();
+            if current == section {
+                section_end = Some(index + 1);
+            }
+            continue;
+        }
+        if current == section {
+            if !line.is_empty() {
+                section_end = Some(index + 1);
+            }
+            if line.split_once('=').is_some_and(|(k, _)| unquote(k.trim()) == key) {
+                existing = Some(index);
+            }
+        }
+    }
+
+    match (existing, section_end) {
+        (Some(index), _) => {
+            // Keep any comment at the end of the line
+            let comment = lines[index][strip_comment(&lines[index]).len()..].trim().to_string();
+            lines[index] = if comment.is_empty() { new_line } else { format!("{} {}", new_line, comment) };
+        }
+        (None, Some(end)) => lines.insert(end, new_line),
+        (None, None) if section.is_empty() => lines.insert(0, new_line),
+        (None, None) => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.push(new_line);
+        }
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(path, text).map_err(|e| format!("Error writing config file {}: {}", path.display(), e))
+}
+
+// Remove a trailing `# comment`, ignoring hashes inside quoted strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
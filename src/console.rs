@@ -0,0 +1,103 @@
+// What the emulator tells the shell: exit codes, so scripts and CI jobs can tell how a run ended, and --quiet, which
+// leaves out everything printed to the terminal but errors and warnings (and what was asked for, like reports).
+//
+//   0  quit normally, or ran the frames given with --frames without a fault
+//   1  bad arguments or config file, or something else went wrong
+//   2  the ROM couldn't be read, doesn't fit in memory or doesn't match --verify
+//   3  with --frames or --strict, the game hit a fault (stack trouble, out of bounds memory access...)
+//   4  with --frames or --strict, the game ran an unknown opcode (which takes precedence over other faults)
+//
+// --strict stops at the first fault, rather than reporting it and carrying on as the game would on most
+// interpreters, for ROM developers and test suites.
+//
+// Faults are reported like a compiler error, with the instructions around the one at fault, in color when the output
+// is a terminal (unless NO_COLOR is set):
+//
+//   error: Unknown opcode: FFFF at 204
+//     --> 204, frame 42
+//       |
+//   200 | 6A02  LD VA, 0x02
+//   202 | A22A  LD I, 0x22A
+//   204 | FFFF  DW 0xFFFF
+//       |       ^^^^^^^^^ the instruction was skipped
+//   206 | DAB6  DRW VA, VB, 6
+//   208 | 1200  JP 0x200
+//       = PC=206 I=22A SP=0 DT=00 ST=00 V0-VF=00 00 ...
+//
+// The registers are as of the end of the frame the fault happened in.
+use chip8::disasm::disassemble;
+use chip8::{Chip8, Fault};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_ROM_LOAD: i32 = 2;
+pub const EXIT_FAULT: i32 = 3;
+pub const EXIT_UNKNOWN_OPCODE: i32 = 4;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// The registers in a line, for diagnostics
+pub fn state(chip8: &Chip8) -> String {
+    let v: Vec<String> = (0..16).map(|x| format!("{:02X}", chip8.v(x))).collect();
+    format!(
+        "  PC={:03X} I={:03X} SP={} DT={:02X} ST={:02X} V0-VF={}",
+        chip8.pc(),
+        chip8.i(),
+        chip8.sp(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        v.join(" ")
+    )
+}
+
+// A fault as a multi-line report, ending in a newline
+pub fn diagnostic(fault: &Fault, chip8: &Chip8, frame: u64) -> String {
+    let (addr, outcome) = match *fault {
+        Fault::UnknownOpcode { addr, .. } => (addr, "the instruction was skipped"),
+        Fault::StackOverflow { addr } => (addr, "the call was skipped"),
+        Fault::StackUnderflow { addr } => (addr, "the return was skipped"),
+        Fault::MemoryOutOfBounds { addr, .. } => (addr, "the access wrapped around to the start of memory"),
+        Fault::PcOutOfBounds { pc } => (pc.min(0xFFE), "execution wrapped around to the start of memory"),
+        Fault::ReservedWrite { addr, .. } => (addr, "the write went ahead"),
+    };
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let paint = |text: &str, style: &str| if color { format!("\x1b[{}m{}\x1b[0m", style, text) } else { text.into() };
+    let (red, blue, bold) = ("1;31", "1;34", "1");
+
+    let mut report = format!("{}{}\n", paint("error", red), paint(&format!(": {}", fault), bold));
+    report += &format!("  {} {:03X}, frame {}\n", paint("-->", blue), addr, frame);
+    report += &format!("{}\n", paint("    |", blue));
+    // Two instructions either side, keeping to the same alignment as the one at fault
+    let first = addr.saturating_sub(4) & !1 | addr & 1;
+    for line_addr in (first..=addr.saturating_add(4).min(0xFFE)).step_by(2) {
+        let opcode = u16::from_be_bytes([chip8.peek(line_addr), chip8.peek(line_addr + 1)]);
+        let text = format!("{:04X}  {}", opcode, disassemble(opcode));
+        let text = if line_addr == addr { paint(&text, bold) } else { text };
+        report += &format!("{} {}\n", paint(&format!("{:03X} |", line_addr), blue), text);
+        if line_addr == addr {
+            let marker = "^".repeat(disassemble(opcode).len());
+            report += &format!("{}       {} {}\n", paint("    |", blue), paint(&marker, red), paint(outcome, red));
+        }
+    }
+    report += &format!("{} {}\n", paint("    =", blue), state(chip8).trim_start());
+    report
+}
+
+// println! for progress and status messages, which --quiet leaves out
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::console::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
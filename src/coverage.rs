@@ -0,0 +1,136 @@
+// Opcode coverage (enabled with --coverage, or the debugger's `coverage` command): counts how many times each of
+// the instructions has run, to see which parts of the instruction set a ROM actually uses. An instruction the
+// quirks change the behavior of is only worth worrying about if the ROM runs it, so the report also lists the
+// quirks the ROM can be affected by.
+use alloc::{format, string::String, vec::Vec};
+
+// Each instruction: its pattern (as written in Cowgod's reference), a short description and the quirk that
+// changes how it behaves, if any (by its --quirk name)
+pub const OPCODES: [(&str, &str, Option<&str>); 34] = [
+    ("00E0", "clear the screen", None),
+    ("00EE", "return from subroutine", None),
+    ("1NNN", "jump", None),
+    ("2NNN", "call subroutine", None),
+    ("3XNN", "skip if VX == NN", None),
+    ("4XNN", "skip if VX != NN", None),
+    ("5XY0", "skip if VX == VY", None),
+    ("6XNN", "VX = NN", None),
+    ("7XNN", "VX += NN", None),
+    ("8XY0", "VX = VY", None),
+    ("8XY1", "VX |= VY", Some("vf-reset")),
+    ("8XY2", "VX &= VY", Some("vf-reset")),
+    ("8XY3", "VX ^= VY", Some("vf-reset")),
+    ("8XY4", "VX += VY with carry", Some("vf-result-last")),
+    ("8XY5", "VX -= VY with borrow", Some("vf-result-last")),
+    ("8XY6", "shift VX right", Some("vf-result-last")),
+    ("8XY7", "VX = VY - VX with borrow", Some("vf-result-last")),
+    ("8XYE", "shift VX left", Some("vf-result-last")),
+    ("9XY0", "skip if VX != VY", None),
+    ("ANNN", "I = NNN", None),
+    ("BNNN", "jump to NNN + V0", Some("jump-vx")),
+    ("CXNN", "VX = random & NN", None),
+    ("DXYN", "draw sprite", None),
+    ("EX9E", "skip if key VX is held", None),
+    ("EXA1", "skip if key VX isn't held", None),
+    ("FX07", "VX = delay timer", None),
+    ("FX0A", "wait for a key", None),
+    ("FX15", "delay timer = VX", None),
+    ("FX18", "sound timer = VX", None),
+    ("FX1E", "I += VX", Some("i-overflow-flag")),
+    ("FX29", "I = font character VX", None),
+    ("FX33", "store VX as BCD", None),
+    ("FX55", "store V0-VX", Some("memory-increment")),
+    ("FX65", "load V0-VX", Some("memory-increment")),
+];
+
+pub struct Coverage {
+    counts: [u64; OPCODES.len()], // Times each of OPCODES has run
+    unknown: u64,                 // Times an opcode that isn't an instruction was run
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { counts: [0; OPCODES.len()], unknown: 0 }
+    }
+
+    pub fn record(&mut self, opcode: u16) {
+        match instruction(opcode) {
+            Some(index) => self.counts[index] += 1,
+            None => self.unknown += 1,
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let used = self.counts.iter().filter(|&&count| count > 0).count();
+        let mut report = format!("Opcode coverage: {} of {} instructions used\n", used, OPCODES.len());
+        for (&(pattern, description, _), &count) in OPCODES.iter().zip(&self.counts) {
+            let count = if count > 0 { format!("{:>10}", count) } else { format!("{:>10}", "-") };
+            report += &format!("  {} {}  {}\n", pattern, count, description);
+        }
+        if self.unknown > 0 {
+            report += &format!("  Unknown opcodes run {} times\n", self.unknown);
+        }
+
+        let mut quirks: Vec<&str> = OPCODES
+            .iter()
+            .zip(&self.counts)
+            .filter(|&(_, &count)| count > 0)
+            .filter_map(|(&(.., quirk), _)| quirk)
+            .collect();
+        quirks.dedup();
+        if quirks.is_empty() {
+            report += "No instructions that quirks affect were used\n";
+        } else {
+            report += &format!("Quirks that can affect this ROM: {}\n", quirks.join(", "));
+        }
+        report
+    }
+}
+
+// Index into OPCODES of the instruction an opcode is, decoded the same way Chip8::execute_instruction does
+fn instruction(opcode: u16) -> Option<usize> {
+    let pattern = match (opcode & 0xF000, opcode & 0x000F, opcode & 0x00FF) {
+        (0x0000, _, 0xE0) => "00E0",
+        (0x0000, _, 0xEE) => "00EE",
+        (0x1000, ..) => "1NNN",
+        (0x2000, ..) => "2NNN",
+        (0x3000, ..) => "3XNN",
+        (0x4000, ..) => "4XNN",
+        (0x5000, ..) => "5XY0",
+        (0x6000, ..) => "6XNN",
+        (0x7000, ..) => "7XNN",
+        (0x8000, 0x0, _) => "8XY0",
+        (0x8000, 0x1, _) => "8XY1",
+        (0x8000, 0x2, _) => "8XY2",
+        (0x8000, 0x3, _) => "8XY3",
+        (0x8000, 0x4, _) => "8XY4",
+        (0x8000, 0x5, _) => "8XY5",
+        (0x8000, 0x6, _) => "8XY6",
+        (0x8000, 0x7, _) => "8XY7",
+        (0x8000, 0xE, _) => "8XYE",
+        (0x9000, ..) => "9XY0",
+        (0xA000, ..) => "ANNN",
+        (0xB000, ..) => "BNNN",
+        (0xC000, ..) => "CXNN",
+        (0xD000, ..) => "DXYN",
+        (0xE000, _, 0x9E) => "EX9E",
+        (0xE000, _, 0xA1) => "EXA1",
+        (0xF000, _, 0x07) => "FX07",
+        (0xF000, _, 0x0A) => "FX0A",
+        (0xF000, _, 0x15) => "FX15",
+        (0xF000, _, 0x18) => "FX18",
+        (0xF000, _, 0x1E) => "FX1E",
+        (0xF000, _, 0x29) => "FX29",
+        (0xF000, _, 0x33) => "FX33",
+        (0xF000, _, 0x55) => "FX55",
+        (0xF000, _, 0x65) => "FX65",
+        _ => return None,
+    };
+    OPCODES.iter().position(|&(known, ..)| known == pattern)
+}
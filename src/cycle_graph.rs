@@ -0,0 +1,62 @@
+// Cycle budget graph (F4, or --cycle-graph): a rolling graph in the bottom-right corner of the instructions run and
+// sprites drawn in each 60Hz frame, to help pick a speed for a ROM. Instructions are green bars against the budget
+// set by the CPU speed (the yellow line); a frame that falls short of it was cut off by the debugger. Draws are the
+// red line, scaled to the busiest frame shown; a game whose draws per frame sag or stutter at a speed usually needs
+// more instructions per frame than it's getting.
+use crate::overlay::{Canvas, CHAR_HEIGHT, CHAR_WIDTH};
+use std::collections::VecDeque;
+
+const FRAMES: usize = 64; // Frames shown, oldest on the left
+
+pub struct CycleGraph {
+    frames: VecDeque<(u32, u32)>, // Instructions run and sprites drawn in each frame
+}
+
+impl CycleGraph {
+    pub fn new() -> Self {
+        CycleGraph { frames: VecDeque::with_capacity(FRAMES) }
+    }
+
+    pub fn record(&mut self, instructions: u32, draws: u32) {
+        if self.frames.len() == FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((instructions, draws));
+    }
+
+    // Draw the graph, with `budget` instructions per frame as the full-speed line
+    pub fn draw(&self, canvas: &mut Canvas, budget: u32) {
+        let (instructions, draws) = self.frames.back().copied().unwrap_or_default();
+        let label = format!("IPF {}/{} DRW {}", instructions, budget, draws);
+
+        let bar_width = canvas.text_scale;
+        let padding = canvas.text_scale * 2;
+        let plot_height = canvas.height / 4;
+        let label_height = CHAR_HEIGHT * canvas.text_scale;
+        let label_width = label.len() * CHAR_WIDTH * canvas.text_scale;
+        let width = (FRAMES * bar_width).max(label_width) + padding * 2;
+        let height = plot_height + label_height + padding * 3;
+        let left = canvas.width.saturating_sub(width);
+        let top = canvas.height.saturating_sub(height);
+        let plot_top = top + padding * 2 + label_height;
+        let plot_bottom = plot_top + plot_height;
+
+        canvas.fill_rect(left, top, width, height, 0x202020);
+        canvas.draw_text(left + padding, top + padding, &label, 0xFFFFFF);
+
+        // Leave headroom above the budget line, so going over it would show
+        let most_instructions = self.frames.iter().map(|&(instructions, _)| instructions).max().unwrap_or(0);
+        let instructions_scale = (budget + budget / 4).max(most_instructions).max(1) as usize;
+        let most_draws = self.frames.iter().map(|&(_, draws)| draws).max().unwrap_or(0).max(1) as usize;
+
+        for (index, &(instructions, draws)) in self.frames.iter().enumerate() {
+            let x = left + padding + index * bar_width;
+            let bar = instructions as usize * plot_height / instructions_scale;
+            canvas.fill_rect(x, plot_bottom - bar, bar_width, bar, 0x40C040);
+            let y = plot_bottom - draws as usize * (plot_height - 1) / most_draws - 1;
+            canvas.fill_rect(x, y, bar_width, 1, 0xFF4040);
+        }
+        let budget_y = plot_bottom - budget as usize * plot_height / instructions_scale;
+        canvas.fill_rect(left + padding, budget_y, FRAMES * bar_width, 1, 0xFFD700);
+    }
+}
@@ -0,0 +1,532 @@
+// Debugger driven by commands typed into the terminal (enabled with --debug-repl)
+//
+// Commands are read on a background thread and handled once per frame, so the game keeps running while
+// you type. Addresses are in hex, and conditions use the same expressions as achievements (see expr.rs):
+//
+//   regs               Show the registers, timers and stack
+//   mem <addr> [len]   Show memory (16 bytes unless a length is given)
+//   disasm [addr] [n]  Disassemble n instructions (10 by default) from an address (the PC by default)
+//   step [n]           Pause and run one instruction (or n), showing the next one
+//   run-until <expr>   Resume at full speed and pause as soon as the condition holds, e.g. `run-until v0 == 0x20`,
+//                      `run-until draws > 100` or `run-until pc == 0x2F0`
+//   break <addr> [do <actions>]
+//                      Pause when execution reaches an address, or run actions there (separated by `;`):
+//                        log <expr>, <expr>, ...   print values, e.g. `log v0, mem[0x3A0]`
+//                        dump <start>..<end>       print a range of memory
+//                        save <slot>               save the machine state in a slot (0-9, see savestate.rs)
+//                        continue                  keep running instead of pausing afterwards
+//                      e.g. `break 0x2F0 do log v0, v1; continue`
+//   break              List breakpoints
+//   delete <addr>      Remove a breakpoint
+//   load <slot>        Go back to a state saved in a slot, by a breakpoint or from the slot picker (F2)
+//   watch <name> = <expr>
+//                      Show a value on screen every frame, e.g. `watch score = mem[0x3A0] * 10 + mem[0x3A1]`
+//   unwatch <name>     Stop showing a value
+//   poke <addr> <bytes>
+//                      Write to memory, e.g. `poke 0x3A0 09` or `poke 0x220 1200 00E0` (each value is a byte or a
+//                      word, going by its number of hex digits)
+//   asm <addr> <instruction>
+//                      Assemble an instruction into memory, e.g. `asm 0x220 "JP 0x200"` (see disasm.rs)
+//   patches            List the pokes and assembled instructions, oldest first
+//   undo               Put back what the last poke or asm overwrote
+//   export-rom <path> [<start>..<end>]
+//                      Write memory back out as a ROM file, e.g. to keep patches; by default the ROM's own bytes
+//                      (from where it was loaded, for as long as it was), or the given range
+//   step-back          Go back one instruction (and pause), as far back as the history timeline goes
+//   coverage           Show how many times each instruction has run (see coverage.rs); unless --coverage was
+//                      given, the first use starts counting
+//   copy regs|mem|disasm ...
+//                      Copy what regs, mem or disasm would show to the clipboard, e.g. `copy mem 0x300 32`, to paste
+//                      into a bug report (needs `--features clipboard`)
+//   pause              Pause the game
+//   continue           Resume the game
+//   help               List the commands
+use crate::rewind::{Event, RewindBuffer};
+use crate::savestate::{self, SaveSlots, SLOTS};
+use crate::watch::Watches;
+use chip8::coverage::Coverage;
+use chip8::disasm::{assemble, disassemble};
+use chip8::expr::Expr;
+use chip8::Chip8;
+use std::fmt;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub struct Debugger {
+    commands: Receiver<String>,        // Lines typed on stdin
+    pub paused: bool,                  // Whether the game is stopped (the front end stops running ticks)
+    run_until: Option<(String, Expr)>, // Condition to pause on, with the text it was written as
+    breakpoints: Vec<Breakpoint>,
+    patches: Vec<Patch>,               // Changes made with poke and asm, most recent last, for undo
+    pub rom_length: usize,             // Size of the loaded ROM, for export-rom
+    // Opened on the first copy and kept open, since on X11 what was copied only stays on the clipboard until then
+    #[cfg(feature = "clipboard")]
+    clipboard: Option<arboard::Clipboard>,
+}
+
+struct Patch {
+    address: u16,
+    before: Vec<u8>, // What was there, to put back on undo
+    after: Vec<u8>,
+}
+
+struct Breakpoint {
+    address: u16,
+    definition: String,   // The rest of the break command, for listing
+    actions: Vec<Action>, // Run in order when the breakpoint is hit
+    resume: bool,         // Carry on running afterwards instead of pausing
+}
+
+enum Action {
+    Log(Vec<(String, Expr)>), // Expressions to print, with the text they were written as
+    Dump(u16, u16),           // Memory range to print (end not included)
+    Save(usize),              // Slot to save the state in
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("Debugger ready; type `help` for a list of commands");
+        Debugger {
+            commands,
+            paused: false,
+            run_until: None,
+            breakpoints: Vec::new(),
+            patches: Vec::new(),
+            rom_length: 0,
+            #[cfg(feature = "clipboard")]
+            clipboard: None,
+        }
+    }
+
+    // Handle the commands typed since the last frame; history is None when going back isn't allowed.
+    // Returns true if the machine was put into a different state (other than by running it)
+    pub fn poll(
+        &mut self,
+        chip8: &mut Chip8,
+        mut history: Option<&mut RewindBuffer>,
+        watches: &mut Watches,
+        slots: &SaveSlots,
+    ) -> bool {
+        let mut rewritten = false;
+        while let Ok(line) = self.commands.try_recv() {
+            match self.execute(line.trim(), chip8, history.as_deref_mut(), watches, slots) {
+                Ok(changed) => rewritten |= changed,
+                Err(e) => println!("{}", e),
+            }
+        }
+        rewritten
+    }
+
+    fn execute(
+        &mut self,
+        line: &str,
+        chip8: &mut Chip8,
+        history: Option<&mut RewindBuffer>,
+        watches: &mut Watches,
+        slots: &SaveSlots,
+    ) -> Result<bool, String> {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let mut arguments = argument.split_whitespace();
+        match command {
+            "" => (),
+            "help" => println!("{}", HELP),
+            "regs" | "mem" | "disasm" => print!("{}", report(command, argument, chip8)?),
+            "copy" => {
+                let (what, rest) = argument.split_once(' ').unwrap_or((argument, ""));
+                if !["regs", "mem", "disasm"].contains(&what) {
+                    return Err("Expected what to copy: `copy regs`, `copy mem <addr> [len]` or `copy disasm`".into());
+                }
+                let text = report(what, rest.trim(), chip8)?;
+                self.copy(&text)?;
+                println!("Copied {} lines to the clipboard", text.lines().count());
+            }
+            "step" => {
+                let history = history.ok_or("Can't step while a replay is playing or recording")?;
+                let count = arguments.next().map_or(Ok(1), parse_number)?;
+                self.paused = true;
+                for _ in 0..count {
+                    chip8.emulate_cycle();
+                    history.log(Event::Cycles(1));
+                }
+                for fault in chip8.take_faults() {
+                    println!("{}", fault);
+                }
+                print!("{}", instruction(chip8, chip8.pc()));
+                return Ok(true);
+            }
+            "run-until" => {
+                let condition = Expr::parse(argument)?;
+                if condition.is_true(chip8) {
+                    return Err(format!("`{}` already holds", argument));
+                }
+                self.run_until = Some((argument.to_string(), condition));
+                self.paused = false;
+            }
+            "break" if argument.is_empty() => {
+                if self.breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for breakpoint in &self.breakpoints {
+                    println!("break {}", breakpoint.definition);
+                }
+            }
+            "break" => {
+                let breakpoint = Breakpoint::parse(argument)?;
+                self.breakpoints.retain(|existing| existing.address != breakpoint.address);
+                self.breakpoints.push(breakpoint);
+            }
+            "delete" => {
+                let address = parse_address(argument)?;
+                let count = self.breakpoints.len();
+                self.breakpoints.retain(|breakpoint| breakpoint.address != address);
+                if self.breakpoints.len() == count {
+                    return Err(format!("No breakpoint at {:03X}", address));
+                }
+            }
+            "load" => {
+                let history = history.ok_or("Can't load a state while a replay is playing or recording")?;
+                let slot = parse_slot(argument)?;
+                chip8.restore(&slots.load(slot)?);
+                history.clear(); // The history leading up to now doesn't lead to the loaded state
+                self.paused = true;
+                println!("Loaded slot {} at {:03X}", slot, chip8.pc());
+                return Ok(true);
+            }
+            "poke" | "asm" => {
+                let history = history.ok_or("Can't change memory while a replay is playing or recording")?;
+                let (address, rest) = argument.split_once(' ').unwrap_or((argument, ""));
+                let address = parse_address(address)?;
+                let bytes = if command == "poke" {
+                    parse_bytes(rest)?
+                } else {
+                    assemble(rest.trim().trim_matches('"'))?.to_be_bytes().to_vec()
+                };
+                if address as usize + bytes.len() > 0x1000 {
+                    return Err(format!("{} bytes at {:03X} would go past the end of memory", bytes.len(), address));
+                }
+                let before = (0..bytes.len() as u16).map(|offset| chip8.peek(address + offset)).collect();
+                let patch = Patch { address, before, after: bytes };
+                patch.write(chip8, &patch.after);
+                println!("{}", patch);
+                self.patches.push(patch);
+                // The history would replay from before the change without it
+                history.clear();
+                return Ok(true);
+            }
+            "coverage" => match &chip8.coverage {
+                Some(coverage) => print!("{}", coverage.report()),
+                None => {
+                    chip8.coverage = Some(Coverage::new());
+                    println!("Counting instructions from now on; `coverage` again shows what has run");
+                }
+            },
+            "patches" => {
+                if self.patches.is_empty() {
+                    println!("No patches");
+                }
+                for patch in &self.patches {
+                    println!("{}", patch);
+                }
+            }
+            "undo" => {
+                let history = history.ok_or("Can't change memory while a replay is playing or recording")?;
+                let patch = self.patches.pop().ok_or("Nothing to undo")?;
+                patch.write(chip8, &patch.before);
+                println!("Undid {}", patch);
+                history.clear();
+                return Ok(true);
+            }
+            "export-rom" => {
+                let (path, range) = argument.split_once(' ').unwrap_or((argument, ""));
+                if path.is_empty() {
+                    return Err("Expected a file to export to, e.g. `export-rom patched.ch8`".to_string());
+                }
+                let (start, end) = match range.trim() {
+                    "" => (chip8.load_address, (chip8.load_address as usize + self.rom_length).min(0x1000) as u16),
+                    range => parse_range(range)?,
+                };
+                if end <= start {
+                    return Err("Nothing to export".to_string());
+                }
+                let rom: Vec<u8> = (start..end).map(|address| chip8.peek(address)).collect();
+                std::fs::write(path, &rom).map_err(|e| format!("Error writing {}: {}", path, e))?;
+                println!("Wrote {:03X}..{:03X} ({} bytes) to {}", start, end, rom.len(), path);
+            }
+            "step-back" => {
+                let history = history.ok_or("Can't step back while a replay is playing or recording")?;
+                if !history.step_back(chip8) {
+                    return Err("No more history to step back through".to_string());
+                }
+                self.paused = true;
+                println!("Stepped back to {:03X}", chip8.pc());
+                return Ok(true);
+            }
+            "watch" => watches.add(argument)?,
+            "unwatch" => {
+                if !watches.remove(argument) {
+                    return Err(format!("Nothing called `{}` is being watched", argument));
+                }
+            }
+            "pause" => {
+                self.paused = true;
+                println!("Paused at {:03X}", chip8.pc());
+            }
+            "continue" => self.paused = false,
+            _ => return Err(format!("Unknown command `{}`", command)),
+        }
+        Ok(false)
+    }
+
+    #[cfg(feature = "clipboard")]
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        let error = |e: arboard::Error| format!("Error copying to the clipboard: {}", e);
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new().map_err(error)?);
+        }
+        self.clipboard.as_mut().unwrap().set_text(text).map_err(error)
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn copy(&mut self, _text: &str) -> Result<(), String> {
+        Err("Copying needs the clipboard feature; build with `--features clipboard`".to_string())
+    }
+
+    // Whether the front end needs to call check after every instruction
+    pub fn is_watching(&self) -> bool {
+        self.run_until.is_some() || !self.breakpoints.is_empty()
+    }
+
+    // Call after each instruction while watching; returns true (and pauses) once a condition is met
+    pub fn check(&mut self, chip8: &Chip8, slots: &mut SaveSlots) -> bool {
+        if let Some((text, condition)) = &self.run_until {
+            if condition.is_true(chip8) {
+                println!("Paused at {:03X}: {}", chip8.pc(), text);
+                self.run_until = None;
+                self.paused = true;
+            }
+        }
+
+        // Breakpoints stop before the instruction at their address runs
+        if let Some(breakpoint) = self.breakpoints.iter().find(|breakpoint| breakpoint.address == chip8.pc()) {
+            for action in &breakpoint.actions {
+                match action {
+                    Action::Log(values) => {
+                        let values: Vec<String> =
+                            values.iter().map(|(text, expr)| format!("{} = {}", text, expr.eval(chip8))).collect();
+                        println!("[{:03X}] {}", breakpoint.address, values.join(", "));
+                    }
+                    Action::Dump(start, end) => print!("{}", dump(chip8, *start, *end)),
+                    Action::Save(slot) => {
+                        let thumbnail = savestate::thumbnail_from_display(chip8.display_rows());
+                        match slots.save(*slot, &chip8.snapshot(), thumbnail) {
+                            Ok(()) => println!("[{:03X}] Saved slot {}", breakpoint.address, slot),
+                            Err(e) => println!("[{:03X}] {}", breakpoint.address, e),
+                        }
+                    }
+                }
+            }
+            if !breakpoint.resume {
+                println!("Breakpoint at {:03X}", breakpoint.address);
+                self.paused = true;
+            }
+        }
+
+        self.paused
+    }
+}
+
+impl Patch {
+    fn write(&self, chip8: &mut Chip8, bytes: &[u8]) {
+        for (address, &byte) in (self.address..).zip(bytes) {
+            chip8.poke(address, byte);
+        }
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+        write!(f, "{:03X}: {} (was {})", self.address, hex(&self.after), hex(&self.before))
+    }
+}
+
+impl Breakpoint {
+    // Parse the part of a break command after `break`, e.g. `0x2F0 do log v0; continue`
+    fn parse(definition: &str) -> Result<Self, String> {
+        let (address, actions) = match definition.split_once(" do ") {
+            Some((address, actions)) => (address, actions),
+            None => (definition, ""),
+        };
+
+        let mut breakpoint = Breakpoint {
+            address: parse_address(address.trim())?,
+            definition: definition.to_string(),
+            actions: Vec::new(),
+            resume: false,
+        };
+
+        for action in actions.split(';').map(str::trim).filter(|action| !action.is_empty()) {
+            let (name, argument) = action.split_once(' ').unwrap_or((action, ""));
+            let argument = argument.trim();
+            match name {
+                "log" => {
+                    let values = argument
+                        .split(',')
+                        .map(|text| Ok((text.trim().to_string(), Expr::parse(text)?)))
+                        .collect::<Result<_, String>>()?;
+                    breakpoint.actions.push(Action::Log(values));
+                }
+                "dump" => {
+                    let (start, end) = parse_range(argument)?;
+                    breakpoint.actions.push(Action::Dump(start, end));
+                }
+                "save" => breakpoint.actions.push(Action::Save(parse_slot(argument)?)),
+                "continue" => breakpoint.resume = true,
+                _ => return Err(format!("Unknown breakpoint action `{}`", name)),
+            }
+        }
+
+        Ok(breakpoint)
+    }
+}
+
+const HELP: &str = "\
+regs                          show the registers, timers and stack
+mem <addr> [len]              show memory
+disasm [addr] [n]             disassemble n instructions from an address (the PC by default)
+step [n]                      pause and run one instruction (or n)
+poke <addr> <bytes>           write bytes (or words) to memory, e.g. `poke 0x220 1200`
+asm <addr> <instruction>      assemble an instruction into memory, e.g. `asm 0x220 \"JP 0x200\"`
+patches                       list the changes made with poke and asm
+undo                          undo the last poke or asm
+export-rom <path> [range]     write the ROM (or a range of memory, e.g. 0x200..0x400) to a file
+step-back                     go back one instruction
+coverage                      show which instructions have run (counting from then on, the first time)
+run-until <expr>              run until a condition holds, e.g. `run-until v0 == 0x20`
+break <addr> [do <actions>]   pause at an address, or run actions there: log <expr>, ...; dump <start>..<end>;
+                              save <slot>; continue
+break                         list breakpoints
+delete <addr>                 remove a breakpoint
+load <slot>                   go back to a state saved in a slot
+watch <name> = <expr>         show a value on screen, e.g. `watch score = mem[0x3A0] * 10 + mem[0x3A1]`
+unwatch <name>                stop showing a value
+copy regs|mem|disasm ...      copy what regs, mem or disasm shows to the clipboard, e.g. `copy mem 0x300 32`
+pause, continue               stop and resume the game
+Addresses are in hex.";
+
+// What regs, mem or disasm shows, given the rest of the command, as lines of text for printing or copying
+fn report(command: &str, argument: &str, chip8: &Chip8) -> Result<String, String> {
+    let mut arguments = argument.split_whitespace();
+    let mut text = String::new();
+    match command {
+        "regs" => {
+            let v: Vec<String> = (0..16).map(|x| format!("V{:X} {:02X}", x, chip8.v(x))).collect();
+            text += &format!("{}\n", v[..8].join("  "));
+            text += &format!("{}\n", v[8..].join("  "));
+            text += &format!(
+                "PC {:03X}  I {:03X}  SP {}  DT {:02X}  ST {:02X}\n",
+                chip8.pc(),
+                chip8.i(),
+                chip8.sp(),
+                chip8.delay_timer(),
+                chip8.sound_timer()
+            );
+            let stack: Vec<String> =
+                (0..chip8.sp() as usize).map(|level| format!("{:03X}", chip8.stack(level))).collect();
+            text += &format!("Stack: {}\n", if stack.is_empty() { "(empty)".to_string() } else { stack.join(" ") });
+        }
+        "mem" => {
+            let start = parse_address(arguments.next().unwrap_or(""))?;
+            let length = arguments.next().map_or(Ok(16), parse_number)?;
+            text = dump(chip8, start, (start as usize + length).min(0x1000) as u16);
+        }
+        _ => {
+            let start = arguments.next().map_or(Ok(chip8.pc()), parse_address)?;
+            let count = arguments.next().map_or(Ok(10), parse_number)?;
+            for index in 0..count {
+                let addr = start as usize + index * 2;
+                if addr > 0xFFE {
+                    break;
+                }
+                text += &instruction(chip8, addr as u16);
+            }
+        }
+    }
+    Ok(text)
+}
+
+// The instruction at an address as a line, marked if it's the next one to run
+fn instruction(chip8: &Chip8, addr: u16) -> String {
+    let opcode = (chip8.peek(addr) as u16) << 8 | chip8.peek(addr + 1) as u16;
+    let marker = if addr == chip8.pc() { '>' } else { ' ' };
+    format!("{} {:03X}: {:04X}  {}\n", marker, addr, opcode, disassemble(opcode))
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("Expected a number, found `{}`", text))
+}
+
+// Addresses are always hex, with or without 0x
+fn parse_address(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&address| address <= 0x1000)
+        .ok_or_else(|| format!("Expected an address (0x000-0xFFF), found `{}`", text))
+}
+
+// A range of memory like 0x300..0x320 (the end isn't included)
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    let (start, end) =
+        text.split_once("..").ok_or_else(|| format!("Expected a range like 0x300..0x320, found `{}`", text))?;
+    Ok((parse_address(start)?, parse_address(end)?))
+}
+
+// Values for poke, each a byte or a word in hex (2 or 4 digits), with or without 0x
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for value in text.split_whitespace() {
+        let digits = value.strip_prefix("0x").unwrap_or(value);
+        let number = u16::from_str_radix(digits, 16).map_err(|_| format!("Expected a hex value, found `{}`", value))?;
+        match digits.len() {
+            1 | 2 => bytes.push(number as u8),
+            3 | 4 => bytes.extend_from_slice(&number.to_be_bytes()),
+            _ => return Err(format!("Expected a byte or word, found `{}`", value)),
+        }
+    }
+    if bytes.is_empty() {
+        return Err("Expected some values to write".to_string());
+    }
+    Ok(bytes)
+}
+
+fn parse_slot(text: &str) -> Result<usize, String> {
+    text.parse()
+        .ok()
+        .filter(|&slot| slot < SLOTS)
+        .ok_or_else(|| format!("Expected a slot number (0-{}), found `{}`", SLOTS - 1, text))
+}
+
+// Memory from start up to (not including) end, 16 bytes to a line
+fn dump(chip8: &Chip8, start: u16, end: u16) -> String {
+    let mut text = String::new();
+    for line in (start..end).step_by(16) {
+        let bytes: Vec<String> = (line..end.min(line + 16)).map(|addr| format!("{:02X}", chip8.peek(addr))).collect();
+        text += &format!("{:03X}: {}\n", line, bytes.join(" "));
+    }
+    text
+}
@@ -0,0 +1,105 @@
+// Memory-mapped pseudo-devices (enabled with --devices). NONSTANDARD: no real CHIP-8 interpreter has these, and a
+// ROM that uses them won't work anywhere else. They are for homebrew experiments, e.g. printing debug output from a
+// ROM or timing something by the clock.
+//
+// The devices take up a few bytes of memory starting at a base address (0xFF0 by default; see DEFAULT_BASE), where
+// data reads and writes (DXYN, FX33, FX55, FX65) go to the devices instead of memory:
+//
+//   base + 0  RTC        reads as the seconds of the host's clock (0-59)
+//   base + 1  Console    writes send the byte to the emulator's standard output; reads as 0
+//   base + 2  Frames     reads as the number of 60Hz ticks since power-on, wrapping around at 256
+//
+// Writes to the read-only ones are ignored. The devices' state isn't part of save states or replays, so a ROM that
+// reads the clock won't replay the same way. The rewind buffer does keep it (see DeviceState), so stepping back in
+// the debugger doesn't tick the frame counter twice or print the console's output again.
+use alloc::vec::Vec;
+
+pub const DEFAULT_BASE: u16 = 0xFF0;
+pub const SIZE: u16 = 3; // Bytes of memory the devices take up
+
+const RTC: u16 = 0;
+const CONSOLE: u16 = 1;
+const FRAMES: u16 = 2;
+
+pub struct Devices {
+    base: u16,
+    pub seconds: u8, // What the RTC reads as; kept up to date by the front end, since the core has no clock
+    frames: u8,      // Ticks since power-on, wrapping around
+    output: Vec<u8>, // Bytes written to the console since the front end last took them
+}
+
+// What the devices read as at some point, for putting them back there later
+#[derive(Clone, Copy)]
+pub struct DeviceState {
+    seconds: u8,
+    frames: u8,
+}
+
+impl Devices {
+    // The devices at a base address, which has to leave room for all of them below 0x1000
+    pub fn new(base: u16) -> Result<Self, &'static str> {
+        if base > 0x1000 - SIZE {
+            return Err("The devices don't fit in memory at that address");
+        }
+        Ok(Devices { base, seconds: 0, frames: 0, output: Vec::new() })
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    // What a read from an address gives, if it's one of the devices'
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr.checked_sub(self.base)? {
+            RTC => Some(self.seconds),
+            CONSOLE => Some(0),
+            FRAMES => Some(self.frames),
+            _ => None,
+        }
+    }
+
+    // Send a write to the devices; returns false if the address isn't one of theirs
+    pub fn write(&mut self, addr: u16, value: u8) -> bool {
+        match addr.checked_sub(self.base) {
+            Some(CONSOLE) => self.output.push(value),
+            Some(RTC | FRAMES) => (),
+            _ => return false,
+        }
+        true
+    }
+
+    // Back to how things are at power-on
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.output.clear();
+    }
+
+    // Call at 60Hz, along with the timers
+    pub fn tick(&mut self) {
+        self.frames = self.frames.wrapping_add(1);
+    }
+
+    pub fn state(&self) -> DeviceState {
+        DeviceState { seconds: self.seconds, frames: self.frames }
+    }
+
+    pub fn set_state(&mut self, state: DeviceState) {
+        self.seconds = state.seconds;
+        self.frames = state.frames;
+    }
+
+    // Bytes waiting to be taken from the console
+    pub fn pending_output(&self) -> usize {
+        self.output.len()
+    }
+
+    // Forget anything written to the console after the first `len` bytes waiting to be taken
+    pub fn discard_output_after(&mut self, len: usize) {
+        self.output.truncate(len);
+    }
+
+    // Bytes written to the console since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+}
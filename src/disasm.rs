@@ -0,0 +1,153 @@
+// Disassembler: turns opcodes into assembly in the style of Cowgod's reference
+// (http://devernay.free.fr/hacks/chip8/C8TECH10.HTM), e.g. 0x6A02 => "LD VA, 0x02"
+//
+// Opcodes are decoded the same way Chip8::execute_instruction does, and anything it wouldn't run comes out
+// as a data word, e.g. "DW 0x0123".
+//
+// assemble goes the other way, for a single instruction written the same way (e.g. for patching memory from the
+// debugger). Numbers are hex with a 0x or $ prefix and decimal without one, and case doesn't matter.
+use alloc::{format, string::String, vec::Vec};
+
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+
+    match (opcode & 0xF000, n, kk) {
+        (0x0000, _, 0xE0) => "CLS".into(),
+        (0x0000, _, 0xEE) => "RET".into(),
+        (0x1000, _, _) => format!("JP 0x{:03X}", nnn),
+        (0x2000, _, _) => format!("CALL 0x{:03X}", nnn),
+        (0x3000, _, _) => format!("SE V{:X}, 0x{:02X}", x, kk),
+        (0x4000, _, _) => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        (0x5000, _, _) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6000, _, _) => format!("LD V{:X}, 0x{:02X}", x, kk),
+        (0x7000, _, _) => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        (0x8000, 0x0, _) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8000, 0x1, _) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8000, 0x2, _) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8000, 0x3, _) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8000, 0x4, _) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8000, 0x5, _) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8000, 0x6, _) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8000, 0x7, _) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8000, 0xE, _) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9000, _, _) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA000, _, _) => format!("LD I, 0x{:03X}", nnn),
+        (0xB000, _, _) => format!("JP V0, 0x{:03X}", nnn),
+        (0xC000, _, _) => format!("RND V{:X}, 0x{:02X}", x, kk),
+        (0xD000, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE000, _, 0x9E) => format!("SKP V{:X}", x),
+        (0xE000, _, 0xA1) => format!("SKNP V{:X}", x),
+        (0xF000, _, 0x07) => format!("LD V{:X}, DT", x),
+        (0xF000, _, 0x0A) => format!("LD V{:X}, K", x),
+        (0xF000, _, 0x15) => format!("LD DT, V{:X}", x),
+        (0xF000, _, 0x18) => format!("LD ST, V{:X}", x),
+        (0xF000, _, 0x1E) => format!("ADD I, V{:X}", x),
+        (0xF000, _, 0x29) => format!("LD F, V{:X}", x),
+        (0xF000, _, 0x33) => format!("LD B, V{:X}", x),
+        (0xF000, _, 0x55) => format!("LD [I], V{:X}", x),
+        (0xF000, _, 0x65) => format!("LD V{:X}, [I]", x),
+        _ => format!("DW 0x{:04X}", opcode),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI, // [I]
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Number(u32),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let upper = text.to_ascii_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "B" => Operand::B,
+            _ if upper.len() == 2 && upper.starts_with('V') => {
+                Operand::V(u16::from_str_radix(&upper[1..], 16).map_err(|_| format!("No register called `{}`", text))?)
+            }
+            _ => {
+                let number = match upper.strip_prefix("0X").or_else(|| upper.strip_prefix('$')) {
+                    Some(digits) => u32::from_str_radix(digits, 16),
+                    None => upper.parse(),
+                };
+                Operand::Number(number.map_err(|_| format!("Expected a register or number, found `{}`", text))?)
+            }
+        };
+        Ok(operand)
+    }
+}
+
+// Turn one instruction, e.g. "LD VA, 0x02", into its opcode
+pub fn assemble(text: &str) -> Result<u16, String> {
+    use Operand::*;
+
+    let text = text.trim();
+    let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let operands = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+        .map(Operand::parse)
+        .collect::<Result<Vec<Operand>, String>>()?;
+
+    let limit = |value: u32, max: u32| {
+        (value <= max).then_some(value as u16).ok_or_else(|| format!("0x{:X} is too big for `{}`", value, text))
+    };
+    let xy = |x: u16, y: u16| x << 8 | y << 4;
+
+    let opcode = match (mnemonic.to_ascii_uppercase().as_str(), &operands[..]) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("JP", [Number(nnn)]) => 0x1000 | limit(*nnn, 0xFFF)?,
+        ("CALL", [Number(nnn)]) => 0x2000 | limit(*nnn, 0xFFF)?,
+        ("SE", [V(x), Number(kk)]) => 0x3000 | x << 8 | limit(*kk, 0xFF)?,
+        ("SNE", [V(x), Number(kk)]) => 0x4000 | x << 8 | limit(*kk, 0xFF)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+        ("LD", [V(x), Number(kk)]) => 0x6000 | x << 8 | limit(*kk, 0xFF)?,
+        ("ADD", [V(x), Number(kk)]) => 0x7000 | x << 8 | limit(*kk, 0xFF)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+        ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+        ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+        ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+        ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+        ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+        ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+        ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+        ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+        ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+        ("LD", [I, Number(nnn)]) => 0xA000 | limit(*nnn, 0xFFF)?,
+        ("JP", [V(0), Number(nnn)]) => 0xB000 | limit(*nnn, 0xFFF)?,
+        ("RND", [V(x), Number(kk)]) => 0xC000 | x << 8 | limit(*kk, 0xFF)?,
+        ("DRW", [V(x), V(y), Number(n)]) => 0xD000 | xy(*x, *y) | limit(*n, 0xF)?,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+        ("LD", [V(x), K]) => 0xF00A | x << 8,
+        ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+        ("LD", [St, V(x)]) => 0xF018 | x << 8,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("LD", [F, V(x)]) => 0xF029 | x << 8,
+        ("LD", [B, V(x)]) => 0xF033 | x << 8,
+        ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+        ("DW", [Number(word)]) => limit(*word, 0xFFFF)?,
+        _ => return Err(format!("Can't assemble `{}`", text)),
+    };
+    Ok(opcode)
+}
@@ -0,0 +1,211 @@
+// Expressions over the machine state, for conditions like `mem[0x3A0] >= 100 && v3 == 2`
+//
+// Values:    numbers (decimal or 0x hex), registers v0-vF, i, pc, sp, dt (delay timer), st (sound timer),
+//            draws (DXYN instructions run so far), mem[<expr>] for the byte at an address, and parentheses
+// Operators: from loosest to tightest binding: ||, &&, == != < <= > >=, + -, * / %, & |
+//
+// Comparisons and the logical operators give 1 for true and 0 for false; any nonzero value counts as true.
+// Dividing by zero gives 0.
+use crate::chip8::Chip8;
+use alloc::{boxed::Box, format, string::String};
+
+pub enum Expr {
+    Number(i64),
+    Register(usize),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Draws,
+    Memory(Box<Expr>),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+}
+
+#[derive(Clone, Copy)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+}
+
+// Operators grouped by precedence, loosest first
+const PRECEDENCE: [&[(&str, Operator)]; 6] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessEqual),
+        (">=", Operator::GreaterEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+    &[("*", Operator::Multiply), ("/", Operator::Divide), ("%", Operator::Remainder)],
+    &[("&", Operator::BitAnd), ("|", Operator::BitOr)],
+];
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, position: 0 };
+        let expr = parser.binary(0)?;
+        parser.skip_whitespace();
+        if parser.position < text.len() {
+            return Err(format!("unexpected `{}` in `{}`", &text[parser.position..], text));
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> i64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(x) => chip8.v(*x) as i64,
+            Expr::I => chip8.i() as i64,
+            Expr::Pc => chip8.pc() as i64,
+            Expr::Sp => chip8.sp() as i64,
+            Expr::DelayTimer => chip8.delay_timer() as i64,
+            Expr::SoundTimer => chip8.sound_timer() as i64,
+            Expr::Draws => chip8.draws() as i64,
+            Expr::Memory(address) => chip8.peek(address.eval(chip8) as u16) as i64,
+            Expr::Binary(left, operator, right) => {
+                let left = left.eval(chip8);
+                // || and && short-circuit
+                match operator {
+                    Operator::Or if left != 0 => return 1,
+                    Operator::And if left == 0 => return 0,
+                    _ => (),
+                }
+                let right = right.eval(chip8);
+                match operator {
+                    Operator::Or | Operator::And => (right != 0) as i64,
+                    Operator::Equal => (left == right) as i64,
+                    Operator::NotEqual => (left != right) as i64,
+                    Operator::Less => (left < right) as i64,
+                    Operator::LessEqual => (left <= right) as i64,
+                    Operator::Greater => (left > right) as i64,
+                    Operator::GreaterEqual => (left >= right) as i64,
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Subtract => left.wrapping_sub(right),
+                    Operator::Multiply => left.wrapping_mul(right),
+                    Operator::Divide => left.checked_div(right).unwrap_or(0),
+                    Operator::Remainder => left.checked_rem(right).unwrap_or(0),
+                    Operator::BitAnd => left & right,
+                    Operator::BitOr => left | right,
+                }
+            }
+        }
+    }
+
+    // Convenience for conditions
+    pub fn is_true(&self, chip8: &Chip8) -> bool {
+        self.eval(chip8) != 0
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize, // Byte offset of the next unread character
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    // Consume `token` if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Parse operators at this precedence level and tighter, left to right
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.value();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        'operators: loop {
+            for &(token, operator) in PRECEDENCE[level] {
+                // Don't mistake the start of || or && for | or &
+                let after = self.rest().trim_start().get(1..).unwrap_or("");
+                let doubled = matches!(token, "&" | "|") && after.starts_with(token);
+                if !doubled && self.eat(token) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(Box::new(left), operator, Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn value(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.binary(0)?;
+            return if self.eat(")") { Ok(expr) } else { Err(format!("missing `)` in `{}`", self.text)) };
+        }
+
+        if self.eat("mem[") {
+            let address = self.binary(0)?;
+            return if self.eat("]") {
+                Ok(Expr::Memory(Box::new(address)))
+            } else {
+                Err(format!("missing `]` in `{}`", self.text))
+            };
+        }
+
+        // Anything else is a single word: a number or a register name
+        self.skip_whitespace();
+        let text = self.text;
+        let rest = &text[self.position..];
+        let length = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+        let word = &rest[..length];
+        self.position += length;
+
+        let lowercase = word.to_ascii_lowercase();
+        let expr = match lowercase.as_str() {
+            "i" => Expr::I,
+            "pc" => Expr::Pc,
+            "sp" => Expr::Sp,
+            "dt" => Expr::DelayTimer,
+            "st" => Expr::SoundTimer,
+            "draws" => Expr::Draws,
+            register if register.len() == 2 && register.starts_with('v') => {
+                let x = usize::from_str_radix(&register[1..], 16).map_err(|_| format!("unknown register `{}`", word))?;
+                Expr::Register(x)
+            }
+            number => {
+                let value = match number.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => number.parse(),
+                };
+                Expr::Number(value.map_err(|_| format!("expected a value in `{}`, found `{}`", self.text, word))?)
+            }
+        };
+        Ok(expr)
+    }
+}
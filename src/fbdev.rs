@@ -0,0 +1,296 @@
+// Linux console backend: draws straight into the kernel framebuffer (/dev/fb0, which the Pi's KMS/DRM driver
+// also provides) and reads keys from the evdev devices under /dev/input, so no X11 or Wayland is needed.
+//
+// The user needs to be able to write /dev/fb0 and read /dev/input/event* (the `video` and `input` groups).
+// The frame is scaled to fill the screen as far as it can while keeping its shape, and centred.
+use crate::backend::Backend;
+use minifb::{Key, KeyRepeat};
+use std::ffi::c_long;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAMEBUFFER: &str = "fb0";
+const EV_KEY: u16 = 1; // Event type for key presses/releases
+
+pub struct Framebuffer {
+    device: File,
+    width: usize,                 // Screen size in pixels
+    height: usize,
+    stride: usize,                // Bytes per screen line (can be more than width * bytes per pixel)
+    bits_per_pixel: usize,        // 16 (RGB565) or 32 (XRGB8888)
+    screen: Vec<u8>,              // Next frame in the framebuffer's own pixel format
+    events: Receiver<(u16, i32)>, // (evdev key code, value) from every keyboard; 0 = up, 1 = down, 2 = repeat
+    held: Vec<Key>,               // Keys down right now
+    pressed: Vec<Key>,            // Keys that went down during the last frame
+    repeated: Vec<Key>,           // Keys the keyboard auto-repeated during the last frame
+    frame_time: Duration,         // Time between frames at the target FPS (zero = don't wait)
+    last_frame: Instant,
+}
+
+impl Framebuffer {
+    pub fn open() -> Result<Self, String> {
+        // The framebuffer's geometry is in sysfs, which saves doing ioctls on the device
+        let sysfs = |name: &str| {
+            let path = format!("/sys/class/graphics/{}/{}", FRAMEBUFFER, name);
+            fs::read_to_string(&path).map_err(|e| format!("Error reading {}: {}", path, e))
+        };
+        let number = |text: &str| {
+            let text = text.trim();
+            text.parse::<usize>().map_err(|_| format!("Unexpected framebuffer value `{}`", text))
+        };
+        let size = sysfs("virtual_size")?;
+        let (width, height) = size
+            .trim()
+            .split_once(',')
+            .ok_or_else(|| format!("Unexpected framebuffer size `{}`", size.trim()))?;
+        let (width, height) = (number(width)?, number(height)?);
+        let stride = number(&sysfs("stride")?)?;
+        let bits_per_pixel = number(&sysfs("bits_per_pixel")?)?;
+        if bits_per_pixel != 16 && bits_per_pixel != 32 {
+            return Err(format!("Unsupported framebuffer format: {} bits per pixel", bits_per_pixel));
+        }
+
+        let path = format!("/dev/{}", FRAMEBUFFER);
+        let device = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("Error opening {}: {}", path, e))?;
+
+        Ok(Framebuffer {
+            device,
+            width,
+            height,
+            stride,
+            bits_per_pixel,
+            screen: vec![0; stride * height],
+            events: open_keyboards(),
+            held: Vec::new(),
+            pressed: Vec::new(),
+            repeated: Vec::new(),
+            frame_time: Duration::ZERO,
+            last_frame: Instant::now(),
+        })
+    }
+
+    // Scale the frame into the screen buffer with nearest-neighbour sampling
+    fn draw(&mut self, buffer: &[u32], width: usize, height: usize) {
+        let target_width = self.width.min(self.height * width / height);
+        let target_height = target_width * height / width;
+        let left = (self.width - target_width) / 2;
+        let top = (self.height - target_height) / 2;
+        let bytes_per_pixel = self.bits_per_pixel / 8;
+
+        for y in 0..target_height {
+            let source = &buffer[y * height / target_height * width..][..width];
+            let line = &mut self.screen[(top + y) * self.stride + left * bytes_per_pixel..];
+            for (x, pixel) in line.chunks_exact_mut(bytes_per_pixel).take(target_width).enumerate() {
+                let color = source[x * width / target_width];
+                if bytes_per_pixel == 4 {
+                    pixel.copy_from_slice(&color.to_le_bytes());
+                } else {
+                    let rgb565 = ((color >> 8) & 0xF800) | ((color >> 5) & 0x07E0) | ((color >> 3) & 0x001F);
+                    pixel.copy_from_slice(&(rgb565 as u16).to_le_bytes());
+                }
+            }
+        }
+    }
+
+    // Apply the key events that arrived since the last frame
+    fn poll_keys(&mut self) {
+        self.pressed.clear();
+        self.repeated.clear();
+        for (code, value) in self.events.try_iter() {
+            let Some(key) = key_from_code(code) else { continue };
+            match value {
+                0 => self.held.retain(|&held| held != key),
+                1 => {
+                    if !self.held.contains(&key) {
+                        self.held.push(key);
+                    }
+                    self.pressed.push(key);
+                }
+                _ => self.repeated.push(key),
+            }
+        }
+    }
+}
+
+impl Backend for Framebuffer {
+    fn is_open(&self) -> bool {
+        true // There is no window to close; Escape still quits
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.held.contains(&key)
+    }
+
+    fn is_key_pressed(&self, key: Key, repeat: KeyRepeat) -> bool {
+        self.pressed.contains(&key) || (repeat == KeyRepeat::Yes && self.repeated.contains(&key))
+    }
+
+    fn get_keys(&self) -> Vec<Key> {
+        self.held.clone()
+    }
+
+    fn update_with_buffer(&mut self, buffer: &[u32], width: usize, height: usize) -> Result<(), String> {
+        self.draw(buffer, width, height);
+        self.device
+            .write_all_at(&self.screen, 0)
+            .map_err(|e| format!("Error writing to the framebuffer: {}", e))?;
+        self.update();
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        self.poll_keys();
+
+        // Wait out the rest of the frame, like minifb does for its window
+        let next_frame = self.last_frame + self.frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        }
+        self.last_frame = Instant::now();
+    }
+
+    fn set_target_fps(&mut self, fps: usize) {
+        self.frame_time = if fps == 0 { Duration::ZERO } else { Duration::from_secs(1) / fps as u32 };
+    }
+}
+
+// Start a thread per input device that forwards its key events; devices that can't be read are skipped
+fn open_keyboards() -> Receiver<(u16, i32)> {
+    let (sender, receiver) = mpsc::channel();
+    let devices = fs::read_dir("/dev/input")
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut opened = 0;
+    for path in devices {
+        if !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("event")) {
+            continue;
+        }
+        let Ok(mut device) = File::open(&path) else { continue };
+        opened += 1;
+
+        let sender = sender.clone();
+        thread::spawn(move || {
+            // struct input_event: a timeval (two longs), then u16 type, u16 code and i32 value
+            let time_size = 2 * std::mem::size_of::<c_long>();
+            let mut event = vec![0; time_size + 8];
+            while device.read_exact(&mut event).is_ok() {
+                let field = &event[time_size..];
+                let kind = u16::from_ne_bytes([field[0], field[1]]);
+                let code = u16::from_ne_bytes([field[2], field[3]]);
+                let value = i32::from_ne_bytes([field[4], field[5], field[6], field[7]]);
+                if kind == EV_KEY && sender.send((code, value)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    if opened == 0 {
+        println!("Warning: couldn't open any input devices in /dev/input; check you're in the `input` group");
+    }
+    receiver
+}
+
+// The keys the emulator uses, by Linux key code (see linux/input-event-codes.h)
+fn key_from_code(code: u16) -> Option<Key> {
+    let key = match code {
+        1 => Key::Escape,
+        2 => Key::Key1,
+        3 => Key::Key2,
+        4 => Key::Key3,
+        5 => Key::Key4,
+        6 => Key::Key5,
+        7 => Key::Key6,
+        8 => Key::Key7,
+        9 => Key::Key8,
+        10 => Key::Key9,
+        11 => Key::Key0,
+        12 => Key::Minus,
+        13 => Key::Equal,
+        14 => Key::Backspace,
+        15 => Key::Tab,
+        16 => Key::Q,
+        17 => Key::W,
+        18 => Key::E,
+        19 => Key::R,
+        20 => Key::T,
+        21 => Key::Y,
+        22 => Key::U,
+        23 => Key::I,
+        24 => Key::O,
+        25 => Key::P,
+        26 => Key::LeftBracket,
+        27 => Key::RightBracket,
+        28 => Key::Enter,
+        29 => Key::LeftCtrl,
+        30 => Key::A,
+        31 => Key::S,
+        32 => Key::D,
+        33 => Key::F,
+        34 => Key::G,
+        35 => Key::H,
+        36 => Key::J,
+        37 => Key::K,
+        38 => Key::L,
+        42 => Key::LeftShift,
+        44 => Key::Z,
+        45 => Key::X,
+        46 => Key::C,
+        47 => Key::V,
+        48 => Key::B,
+        49 => Key::N,
+        50 => Key::M,
+        54 => Key::RightShift,
+        56 => Key::LeftAlt,
+        57 => Key::Space,
+        59 => Key::F1,
+        60 => Key::F2,
+        61 => Key::F3,
+        62 => Key::F4,
+        63 => Key::F5,
+        64 => Key::F6,
+        65 => Key::F7,
+        66 => Key::F8,
+        67 => Key::F9,
+        68 => Key::F10,
+        71 => Key::NumPad7,
+        72 => Key::NumPad8,
+        73 => Key::NumPad9,
+        74 => Key::NumPadMinus,
+        75 => Key::NumPad4,
+        76 => Key::NumPad5,
+        77 => Key::NumPad6,
+        78 => Key::NumPadPlus,
+        79 => Key::NumPad1,
+        80 => Key::NumPad2,
+        81 => Key::NumPad3,
+        82 => Key::NumPad0,
+        83 => Key::NumPadDot,
+        87 => Key::F11,
+        88 => Key::F12,
+        96 => Key::NumPadEnter,
+        97 => Key::RightCtrl,
+        100 => Key::RightAlt,
+        102 => Key::Home,
+        103 => Key::Up,
+        104 => Key::PageUp,
+        105 => Key::Left,
+        106 => Key::Right,
+        107 => Key::End,
+        108 => Key::Down,
+        109 => Key::PageDown,
+        110 => Key::Insert,
+        111 => Key::Delete,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -0,0 +1,128 @@
+// Frame output for capture tools: every presented frame is written to a file or named pipe (--frame-output <path>)
+// so another process, e.g. an OBS source plugin, can pick it up without grabbing the screen.
+//
+// Each frame is a 32-byte header followed by the pixels, all little-endian:
+//
+//   "C8FRAME\0"   magic (8 bytes)
+//   u32 version   1
+//   u32 width     in pixels, as presented (after any rotation)
+//   u32 height
+//   u32 format    0 = XRGB8888: one u32 per pixel, 0x00RRGGBB, rows top to bottom
+//   u64 sequence  see below
+//   pixels        width * height * 4 bytes
+//
+// If the path is a named pipe (create it with `mkfifo` before starting the emulator), frames are streamed into it
+// one after another, with the sequence counting every frame presented. Frames are dropped rather than holding up
+// the game if the reader falls behind (which shows up as a gap in the sequence), and nothing is written until a
+// reader opens the pipe.
+//
+// Otherwise the path is a file that always holds the latest frame, overwritten in place; put it under /dev/shm on
+// Linux and it's a shared-memory region that readers can map. The sequence works like a seqlock: it's odd while
+// a frame is being written and even once it's complete, so a reader copies the pixels only between two reads of
+// the same even sequence (the frame number is sequence / 2).
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{FileExt, FileTypeExt};
+#[cfg(windows)]
+use std::os::windows::fs::FileExt;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+const MAGIC: &[u8; 8] = b"C8FRAME\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 32;
+
+pub enum FrameOutput {
+    Shared { file: File, sequence: u64, frame: Vec<u8> }, // Latest frame overwritten in place
+    Pipe { frames: SyncSender<Vec<u8>>, sequence: u64 },  // Frames handed to a thread that writes them to the pipe
+}
+
+impl FrameOutput {
+    pub fn open(path: &str) -> Result<Self, String> {
+        #[cfg(unix)]
+        let is_pipe = std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo());
+        #[cfg(not(unix))]
+        let is_pipe = path.starts_with(r"\\.\pipe\");
+
+        if is_pipe {
+            // Opening a pipe waits for a reader, so that happens on the writer thread too
+            let (frames, receiver) = mpsc::sync_channel::<Vec<u8>>(1);
+            let path = path.to_string();
+            thread::spawn(move || {
+                let mut pipe = match OpenOptions::new().write(true).open(&path) {
+                    Ok(pipe) => pipe,
+                    Err(e) => return println!("Error opening {}: {}", path, e),
+                };
+                for frame in receiver {
+                    if let Err(e) = pipe.write_all(&frame) {
+                        return println!("Stopped writing frames to {}: {}", path, e);
+                    }
+                }
+            });
+            Ok(FrameOutput::Pipe { frames, sequence: 0 })
+        } else {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .map_err(|e| format!("Error opening {}: {}", path, e))?;
+            Ok(FrameOutput::Shared { file, sequence: 0, frame: Vec::new() })
+        }
+    }
+
+    pub fn write(&mut self, pixels: &[u32], width: usize, height: usize) {
+        match self {
+            FrameOutput::Shared { file, sequence, frame } => {
+                // Mark the frame as being written, write it, then mark it complete
+                *sequence += 1;
+                encode(frame, pixels, width, height, *sequence);
+                let written = write_at(file, &sequence.to_le_bytes(), 24)
+                    .and_then(|()| write_at(file, &frame[..24], 0))
+                    .and_then(|()| write_at(file, &frame[HEADER_SIZE..], HEADER_SIZE as u64))
+                    .and_then(|()| {
+                        *sequence += 1;
+                        write_at(file, &sequence.to_le_bytes(), 24)
+                    });
+                if let Err(e) = written {
+                    eprintln!("Error writing frame: {}", e);
+                }
+            }
+            FrameOutput::Pipe { frames, sequence } => {
+                let mut frame = Vec::new();
+                encode(&mut frame, pixels, width, height, *sequence);
+                // A full channel means the reader is behind, so this frame is skipped
+                let _ = frames.try_send(frame);
+                *sequence += 1;
+            }
+        }
+    }
+}
+
+fn encode(frame: &mut Vec<u8>, pixels: &[u32], width: usize, height: usize, sequence: u64) {
+    frame.clear();
+    frame.extend_from_slice(MAGIC);
+    for field in [VERSION, width as u32, height as u32, 0] {
+        frame.extend_from_slice(&field.to_le_bytes());
+    }
+    frame.extend_from_slice(&sequence.to_le_bytes());
+    for pixel in pixels {
+        frame.extend_from_slice(&(pixel & 0xFFFFFF).to_le_bytes());
+    }
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, bytes: &[u8], offset: u64) -> std::io::Result<()> {
+    file.write_all_at(bytes, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &File, mut bytes: &[u8], mut offset: u64) -> std::io::Result<()> {
+    while !bytes.is_empty() {
+        let written = file.seek_write(bytes, offset)?;
+        bytes = &bytes[written..];
+        offset += written as u64;
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// Fault injection (--fuzz-bitflips N): flips random bits of RAM while the program runs, N per second of emulated
+// time on average, and reports the faults that follow (see Fault in chip8.rs). Handy for seeing how robust a ROM
+// (or the emulator) is against corrupted memory.
+//
+// Only the program area (0x200 onwards) is hit, since that holds the loaded ROM and everything it works on.
+use chip8::{Chip8, Fault};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub struct BitFlipper {
+    flips_per_second: u32,
+    rng: StdRng,
+    seed: u64,                      // Printed so that a run can be repeated
+    last_flip: Option<(u64, u16, u8)>, // Tick, address and bit of the most recent flip
+    flips: u64,
+    faults: Vec<(u64, Fault)>,      // Every fault seen, with the tick it happened on
+}
+
+impl BitFlipper {
+    pub fn new(flips_per_second: u32, seed: u64) -> Self {
+        status!("Fuzzing with {} bit flips per second (seed {})", flips_per_second, seed);
+        BitFlipper {
+            flips_per_second,
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            last_flip: None,
+            flips: 0,
+            faults: Vec::new(),
+        }
+    }
+
+    // Call once per 60Hz tick; returns the address and new value of each byte flipped, for the rewind log and
+    // the block cache
+    pub fn tick(&mut self, chip8: &mut Chip8, tick: u64) -> Vec<(u16, u8)> {
+        let mut flipped = Vec::new();
+        for _ in 0..self.flips_per_second {
+            if !self.rng.random_ratio(1, 60) {
+                continue;
+            }
+
+            let addr = self.rng.random_range(0x200..0x1000);
+            let bit = self.rng.random_range(0..8);
+            let value = chip8.peek(addr) ^ (1 << bit);
+            chip8.poke(addr, value);
+            flipped.push((addr, value));
+            self.last_flip = Some((tick, addr, bit));
+            self.flips += 1;
+        }
+        flipped
+    }
+
+    // Report a fault the program ran into
+    pub fn observe(&mut self, tick: u64, fault: Fault) {
+        match self.last_flip {
+            Some((flip_tick, addr, bit)) => println!(
+                "Tick {}: {} ({} ticks after flipping bit {} of {:03X})",
+                tick,
+                fault,
+                tick - flip_tick,
+                bit,
+                addr
+            ),
+            None => println!("Tick {}: {} (before any bit flips)", tick, fault),
+        }
+        self.faults.push((tick, fault));
+    }
+
+    // Summary of the run, for the end
+    pub fn report(&self) {
+        println!("\nFuzzing summary (seed {}): {} bits flipped, {} faults", self.seed, self.flips, self.faults.len());
+
+        // Count the faults of each kind, in the order they first turned up
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for (_, fault) in &self.faults {
+            match counts.iter_mut().find(|(kind, _)| *kind == fault.kind()) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((fault.kind(), 1)),
+            }
+        }
+        for (kind, count) in counts {
+            println!("  {:>6} {}", count, kind);
+        }
+        if let Some((tick, _)) = self.faults.first() {
+            println!("  First fault at tick {} ({:.1} seconds in)", tick, *tick as f32 / 60.0);
+        }
+    }
+}
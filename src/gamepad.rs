@@ -0,0 +1,171 @@
+// Gamepad backend (enabled with `cargo run --features gamepad`)
+//
+// Buttons and the D-pad map straight onto keypad keys, and the left analog stick is turned into
+// presses of the keypad's direction keys (2/4/6/8 by default) once it is pushed far enough:
+//
+//   [gamepad]
+//   dead_zone = 0.25      # Stick positions closer to the center than this are ignored entirely
+//   axis_threshold = 0.5  # How far along an axis the stick must be pushed to press that direction
+//   stick_up = "2"        # Keypad keys for each stick direction
+//   button_south = "5"    # Keypad keys for face buttons (south/east/west/north) and the D-pad (dpad_up, ...)
+//   rumble_sound = 0.5    # Rumble strength (0 to 1) while the sound timer runs; 0 (the default) turns it off
+//   rumble_collision = 0  # Strength of a short jolt whenever a sprite collides (DXYN sets VF); also off by default
+//
+// Many games redraw their sprites by erasing and drawing them again, which counts as a collision every frame,
+// so collision rumble works best in games that only use VF for actual hits.
+use crate::config::Config;
+use crate::interface::keypad_key_from_name;
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+const RUMBLE_MS: u32 = 50; // Length of the sound effect, which is repeated for as long as the sound timer runs
+const COLLISION_MS: u32 = 80; // Length of the jolt on a collision
+
+pub struct Gamepad {
+    gilrs: Gilrs,
+    pub dead_zone: f32,               // Radial dead zone around the stick's center
+    pub axis_threshold: f32,          // Axis value at which a stick direction counts as pressed
+    pub stick_keys: [usize; 4],       // Keypad keys for stick up, down, left and right
+    pub buttons: Vec<(Button, usize)>, // Keypad key pressed by each button
+    pub rumble_sound: f32,            // Rumble strength while the sound timer runs (0 = off)
+    pub rumble_collision: f32,        // Rumble strength on sprite collisions (0 = off)
+    effects: Option<(Effect, Effect)>, // Sound and collision rumble for the connected gamepads that support it
+    connections_changed: bool,        // A gamepad was connected or disconnected since the effects were made
+    sound_rumbling: bool,             // Whether the sound effect is playing
+}
+
+impl Gamepad {
+    // Returns None (with a warning) if no gamepad support is available on this system
+    pub fn new(config: &Config) -> Option<Self> {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(e) => {
+                eprintln!("Gamepad support unavailable: {}", e);
+                return None;
+            }
+        };
+
+        let keypad_key = |key: &str, default: usize| {
+            config
+                .get("gamepad", key)
+                .and_then(keypad_key_from_name)
+                .unwrap_or(default)
+        };
+
+        Some(Gamepad {
+            gilrs,
+            dead_zone: config.get_or("gamepad", "dead_zone", 0.25),
+            axis_threshold: config.get_or("gamepad", "axis_threshold", 0.5),
+            stick_keys: [
+                keypad_key("stick_up", 0x2),
+                keypad_key("stick_down", 0x8),
+                keypad_key("stick_left", 0x4),
+                keypad_key("stick_right", 0x6),
+            ],
+            buttons: vec![
+                (Button::DPadUp, keypad_key("dpad_up", 0x2)),
+                (Button::DPadDown, keypad_key("dpad_down", 0x8)),
+                (Button::DPadLeft, keypad_key("dpad_left", 0x4)),
+                (Button::DPadRight, keypad_key("dpad_right", 0x6)),
+                (Button::South, keypad_key("button_south", 0x5)),
+                (Button::East, keypad_key("button_east", 0x6)),
+                (Button::West, keypad_key("button_west", 0x4)),
+                (Button::North, keypad_key("button_north", 0x2)),
+            ],
+            rumble_sound: config.get_or("gamepad", "rumble_sound", 0.0),
+            rumble_collision: config.get_or("gamepad", "rumble_collision", 0.0),
+            effects: None,
+            connections_changed: true,
+            sound_rumbling: false,
+        })
+    }
+
+    // Mark the keypad keys held on any connected gamepad
+    pub fn poll(&mut self, held: &mut [bool; 16]) {
+        // Drain pending events so gilrs updates each gamepad's cached state
+        while let Some(event) = self.gilrs.next_event() {
+            if matches!(event.event, EventType::Connected | EventType::Disconnected) {
+                self.connections_changed = true;
+            }
+        }
+
+        for (_, gamepad) in self.gilrs.gamepads() {
+            for &(button, key) in &self.buttons {
+                if gamepad.is_pressed(button) {
+                    held[key] = true;
+                }
+            }
+
+            // gilrs reports up as positive Y
+            let x = gamepad.value(Axis::LeftStickX);
+            let y = gamepad.value(Axis::LeftStickY);
+            if (x * x + y * y).sqrt() < self.dead_zone {
+                continue;
+            }
+
+            let [up, down, left, right] = self.stick_keys;
+            held[up] |= y >= self.axis_threshold;
+            held[down] |= y <= -self.axis_threshold;
+            held[left] |= x <= -self.axis_threshold;
+            held[right] |= x >= self.axis_threshold;
+        }
+    }
+
+    // Rumble for the sound timer and for collisions; call once per 60Hz tick
+    pub fn rumble(&mut self, beeping: bool, collided: bool) {
+        if self.rumble_sound <= 0.0 && self.rumble_collision <= 0.0 {
+            return;
+        }
+        if self.connections_changed {
+            self.connections_changed = false;
+            self.sound_rumbling = false;
+            self.effects = self.make_effects();
+        }
+        let Some((sound, collision)) = &self.effects else {
+            return;
+        };
+
+        // Playing fails if a gamepad has just gone away, which the next poll will notice
+        if self.rumble_sound > 0.0 && beeping != self.sound_rumbling {
+            let _ = if beeping { sound.play() } else { sound.stop() };
+            self.sound_rumbling = beeping;
+        }
+        if self.rumble_collision > 0.0 && collided {
+            let _ = collision.play();
+        }
+    }
+
+    // Effects that play on every connected gamepad with force feedback, or None if there aren't any
+    fn make_effects(&mut self) -> Option<(Effect, Effect)> {
+        let ids: Vec<GamepadId> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if ids.is_empty() {
+            return None;
+        }
+
+        let mut effect = |strength: f32, length: u32, repeat: Repeat| {
+            let magnitude = (strength.clamp(0.0, 1.0) * u16::MAX as f32) as u16;
+            EffectBuilder::new()
+                .add_effect(BaseEffect {
+                    kind: BaseEffectType::Strong { magnitude },
+                    scheduling: Replay {
+                        play_for: Ticks::from_ms(length),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .gamepads(&ids)
+                .repeat(repeat)
+                .finish(&mut self.gilrs)
+                .map_err(|e| eprintln!("Couldn't set up rumble: {}", e))
+                .ok()
+        };
+        let sound = effect(self.rumble_sound, RUMBLE_MS, Repeat::Infinitely)?;
+        let collision = effect(self.rumble_collision, COLLISION_MS, Repeat::For(Ticks::from_ms(COLLISION_MS)))?;
+        Some((sound, collision))
+    }
+}
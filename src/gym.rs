@@ -0,0 +1,113 @@
+// Gym-style environment for training agents on CHIP-8 games (reinforcement learning and the like)
+//
+// Each step holds down the given keypad keys for one 60Hz frame, runs that frame's worth of instructions and
+// returns the display. There is no window or sound, so it runs as fast as the host allows, and given the same
+// ROM, seed and keys a run always plays out exactly the same:
+//
+//   let mut env = Env::new(&rom, 500, 42)?;
+//   env.done_when = Some(Expr::parse("mem[0x3A0] == 0")?); // e.g. out of lives
+//   let mut display = env.reset();
+//   loop {
+//       let (next, done) = env.step(agent.choose_keys(&display));
+//       ...
+//   }
+//
+// See examples/random_agent.rs for a complete (if not very smart) agent.
+use crate::expr::Expr;
+use crate::quirks::Quirks;
+use crate::Chip8;
+use alloc::vec::Vec;
+
+pub struct Env {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    seed: u64,                   // Seed for CXNN's random numbers, applied again on every reset
+    cpu_hz: u32,                 // Instructions per second of emulated time
+    cycle_units: u32,            // Leftover fraction of a cycle in 1/60ths (the same scheme as the tick loop in main.rs)
+    steps: u64,                  // Steps taken since the last reset
+    pub max_steps: Option<u64>,  // End the episode after this many steps
+    pub done_when: Option<Expr>, // End the episode once this condition holds (see expr.rs)
+}
+
+impl Env {
+    // Fails if the ROM doesn't fit in memory
+    pub fn new(rom: &[u8], cpu_hz: u32, seed: u64) -> Result<Self, &'static str> {
+        let mut env = Env {
+            chip8: Chip8::new(),
+            rom: rom.to_vec(),
+            seed,
+            cpu_hz,
+            cycle_units: 0,
+            steps: 0,
+            max_steps: None,
+            done_when: None,
+        };
+        env.chip8.load_bytes(rom)?;
+        env.reset();
+        Ok(env)
+    }
+
+    // Quirks stay in effect across resets
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.chip8.quirks = quirks;
+    }
+
+    // Start a new episode from power-on, returning the first observation
+    pub fn reset(&mut self) -> [u64; 32] {
+        self.chip8.reset();
+        self.chip8.load_bytes(&self.rom).expect("new checked that the ROM fits");
+        self.chip8.seed_rng(self.seed);
+        self.chip8.keypad = [false; 16];
+        self.cycle_units = 0;
+        self.steps = 0;
+        self.display()
+    }
+
+    // Run one frame with `keys` held, returning the display (one u64 per row, leftmost pixel in the highest bit)
+    // and whether the episode is over
+    pub fn step(&mut self, keys: [bool; 16]) -> ([u64; 32], bool) {
+        self.chip8.keypad = keys;
+
+        self.cycle_units += self.cpu_hz;
+        for _ in 0..self.cycle_units / 60 {
+            self.chip8.emulate_cycle();
+        }
+        self.cycle_units %= 60;
+        self.chip8.update_timers();
+        self.steps += 1;
+
+        (self.display(), self.is_done())
+    }
+
+    // The display as reset or step last returned it; an Env's machine always has the 64x32 display
+    pub fn display(&self) -> [u64; 32] {
+        let mut display = [0; 32];
+        display.copy_from_slice(&self.chip8.display[..32]);
+        display
+    }
+
+    // The machine itself, e.g. for reading the score out of memory to compute a reward
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn is_done(&self) -> bool {
+        // Most programs end by jumping to themselves forever
+        let pc = self.chip8.pc();
+        let opcode = (self.chip8.peek(pc) as u16) << 8 | self.chip8.peek(pc + 1) as u16;
+        let halted = opcode == 0x1000 | pc;
+
+        halted
+            || self.max_steps.is_some_and(|max_steps| self.steps >= max_steps)
+            || self.done_when.as_ref().is_some_and(|condition| condition.is_true(&self.chip8))
+    }
+}
+
+// Whether the pixel at (x, y) is on in a display returned by reset or step
+pub fn pixel(display: &[u64; 32], x: usize, y: usize) -> bool {
+    display[y] & (0x8000_0000_0000_0000 >> x) != 0
+}
@@ -0,0 +1,106 @@
+// Memory heat map debug view (enabled with --heatmap)
+//
+// Every byte of memory gets a pixel in a 64x64 grid (row = address / 64), colored by how recently and how often
+// it has been written (red), read (green) or executed (blue). Each access brightens the pixel and it fades
+// over the following frames, so hot loops show up as steady blue and data being shuffled around flickers.
+#[cfg(feature = "std")]
+use minifb::{Scale, Window, WindowOptions};
+
+#[cfg(feature = "std")]
+const SIZE: usize = 64; // 64x64 = 4096 bytes
+const ACCESS_HEAT: u8 = 96; // Brightness added by each access; repeated accesses saturate at full brightness
+const FADE: u16 = 230; // Heat kept each frame, out of 256 (fades to black in about a second)
+
+pub struct HeatMap {
+    reads: [u8; 4096],
+    writes: [u8; 4096],
+    executes: [u8; 4096],
+}
+
+impl Default for HeatMap {
+    fn default() -> Self {
+        HeatMap::new()
+    }
+}
+
+impl HeatMap {
+    pub fn new() -> Self {
+        HeatMap {
+            reads: [0; 4096],
+            writes: [0; 4096],
+            executes: [0; 4096],
+        }
+    }
+
+    pub fn read(&mut self, addr: usize) {
+        self.reads[addr] = self.reads[addr].saturating_add(ACCESS_HEAT);
+    }
+
+    pub fn write(&mut self, addr: usize) {
+        self.writes[addr] = self.writes[addr].saturating_add(ACCESS_HEAT);
+    }
+
+    pub fn execute(&mut self, addr: usize) {
+        self.executes[addr] = self.executes[addr].saturating_add(ACCESS_HEAT);
+    }
+
+    // Cool every byte down by one frame's worth
+    pub fn fade(&mut self) {
+        for heat in self.reads.iter_mut().chain(&mut self.writes).chain(&mut self.executes) {
+            *heat = ((*heat as u16 * FADE) >> 8) as u8;
+        }
+    }
+}
+
+// Secondary window the heat map is drawn into
+#[cfg(feature = "std")]
+pub struct HeatMapView {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+#[cfg(feature = "std")]
+impl Default for HeatMapView {
+    fn default() -> Self {
+        HeatMapView::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl HeatMapView {
+    pub fn new() -> Self {
+        let window = Window::new(
+            "Chip-8 Memory Heat Map",
+            SIZE,
+            SIZE,
+            WindowOptions {
+                scale: Scale::X8,
+                ..WindowOptions::default()
+            },
+        )
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+
+        HeatMapView {
+            window,
+            buffer: vec![0; SIZE * SIZE],
+        }
+    }
+
+    // Draw the current heat and then fade it for the next frame
+    pub fn render(&mut self, heat_map: &mut HeatMap) {
+        for (addr, pixel) in self.buffer.iter_mut().enumerate() {
+            let red = heat_map.writes[addr] as u32;
+            let green = heat_map.reads[addr] as u32;
+            let blue = heat_map.executes[addr] as u32;
+            *pixel = (red << 16) | (green << 8) | blue;
+        }
+        heat_map.fade();
+
+        // Closing the heat map window just stops it updating; the emulator keeps running
+        if self.window.is_open() {
+            self.window.update_with_buffer(&self.buffer, SIZE, SIZE).unwrap();
+        }
+    }
+}
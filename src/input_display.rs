@@ -0,0 +1,62 @@
+// Input display (F5, or --input-display): the keypad in the bottom-left corner with the keys the game sees as held
+// lit up, and a strip of the most recent presses next to it, newest on the right, for streams and tutorial videos.
+// It shows the keypad as the CHIP-8 sees it, so replays, macros and gamepads show up the same as the keyboard.
+use crate::input_test::LAYOUT;
+use crate::overlay::{Canvas, CHAR_HEIGHT, CHAR_WIDTH};
+use std::collections::VecDeque;
+
+const PRESSES: usize = 8; // Presses shown in the strip
+
+pub struct InputDisplay {
+    held: [bool; 16],      // Keys held as of the last tick
+    presses: VecDeque<u8>, // Most recent presses, oldest first
+}
+
+impl InputDisplay {
+    pub fn new() -> Self {
+        InputDisplay { held: [false; 16], presses: VecDeque::with_capacity(PRESSES) }
+    }
+
+    // Note the keypad for a tick, adding any key that has just gone down to the strip
+    pub fn record(&mut self, keypad: &[bool; 16]) {
+        for key in (0..16).filter(|&key| keypad[key] && !self.held[key]) {
+            if self.presses.len() == PRESSES {
+                self.presses.pop_front();
+            }
+            self.presses.push_back(key as u8);
+        }
+        self.held = *keypad;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let scale = canvas.text_scale;
+        let padding = scale * 2;
+        let cell = (CHAR_HEIGHT + 2) * scale; // Each key is a square with its digit in the middle
+        let keypad_size = cell * 4;
+        let strip_width = PRESSES * CHAR_WIDTH * scale * 2;
+        let width = keypad_size + strip_width + padding * 3;
+        let height = keypad_size + padding * 2;
+        let top = canvas.height.saturating_sub(height);
+
+        canvas.fill_rect(0, top, width, height, 0x202020);
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let x = padding + column * cell;
+                let y = top + padding + row * cell;
+                let (background, foreground) = if self.held[key] { (0xFFFFFF, 0x000000) } else { (0x404040, 0xC0C0C0) };
+                canvas.fill_rect(x, y, cell - scale, cell - scale, background);
+                canvas.draw_text(x + scale * 2, y + scale, &format!("{:X}", key), foreground);
+            }
+        }
+
+        // The strip fades from the newest press back to the oldest, and sits level with the keypad's bottom row
+        let strip_left = keypad_size + padding * 2;
+        let strip_top = top + padding + cell * 3 + scale;
+        let newest = self.presses.len().saturating_sub(1);
+        for (index, &key) in self.presses.iter().enumerate() {
+            let brightness = 0xFF - (newest - index) as u32 * 0x18;
+            let color = brightness << 16 | brightness << 8 | brightness;
+            canvas.draw_text(strip_left + index * CHAR_WIDTH * scale * 2, strip_top, &format!("{:X}", key), color);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Keypad test screen (--input-test): shows the 16 keypad keys laid out like the real keypad and lights each
+// one up while it is held, so key mappings, input options and controllers can be checked without a game.
+// Everything the emulator applies to input (gamepads, sticky keys, macros, ...) applies here too.
+use crate::interface::Interface;
+use minifb::Key;
+
+// Keypad keys in the order they appear on the original keypad
+pub const LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+pub fn run(interface: &mut Interface) {
+    status!("Keypad test ({} keymap): press keys to light them up; Escape quits", interface.keymap.name);
+
+    // The host keys bound to each keypad key, as minifb names them but with number keys as plain digits
+    let host_keys: [String; 16] = std::array::from_fn(|key| {
+        let names = interface.keymap.bindings(key).iter().map(|host_key| format!("{:?}", host_key));
+        let names: Vec<String> = names.map(|name| name.strip_prefix("Key").unwrap_or(&name).to_string()).collect();
+        names.join(" ")
+    });
+
+    while interface.window.is_open() && !interface.window.is_key_down(Key::Escape) {
+        interface.process_keys();
+        let keypad = interface.keypad;
+
+        let mut canvas = interface.canvas();
+        let (width, height, scale) = (canvas.width, canvas.height, canvas.text_scale);
+        canvas.fill_rect(0, 0, width, height, 0);
+        canvas.draw_text(scale * 2, scale * 2, "KEYPAD TEST - ESCAPE TO QUIT", 0x808080);
+
+        // A 4x4 grid of cells below the title, each with the keypad key in big digits and its host key underneath
+        let top = scale * 10;
+        let cell_width = width / 4;
+        let cell_height = (height - top) / 4;
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let x = column * cell_width;
+                let y = top + row * cell_height;
+                let (background, foreground) = if keypad[key] { (0xFFFFFF, 0x000000) } else { (0x303030, 0xFFFFFF) };
+                canvas.fill_rect(x + scale, y + scale, cell_width - scale * 2, cell_height - scale * 2, background);
+
+                canvas.text_scale = scale * 2;
+                canvas.draw_text(x + scale * 4, y + scale * 4, &format!("{:X}", key), foreground);
+                canvas.text_scale = scale;
+
+                canvas.draw_text(x + scale * 4, y + cell_height - scale * 10, &host_keys[key], 0x808080);
+            }
+        }
+
+        interface.present();
+    }
+}
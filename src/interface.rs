@@ -1,60 +1,393 @@
+use crate::backend::{Backend, BackendKind};
+#[cfg(target_os = "linux")]
+use crate::fbdev::Framebuffer;
+use crate::frame_output::FrameOutput;
+use crate::keymap::Keymap;
+use crate::overlay::{Canvas, CHAR_HEIGHT};
+use crate::palette::{blend, mix, threshold, Palette, PALETTES};
+use crate::savestate::{SLOTS, THUMBNAIL_WIDTH};
+use crate::scaler::Scaling;
+use chip8::MAX_DISPLAY_HEIGHT;
 use minifb::{Key, Scale, Window, WindowOptions};
-use rodio::{OutputStream, source::{SineWave, Source}};
-use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "gamepad")]
+use crate::gamepad::Gamepad;
 
 pub struct Interface {
-    pub window: Window,
-    pub framebuffer: [u32; 64 * 32], // Chip-8 resolution is 64x32
+    pub window: Box<dyn Backend>, // The window, or the whole screen with the framebuffer backend
+    pub framebuffer: Vec<u32>, // 64 x rows Chip-8 pixels, drawn with pixel_size x pixel_size pixels per Chip-8 pixel
+    pixel_size: usize,         // Framebuffer pixels per Chip-8 pixel, so overlays can be drawn in finer detail
+    rows: usize,               // Rows of Chip-8 pixels in the framebuffer: 32, or 48 for the ETI-660
     pub keypad: [bool; 16],
-    pub muted: bool,
-    pub debug_mode: bool, // Debug mode to print additional information
-    sound_stream: Option<(OutputStream, rodio::OutputStreamHandle)>,
-    is_beeping: bool,
+    pub input: InputLayer,     // Accessibility options applied between the keyboard and the keypad
+    pub keymap: Keymap,        // Host keys that press each keypad key
+    #[cfg(feature = "gamepad")]
+    pub gamepad: Option<Gamepad>, // Connected controllers, if gamepad support is available
+    toast: Option<(String, u32)>, // Notification shown at the top of the screen and the frames it has left
+    pub palette: Palette,         // Colors the display is drawn in
+    pub outlines: bool,           // Leave a line of background along the right and bottom of each lit pixel
+    pub interpolate: bool,        // Blend each frame 50/50 with the one presented before it
+    pub phosphor: Option<Phosphor>, // Fade pixels out gradually after they go off, instead of interpolating
+    glow: Vec<f32>,               // Brightness of each Chip-8 pixel (0-1) for the phosphor effect
+    glowed_at: Option<Instant>,   // When the glow was last updated
+    shown: [u64; MAX_DISPLAY_HEIGHT], // Display drawn for the frame being put together
+    previous: [u64; MAX_DISPLAY_HEIGHT], // Display drawn for the last frame presented
+    scaling: Scaling,             // Aspect-ratio correction applied to the framebuffer
+    scaled: Vec<u32>,             // The framebuffer stretched, when it needs stretching
+    orientation: Orientation,     // Which way up the (stretched) framebuffer is shown
+    oriented: Vec<u32>,           // The framebuffer turned the right way, when it needs turning
+    pub frame_output: Option<FrameOutput>, // Where to copy each presented frame for capture tools, if anywhere
+    pub eink: bool,               // Black and white only, and only pushed to the screen when something changed
+    pushed: Vec<u32>,             // The last frame pushed to the screen in e-ink mode
+}
+
+// Window options for fitting the emulator into a desktop, stream overlay or multi-monitor setup (the framebuffer
+// backend has no window, so it ignores them)
+pub struct WindowSettings {
+    pub title: String,
+    pub borderless: bool,                 // No title bar or border
+    pub position: Option<(isize, isize)>, // Top-left corner on the desktop, rather than wherever the OS puts it
+    pub topmost: bool,                    // Stay on top of other windows
+}
+
+// Phosphor persistence, like the slow phosphor of an old CRT: a pixel lights up fully as soon as it goes on, but
+// fades out exponentially after it goes off. A longer decay hides more flicker, but smears moving sprites more
+#[derive(Clone, Copy)]
+pub struct Phosphor {
+    pub decay: f32,          // Time constant in seconds: a pixel is at about 37% brightness this long after going off
+    pub min_brightness: f32, // Brightness (0-1) below which a fading pixel is drawn as off, cutting faint trails short
+}
+
+// How the picture is turned on its way to the screen, e.g. for a screen mounted sideways or seen in a mirror
+#[derive(Clone, Copy, Default)]
+pub struct Orientation {
+    pub rotate: u32,    // Degrees clockwise: 0, 90, 180 or 270
+    pub mirror_x: bool, // Flip left to right (before rotating)
+    pub mirror_y: bool, // Flip upside down (before rotating)
+}
+
+impl Orientation {
+    pub fn is_upright(&self) -> bool {
+        self.rotate == 0 && !self.mirror_x && !self.mirror_y
+    }
+
+    // Size of a width x height picture once turned
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.rotate % 180 == 90 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    // Turn a width x height picture into `out`
+    fn apply(&self, pixels: &[u32], width: usize, height: usize, out: &mut Vec<u32>) {
+        out.resize(pixels.len(), 0);
+        let out_width = self.size(width, height).0;
+        for y in 0..height {
+            let my = if self.mirror_y { height - 1 - y } else { y };
+            for x in 0..width {
+                let mx = if self.mirror_x { width - 1 - x } else { x };
+                let (ox, oy) = match self.rotate {
+                    90 => (height - 1 - my, mx),
+                    180 => (width - 1 - mx, height - 1 - my),
+                    270 => (my, width - 1 - mx),
+                    _ => (mx, my),
+                };
+                out[oy * out_width + ox] = pixels[y * width + x];
+            }
+        }
+    }
 }
 
+// Host key for each keypad key (0 through F) in the standard keymap; see the layout above process_keys
+pub const KEYMAP: [Key; 16] = [
+    Key::X,    // 0
+    Key::Key1, // 1
+    Key::Key2, // 2
+    Key::Key3, // 3
+    Key::Q,    // 4
+    Key::W,    // 5
+    Key::E,    // 6
+    Key::A,    // 7
+    Key::S,    // 8
+    Key::D,    // 9
+    Key::Z,    // A
+    Key::C,    // B
+    Key::Key4, // C
+    Key::R,    // D
+    Key::F,    // E
+    Key::V,    // F
+];
+
+const TOAST_FRAMES: u32 = 180; // How long notifications stay up (3 seconds at 60 frames per second)
+
 impl Interface {
-    pub fn new(scale: Scale) -> Self {
-        // Create a new window with the specified options
-        let window: Window = Window::new(
-            "Chip-8 Emulator",
-            64,
-            32,
-            WindowOptions {
-                scale: scale,
-                ..WindowOptions::default()
-            },
-        )
-        .unwrap_or_else(|e| {
-            panic!("{}", e);
-        });
+    pub fn new(
+        scale: Scale,
+        backend: BackendKind,
+        scaling: Scaling,
+        orientation: Orientation,
+        settings: &WindowSettings,
+        rows: usize,
+    ) -> Self {
+        // Part of the scaling is done by drawing each Chip-8 pixel as a block of pixels ourselves (up to 8x8),
+        // which leaves room to draw overlay text, and the window scales up the rest
+        let (pixel_size, window_scale) = match scale {
+            Scale::X1 => (1, Scale::X1),
+            Scale::X2 => (2, Scale::X1),
+            Scale::X4 => (4, Scale::X1),
+            Scale::X8 => (8, Scale::X1),
+            Scale::X16 => (8, Scale::X2),
+            Scale::X32 => (8, Scale::X4),
+            other => (8, other),
+        };
 
-        // Initialize audio stream
-        let sound_stream = OutputStream::try_default().ok();
+        let (width, height) = scaling.size(64 * pixel_size, rows * pixel_size);
+        let (width, height) = orientation.size(width, height);
+        let window: Box<dyn Backend> = match backend {
+            // Create a new window with the specified options
+            BackendKind::Window => {
+                let mut window = Window::new(
+                    &settings.title,
+                    width,
+                    height,
+                    WindowOptions {
+                        scale: window_scale,
+                        borderless: settings.borderless,
+                        title: !settings.borderless,
+                        topmost: settings.topmost,
+                        ..WindowOptions::default()
+                    },
+                )
+                .unwrap_or_else(|e| {
+                    panic!("{}", e);
+                });
+                if let Some((x, y)) = settings.position {
+                    window.set_position(x, y);
+                }
+                Box::new(window)
+            }
+            // The framebuffer backend scales the frame to the screen itself
+            #[cfg(target_os = "linux")]
+            BackendKind::Framebuffer => Box::new(Framebuffer::open().unwrap_or_else(|e| {
+                panic!("{}", e);
+            })),
+            #[cfg(not(target_os = "linux"))]
+            BackendKind::Framebuffer => panic!("The framebuffer backend is only available on Linux"),
+        };
 
         Interface {
             window,
-            framebuffer: [0; 64 * 32], // Initialize screen with all pixels off (0 = black)
+            framebuffer: vec![0; 64 * rows * pixel_size * pixel_size], // Initialize screen with all pixels off (0 = black)
+            pixel_size,
+            rows,
             keypad: [false; 16],   // Initialize keypad with all keys unpressed
-            muted: false,
-            debug_mode: false,
-            sound_stream,
-            is_beeping: false,
+            input: InputLayer::new(),
+            keymap: Keymap::standard(),
+            #[cfg(feature = "gamepad")]
+            gamepad: None,
+            toast: None,
+            palette: PALETTES[0],
+            outlines: false,
+            interpolate: false,
+            phosphor: None,
+            glow: vec![0.0; 64 * rows],
+            glowed_at: None,
+            shown: [0; MAX_DISPLAY_HEIGHT],
+            previous: [0; MAX_DISPLAY_HEIGHT],
+            scaling,
+            scaled: Vec::new(),
+            orientation,
+            oriented: Vec::new(),
+            frame_output: None,
+            eink: false,
+            pushed: Vec::new(),
         }
     }
 
-    // Render screen by updating the window with the current framebuffer
-    pub fn render_screen(&mut self) {
-        let width = 64; // Original screen width
-        let height = 32; // Original screen height
+    // Unpack the display rows into the framebuffer; overlays can then be drawn on top before presenting.
+    // With interpolation on, pixels that changed since the last frame are drawn halfway between on and off, which
+    // smooths out the flicker and jerky movement of games that only redraw every few frames. The phosphor effect
+    // does the same more gradually, fading pixels by however much time has passed since the last frame.
+    // A display with a different number of rows (a game under another quirk profile) resizes the framebuffer
+    pub fn draw_display(&mut self, display: &[u64]) {
+        if display.len() != self.rows {
+            self.rows = display.len();
+            self.framebuffer.resize(64 * self.rows * self.pixel_size * self.pixel_size, 0);
+            self.glow.resize(64 * self.rows, 0.0);
+        }
+        let width = 64 * self.pixel_size; // Framebuffer width
+        let size = self.pixel_size;
+        let [background, foreground, ..] = self.palette.colors;
+        let color_of = |on: bool| if on { foreground } else { background };
+        let outlines = self.outlines && size >= 4; // Smaller pixels would be mostly outline
+        let fade = self.phosphor.map(|phosphor| {
+            // A long stall (e.g. dragging the window) fades no more than a tenth of a second's worth
+            let now = Instant::now();
+            let elapsed = self.glowed_at.replace(now).map_or(0.0, |then| (now - then).as_secs_f32().min(0.1));
+            ((-elapsed / phosphor.decay.max(0.001)).exp(), phosphor.min_brightness)
+        });
+
+        for (y, row) in display.iter().enumerate() {
+            // Draw the first line of this row of Chip-8 pixels, then copy it down for the rest of the block
+            let top = y * size * width;
+            for x in 0..64 {
+                let mask = 0x8000_0000_0000_0000 >> x;
+                let mut color = color_of(row & mask != 0);
+                if let Some((fade, min_brightness)) = fade {
+                    let glow = &mut self.glow[y * 64 + x];
+                    *glow = if row & mask != 0 { 1.0 } else { *glow * fade };
+                    if *glow < min_brightness {
+                        *glow = 0.0;
+                    }
+                    color = mix(background, foreground, *glow);
+                } else if self.interpolate {
+                    color = blend(color, color_of(self.previous[y] & mask != 0));
+                }
+                let block = &mut self.framebuffer[top + x * size..top + (x + 1) * size];
+                block.fill(color);
+                if outlines && row & mask != 0 {
+                    block[size - 1] = background;
+                }
+            }
+            for line in 1..size {
+                self.framebuffer.copy_within(top..top + width, top + line * width);
+            }
+            if outlines {
+                let bottom = top + (size - 1) * width;
+                for x in (0..64).filter(|x| row & (0x8000_0000_0000_0000 >> x) != 0) {
+                    self.framebuffer[bottom + x * size..bottom + (x + 1) * size].fill(background);
+                }
+            }
+        }
+        self.shown[..display.len()].copy_from_slice(display);
+    }
+
+    // Canvas for drawing overlays onto the framebuffer
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        Canvas {
+            pixels: &mut self.framebuffer,
+            width: 64 * self.pixel_size,
+            height: self.rows * self.pixel_size,
+            text_scale: (self.pixel_size / 4).max(1),
+        }
+    }
+
+    // Draw the history timeline along the bottom: the recorded span in grey and the selected point in red
+    pub fn draw_timeline(&mut self, position: usize, len: usize) {
+        let size = self.pixel_size;
+        let mut canvas = self.canvas();
+        let bottom = canvas.height - size;
+
+        canvas.fill_rect(0, bottom, canvas.width, size, 0x404040);
+        let marker = if len > 1 { position * (canvas.width - size) / (len - 1) } else { canvas.width - size };
+        canvas.fill_rect(marker, bottom, size, size, 0xFF0000);
+    }
+
+    // Draw the save slot picker: each slot's thumbnail in a 5x2 grid, with the selected slot outlined
+    pub fn draw_slot_picker(&mut self, thumbnails: &[Option<Vec<u32>>; SLOTS], selected: usize) {
+        let mut canvas = self.canvas();
+        let cell_width = canvas.width / 5;
+        let cell_height = canvas.height / 2;
+        let padding = canvas.text_scale * 2;
+        let label_height = CHAR_HEIGHT * canvas.text_scale + padding;
+        let image_width = cell_width - padding * 2;
+        let image_height = (image_width / 2).min(cell_height - label_height - padding * 2);
+
+        canvas.fill_rect(0, 0, canvas.width, canvas.height, 0x101010);
+        for (slot, thumbnail) in thumbnails.iter().enumerate() {
+            let left = slot % 5 * cell_width;
+            let top = slot / 5 * cell_height;
+            if slot == selected {
+                canvas.fill_rect(left, top, cell_width, cell_height, 0xFFD700);
+                canvas.fill_rect(left + 1, top + 1, cell_width - 2, cell_height - 2, 0x101010);
+            }
+
+            let image_top = top + padding + label_height;
+            match thumbnail {
+                Some(thumbnail) => {
+                    canvas.draw_text(left + padding, top + padding, &format!("SLOT {}", slot), 0xFFFFFF);
+                    canvas.draw_image(left + padding, image_top, image_width, image_height, thumbnail, THUMBNAIL_WIDTH);
+                }
+                None => {
+                    canvas.draw_text(left + padding, top + padding, &format!("SLOT {}", slot), 0x808080);
+                    canvas.fill_rect(left + padding, image_top, image_width, image_height, 0x303030);
+                }
+            }
+        }
+    }
+
+    // Shrink the framebuffer down to a thumbnail with one pixel per CHIP-8 pixel, overlays and all
+    pub fn thumbnail(&self) -> Vec<u32> {
+        let width = 64 * self.pixel_size;
+        let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * self.rows);
+        for y in 0..self.rows {
+            let line = &self.framebuffer[y * self.pixel_size * width..];
+            thumbnail.extend((0..THUMBNAIL_WIDTH).map(|x| line[x * self.pixel_size] & 0xFFFFFF));
+        }
+        thumbnail
+    }
+
+    // Show a short notification at the top of the screen for a few seconds
+    pub fn show_toast(&mut self, message: &str) {
+        self.toast = Some((message.to_string(), TOAST_FRAMES));
+    }
 
-        // Update the window with the screen buffer (no scaling)
+    // Update the window with the framebuffer, drawing any notification on top
+    pub fn present(&mut self) {
+        self.previous = self.shown;
+        let width = 64 * self.pixel_size;
+        let height = self.rows * self.pixel_size;
+
+        if let Some((message, frames_left)) = self.toast.take() {
+            self.canvas().draw_toast(&message, 0xFFD700);
+            if frames_left > 1 {
+                self.toast = Some((message, frames_left - 1));
+            }
+        }
+
+        // An e-ink screen shows black and white, overlays and all
+        if self.eink {
+            for pixel in &mut self.framebuffer {
+                *pixel = threshold(*pixel);
+            }
+        }
+
+        // Stretch and turn the picture as needed
+        let (mut buffer, mut width, mut height) = (&self.framebuffer, width, height);
+        if self.scaling.aspect.is_some() {
+            self.scaling.apply(buffer, width, height, &mut self.scaled);
+            (buffer, (width, height)) = (&self.scaled, self.scaling.size(width, height));
+        }
+        if !self.orientation.is_upright() {
+            self.orientation.apply(buffer, width, height, &mut self.oriented);
+            (buffer, (width, height)) = (&self.oriented, self.orientation.size(width, height));
+        }
+
+        // Every refresh of an e-ink screen is slow and flashes, so a frame the same as the last one isn't pushed;
+        // the window still needs updating for the keyboard, though
+        if self.eink {
+            if *buffer == self.pushed {
+                self.window.update();
+                return;
+            }
+            self.pushed.clone_from(buffer);
+        }
+        if let Some(frame_output) = &mut self.frame_output {
+            frame_output.write(buffer, width, height);
+        }
+
+        // Update the window with the screen buffer (the backend applies any remaining scaling)
         self.window
-            .update_with_buffer(&self.framebuffer, width, height)
+            .update_with_buffer(buffer, width, height)
             .unwrap();
     }
 
     // Process key press events;
     // Mapping: https://multigesture.net/articles/how-to-write-an-emulator-chip-8-interpreter/
+    // (this is the standard keymap; see keymap.rs for the others)
     // Keypad       Keyboard
     // +-+-+-+-+    +-+-+-+-+
     // |1|2|3|C|    |1|2|3|4|
@@ -66,57 +399,169 @@ impl Interface {
     // |A|0|B|F|    |Z|X|C|V|
     // +-+-+-+-+    +-+-+-+-+
     pub fn process_keys(&mut self) {
-        // Clear the current state of the keypad
-        self.keypad = [false; 16];
-
         let keys = self.window.get_keys();
 
-        // Update the keypad based on the pressed keys
-        for key in keys {
-            match key {
-                Key::Key1 => self.keypad[0x1] = true,
-                Key::Key2 => self.keypad[0x2] = true,
-                Key::Key3 => self.keypad[0x3] = true,
-                Key::Key4 => self.keypad[0xC] = true,
-                Key::Q => self.keypad[0x4] = true,
-                Key::W => self.keypad[0x5] = true,
-                Key::E => self.keypad[0x6] = true,
-                Key::R => self.keypad[0xD] = true,
-                Key::A => self.keypad[0x7] = true,
-                Key::S => self.keypad[0x8] = true,
-                Key::D => self.keypad[0x9] = true,
-                Key::F => self.keypad[0xE] = true,
-                Key::Z => self.keypad[0xA] = true,
-                Key::X => self.keypad[0x0] = true,
-                Key::C => self.keypad[0xB] = true,
-                Key::V => self.keypad[0xF] = true,
-                _ => (),
-            }
+        // Keys physically held down this frame, before the input layer gets to them
+        #[cfg_attr(not(feature = "gamepad"), allow(unused_mut))]
+        let mut held = self.keymap.held(&keys);
+
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.poll(&mut held);
+        }
+
+        self.keypad = self.input.apply(held, &keys);
+    }
+
+    // Override the keypad with keys from somewhere other than the keyboard (e.g. a replay being played back)
+    pub fn set_keypad(&mut self, keypad: [bool; 16]) {
+        self.keypad = keypad;
+    }
+}
+
+// Input-mapping layer between the physical keys and the keypad the CPU sees;
+// process_keys runs once per frame, so all timings here are in frames
+pub struct InputLayer {
+    pub sticky_keys: bool,    // A tap toggles a keypad key on/off instead of having to hold it
+    pub repeat_delay: u32,    // Frames a key is held before auto-repeat kicks in (0 = no auto-repeat)
+    pub repeat_interval: u32, // Frames between repeated presses once auto-repeat is active
+    pub macros: Vec<KeyMacro>, // Host keys bound to sequences of keypad presses
+    held_frames: [u32; 16],   // How many frames each key has been held for
+    toggled: [bool; 16],      // Current state of each key in sticky mode
+}
+
+impl InputLayer {
+    pub fn new() -> Self {
+        InputLayer {
+            sticky_keys: false,
+            repeat_delay: 0,
+            repeat_interval: 6,
+            macros: Vec::new(),
+            held_frames: [0; 16],
+            toggled: [false; 16],
         }
     }
 
+    fn apply(&mut self, held: [bool; 16], host_keys: &[Key]) -> [bool; 16] {
+        let mut keypad = [false; 16];
 
-    pub fn set_beep(&mut self, should_beep: bool) {
-        if self.muted {
-            return; // If muted, do not play sound
+        for key in 0..16 {
+            let just_pressed = held[key] && self.held_frames[key] == 0;
+            self.held_frames[key] = if held[key] { self.held_frames[key] + 1 } else { 0 };
+
+            keypad[key] = if self.sticky_keys {
+                // Toggle on the frame the key goes down and ignore how long it is held
+                if just_pressed {
+                    self.toggled[key] = !self.toggled[key];
+                }
+                self.toggled[key]
+            } else if held[key] && self.repeat_delay > 0 && self.held_frames[key] > self.repeat_delay {
+                // Auto-repeat: release the key for one frame every repeat_interval frames, so games
+                // that wait for a fresh press (e.g. with FX0A) see a steady stream of presses
+                !(self.held_frames[key] - self.repeat_delay).is_multiple_of(self.repeat_interval.max(1))
+            } else {
+                held[key]
+            };
         }
 
-        if should_beep == self.is_beeping {
-            return; // No change needed
+        // Macros are layered on top, so a turbo button still works alongside sticky keys/auto-repeat
+        for key_macro in &mut self.macros {
+            let step = key_macro.advance(host_keys.contains(&key_macro.host_key));
+            for (key, pressed) in keypad.iter_mut().enumerate() {
+                *pressed |= step & (1 << key) != 0;
+            }
         }
 
-        if let Some((_, stream_handle)) = &self.sound_stream {
-            if should_beep {
-                // Create a sine wave at 440Hz (standard A note)
-                let source = SineWave::new(440.0)
-                    .take_duration(Duration::from_secs(1))
-                    .amplify(0.10); // Reduce volume to 10%
-                
-                // Play the sound
-                let _ = stream_handle.play_raw(source.convert_samples());
+        keypad
+    }
+}
+
+// A host key bound to a looping sequence of keypad presses, one step per frame; e.g. binding
+// T to "5 -" makes holding T tap keypad key 5 every other frame (a rapid-fire/turbo button)
+pub struct KeyMacro {
+    host_key: Key,
+    steps: Vec<u16>, // Keypad keys held on each frame of the macro, as a bitmask
+    position: usize, // Next step to play while the host key is held
+}
+
+impl KeyMacro {
+    // Parse a macro from the [macros] config section, e.g. `T = "5 -"` or `G = "4+6 4+6 - -"`:
+    // each space-separated step lists the keypad keys (hex digits joined with +) held for one frame,
+    // and "-" is a frame with nothing pressed
+    pub fn parse(host_key: &str, definition: &str) -> Result<Self, String> {
+        let host_key = key_from_name(host_key).ok_or_else(|| format!("Unknown key '{}'", host_key))?;
+
+        let mut steps = Vec::new();
+        for step in definition.split_whitespace() {
+            let mut mask = 0;
+            if step != "-" {
+                for name in step.split('+') {
+                    let key = keypad_key_from_name(name)
+                        .ok_or_else(|| format!("'{}' is not a keypad key (0-F)", name))?;
+                    mask |= 1 << key;
+                }
             }
+            steps.push(mask);
         }
 
-        self.is_beeping = should_beep;
+        if steps.is_empty() {
+            return Err(format!("Macro for {:?} has no steps", host_key));
+        }
+
+        Ok(KeyMacro {
+            host_key,
+            steps,
+            position: 0,
+        })
+    }
+
+    // Returns the keypad keys to press this frame; the sequence restarts whenever the host key is released
+    fn advance(&mut self, host_key_held: bool) -> u16 {
+        if !host_key_held {
+            self.position = 0;
+            return 0;
+        }
+
+        let step = self.steps[self.position];
+        self.position = (self.position + 1) % self.steps.len();
+        step
     }
 }
+
+// Keypad keys are named by their hex digit ("0" to "F")
+pub fn keypad_key_from_name(name: &str) -> Option<usize> {
+    usize::from_str_radix(name, 16).ok().filter(|&key| key < 16)
+}
+
+// Every key minifb knows about, so config files can refer to keys by name
+const ALL_KEYS: [Key; 106] = [
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11,
+    Key::F12, Key::F13, Key::F14, Key::F15,
+    Key::Down, Key::Left, Key::Right, Key::Up, Key::Apostrophe, Key::Backquote, Key::Backslash, Key::Comma,
+    Key::Equal, Key::LeftBracket, Key::Minus, Key::Period, Key::RightBracket, Key::Semicolon, Key::Slash,
+    Key::Backspace, Key::Delete, Key::End, Key::Enter, Key::Escape, Key::Home, Key::Insert, Key::Menu,
+    Key::PageDown, Key::PageUp, Key::Pause, Key::Space, Key::Tab, Key::NumLock, Key::CapsLock, Key::ScrollLock,
+    Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl,
+    Key::NumPad0, Key::NumPad1, Key::NumPad2, Key::NumPad3, Key::NumPad4, Key::NumPad5, Key::NumPad6,
+    Key::NumPad7, Key::NumPad8, Key::NumPad9, Key::NumPadDot, Key::NumPadSlash, Key::NumPadAsterisk,
+    Key::NumPadMinus, Key::NumPadPlus, Key::NumPadEnter,
+    Key::LeftAlt, Key::RightAlt, Key::LeftSuper, Key::RightSuper,
+];
+
+// Look up a key by its minifb name (case-insensitive, e.g. "T", "Space", "NumPad5");
+// bare digits are accepted as shorthand for the number row ("5" => Key5)
+pub fn key_from_name(name: &str) -> Option<Key> {
+    let name = if name.len() == 1 && name.as_bytes()[0].is_ascii_digit() {
+        format!("Key{}", name)
+    } else {
+        name.to_string()
+    };
+
+    ALL_KEYS
+        .iter()
+        .copied()
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(&name))
+}
@@ -0,0 +1,92 @@
+// Keymap profiles: which host keys press which keypad keys. Besides the standard layout (the keypad laid over
+// 1234/QWER/ASDF/ZXCV, see Interface::process_keys), there is a preset for two players on one keyboard, and more
+// can be defined in the config file.
+//
+// The two-player preset splits the keypad down the middle, since that's how two-player games usually share it
+// (Pong, for one, has player 1 on 1/4 and player 2 on C/D). The left half stays on the keys around WASD and the
+// right half moves to the numpad, keeping its shape, with the arrow keys' Up and Down on C and D as well:
+//
+//   Keypad       Player 1     Player 2 (numpad)
+//   +-+-+-+-+    +-+-+        +-+-+
+//   |1|2|3|C|    |1|2|        |7|8|  (C: 8 or Up)
+//   |4|5|6|D|    |Q|W|        |4|5|  (D: 5 or Down)
+//   |7|8|9|E|    |A|S|        |1|2|
+//   |A|0|B|F|    |Z|X|        |0|.|
+//
+// A profile of your own goes in a [keymap.<name>] section, listing the host keys (space-separated, by the names in
+// interface.rs) for each keypad key to change; any key not listed keeps its standard binding:
+//
+//   [keymap.arrows]
+//   2 = "Up"
+//   8 = "Down"
+//   4 = "Left"
+//   6 = "Right W"
+use crate::config::Config;
+use crate::interface::{key_from_name, keypad_key_from_name, KEYMAP};
+use minifb::Key;
+
+pub struct Keymap {
+    pub name: String,
+    bindings: [Vec<Key>; 16], // Host keys that press each keypad key
+}
+
+impl Keymap {
+    pub fn standard() -> Self {
+        Keymap { name: "standard".into(), bindings: KEYMAP.map(|key| vec![key]) }
+    }
+
+    pub fn two_player() -> Self {
+        let mut keymap = Keymap::standard();
+        keymap.name = "two-player".into();
+        let player_2 = [
+            (0x3, vec![Key::NumPad7]),
+            (0xC, vec![Key::NumPad8, Key::Up]),
+            (0x6, vec![Key::NumPad4]),
+            (0xD, vec![Key::NumPad5, Key::Down]),
+            (0x9, vec![Key::NumPad1]),
+            (0xE, vec![Key::NumPad2]),
+            (0xB, vec![Key::NumPad0]),
+            (0xF, vec![Key::NumPadDot]),
+        ];
+        for (key, host_keys) in player_2 {
+            keymap.bindings[key] = host_keys;
+        }
+        keymap
+    }
+
+    // A preset, or a profile from the config file
+    pub fn from_name(name: &str, config: &Config) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "standard" | "default" => return Ok(Keymap::standard()),
+            "two-player" | "2-player" | "2p" => return Ok(Keymap::two_player()),
+            _ => (),
+        }
+
+        let section = format!("keymap.{}", name);
+        let entries = config.section(&section);
+        if entries.is_empty() {
+            return Err(format!("Unknown keymap '{}'; expected standard, two-player or a [{}] section", name, section));
+        }
+        let mut keymap = Keymap::standard();
+        keymap.name = name.to_string();
+        for (keypad_key, host_keys) in entries {
+            let key = keypad_key_from_name(keypad_key)
+                .ok_or_else(|| format!("'{}' in [{}] is not a keypad key (0-F)", keypad_key, section))?;
+            keymap.bindings[key] = host_keys
+                .split_whitespace()
+                .map(|name| key_from_name(name).ok_or_else(|| format!("Unknown key '{}' in [{}]", name, section)))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(keymap)
+    }
+
+    // Keypad keys pressed by the host keys that are down
+    pub fn held(&self, host_keys: &[Key]) -> [bool; 16] {
+        std::array::from_fn(|key| self.bindings[key].iter().any(|binding| host_keys.contains(binding)))
+    }
+
+    // Host keys bound to a keypad key
+    pub fn bindings(&self, key: usize) -> &[Key] {
+        &self.bindings[key]
+    }
+}
@@ -0,0 +1,25 @@
+// The emulator core: the CHIP-8 machine itself, without any window, sound or keyboard handling.
+// The emulator binary (main.rs) builds its front end on top of this, and the gym module drives it headlessly.
+//
+// Without the default `std` feature the core is no_std (it still needs an allocator), so it can run on
+// microcontrollers; see examples/ssd1306.rs.
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+mod chip8;
+pub use chip8::{Chip8, Fault, MachineState, MAX_DISPLAY_HEIGHT, PROGRAM_START};
+#[cfg(feature = "jit")]
+pub use chip8::Jit;
+mod state;
+
+pub mod coverage;
+pub mod devices;
+pub mod disasm;
+pub mod expr;
+pub mod gym;
+pub mod heatmap;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod quirks;
+pub mod screen;
+pub use screen::Screen;
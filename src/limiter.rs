@@ -0,0 +1,57 @@
+// Frame pacing for the main loop, in place of the window's own limiter (which sleeps the whole wait in one go and
+// can wake up a millisecond or more late). Most of the wait is slept through, and the last SPIN of it is spun out
+// so frames start on time without keeping a core busy.
+//
+// With --power-save, once the display has stayed the same for POWER_SAVE_AFTER the frame rate drops to
+// POWER_SAVE_HZ, until the picture changes, a key is pressed or the beeper sounds. The emulation doesn't slow
+// down, since the main loop runs however many ticks are due each frame; only the screen updates and key reads
+// are less frequent, which matters most on a laptop running on battery.
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SPIN: Duration = Duration::from_millis(1); // How much of the wait to spin out rather than sleep through
+const POWER_SAVE_HZ: f32 = 15.0; // Frames per second while the display isn't changing
+const POWER_SAVE_AFTER: Duration = Duration::from_secs(2); // How long the display must stay the same first
+
+pub struct FrameLimiter {
+    frame_time: Duration,     // Time between frames at the full frame rate
+    next_frame: Instant,      // When the next frame is due
+    pub power_save: bool,     // Drop the frame rate while nothing is happening
+    display: Vec<u64>,        // The display as of the last frame, to see whether it has changed
+    unchanged_since: Instant, // When the display last changed (or something else happened)
+}
+
+impl FrameLimiter {
+    pub fn new(fps: f32) -> Self {
+        let now = Instant::now();
+        FrameLimiter {
+            frame_time: Duration::from_secs_f32(1.0 / fps),
+            next_frame: now,
+            power_save: false,
+            display: Vec::new(),
+            unchanged_since: now,
+        }
+    }
+
+    // Wait until the next frame is due; busy says whether anything besides the display needs the full frame rate
+    // (keys held, the beeper sounding)
+    pub fn wait(&mut self, display: &[u64], busy: bool) {
+        let now = Instant::now();
+        if busy || display != self.display {
+            self.unchanged_since = now;
+            self.display = display.to_vec();
+        }
+        let idle = self.power_save && now - self.unchanged_since >= POWER_SAVE_AFTER;
+        let frame_time = if idle { Duration::from_secs_f32(1.0 / POWER_SAVE_HZ) } else { self.frame_time };
+
+        // A frame that ran late starts the schedule over from now, rather than rushing through frames to catch up
+        self.next_frame = (self.next_frame + frame_time).max(now);
+        let wait = self.next_frame - now;
+        if wait > SPIN {
+            thread::sleep(wait - SPIN);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+    }
+}
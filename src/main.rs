@@ -1,65 +1,1555 @@
 extern crate minifb;
 extern crate rand;
+#[macro_use]
+mod console;
+mod backend;
+use backend::BackendKind;
+#[cfg(target_os = "linux")]
+mod fbdev;
+mod frame_output;
+use frame_output::FrameOutput;
 mod interface;
-use interface::Interface;
-mod chip8;
-use chip8::Chip8;
-use minifb::{Key, Scale};
+use interface::{Interface, KeyMacro, Orientation, Phosphor, WindowSettings};
+mod limiter;
+use limiter::FrameLimiter;
+use chip8::{Chip8, Fault, PROGRAM_START};
+#[cfg(feature = "jit")]
+use chip8::Jit;
+use chip8::quirks::{QuirkProfile, Quirks};
+mod checksum;
+use checksum::Checksums;
+mod rom;
+mod trace;
+use rom::RomFormat;
+mod config;
+use config::Config;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod splash;
+use chip8::coverage::Coverage;
+use chip8::devices::{self, Devices};
+use chip8::heatmap::{HeatMap, HeatMapView};
+mod rewind;
+use rewind::{Event, RewindBuffer};
+mod recording;
+use recording::Recording;
+mod render;
+mod commands;
+mod overlay;
+mod palette;
+use palette::Palette;
+use overlay::{wrap_text, CHAR_HEIGHT, CHAR_WIDTH};
+mod replay;
+use replay::{Replay, Setup};
+mod achievements;
+mod announce;
+use announce::Announcer;
+use achievements::Achievements;
+mod fuzz;
+use fuzz::BitFlipper;
+mod input_test;
+mod keymap;
+use keymap::Keymap;
+mod input_display;
+use input_display::InputDisplay;
+mod cycle_graph;
+use cycle_graph::CycleGraph;
+mod auto_quirks;
+mod debugger;
+use debugger::Debugger;
+mod savestate;
+mod scaler;
+use scaler::{Filter, Scaling};
+use savestate::SaveSlots;
+mod watch;
+use watch::Watches;
+mod attract;
+use attract::Attract;
+mod audio;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "api")]
+mod api;
+use audio::{Audio, Waveform};
+use minifb::{Key, KeyRepeat, Scale};
+use std::io::Write;
 use std::path::Path;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // ======================= USER SETTINGS =======================
 const SCALE_FACTOR: Scale = Scale::X16; // Scaling size for screen (original is 64x32; factor of 16 will make it 1024x512)
 const CPU_HZ: f32 = 500.0; // Instructions per second
 const DISPLAY_HZ: f32 = 60.0; // Frames per second
-const MUTED: bool = false; // Whether or not to mute sound
+const MUTED: bool = false; // Whether or not to mute sound at startup (M toggles it while playing)
+const VOLUME: u8 = 10; // Beeper volume in percent (- and = change it while playing)
+const AUDIO_BUFFER_MS: u32 = 50; // Most sound buffered ahead of the sound card; lower means less delay but more risk of crackles
 const DEBUG_MODE: bool = true; // Enable debug mode to print additional information
+const STICKY_KEYS: bool = false; // Tap a key to toggle it instead of holding it down
+const KEY_REPEAT_DELAY: u32 = 0; // Frames a key must be held before it auto-repeats (0 disables auto-repeat)
+const KEY_REPEAT_INTERVAL: u32 = 6; // Frames between auto-repeated presses
+const REWIND_INTERVAL: u32 = 30; // Frames between states recorded for the history timeline (press P to pause and scrub)
+const REWIND_STATES: usize = 240; // Number of states kept for the history timeline (240 every 30 frames = 2 minutes)
+const SHOW_SPLASH: bool = true; // Show the boot splash screen before the ROM starts; skip it with --no-splash
+const AUTOSAVE_SECONDS: u32 = 60; // Seconds of play between autosaves (0 disables autosaving); see savestate.rs
+const PHOSPHOR_DECAY_MS: f32 = 50.0; // With --phosphor, how fast pixels fade after going off (time constant, in ms)
+const PHOSPHOR_MIN_PERCENT: f32 = 5.0; // With --phosphor, brightness a fading pixel is cut off below, in percent
+const QUIRK_PROFILE: QuirkProfile = QuirkProfile::Modern; // Interpreter behavior to emulate (see quirks.rs); override with --quirks
 // =============================================================
 
+const TIMER_HZ: f32 = 60.0; // The delay and sound timers always count down at 60Hz
+const VOLUME_STEP: u8 = 5; // Volume change per press of the volume hotkeys
+// Speeds the [ and ] hotkeys step through, in instructions per second
+const CPU_HZ_STEPS: [u32; 18] =
+    [100, 200, 300, 400, 500, 600, 700, 800, 1000, 1200, 1500, 2000, 3000, 5000, 10000, 20000, 30000, 60000];
+const KEY_WAIT_FRAMES: u32 = 30; // Frames a game must be held at FX0A before the "waiting for a key" indicator shows
+const FAST_FORWARD: u32 = 8; // How many times as fast the game runs while Tab is held
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250); // Cap on time to catch up on after a stall (e.g. window dragging)
+
 fn main() {
-    // Get rom_filepath from command-line arguments
-    let args: Vec<String> = std::env::args().collect();
-    let rom_filepath = if args.len() > 1 {
-        let filepath = &args[1];
-        println!("Found program: {}", filepath);
+    // Get the ROM path(s) and any options from command-line arguments
+    let mut rom_filepaths = Vec::new();
+    let mut quirk_profile = QUIRK_PROFILE;
+    let mut quirk_overrides = Vec::new();
+    let mut auto_quirks = false;
+    let mut verify_hash = None;
+    let mut rom_format = None;
+    let mut load_address = None;
+    let mut entry_point = None;
+    let mut verify_trace_path = None;
+    let mut config_filepath = None;
+    let mut show_splash = SHOW_SPLASH;
+    let mut show_heat_map = false;
+    let mut show_coverage = false;
+    let mut audio_recording_path = None;
+    let mut video_recording_path = None;
+    let mut replay_recording_path = None;
+    let mut replay_playback_path = None;
+    let mut embed_rom = false;
+    let mut achievements_path = None;
+    let mut fuzz_bitflips = None;
+    let mut trap_reserved_writes = false;
+    let mut devices = false;
+    let mut input_test = false;
+    let mut attract_mode = false;
+    let mut audio_device = None;
+    let mut vip_speaker = false;
+    let mut backend = BackendKind::Window;
+    let mut debug_repl = false;
+    let mut interpolate = false;
+    let mut phosphor = false;
+    let mut phosphor_decay = None;
+    let mut phosphor_min = None;
+    let mut rotate = None;
+    let mut mirror = None;
+    let mut window_title = None;
+    let mut borderless = false;
+    let mut window_position = None;
+    let mut topmost = false;
+    let mut frame_output_path = None;
+    let mut palette = None;
+    let mut outlines = false;
+    let mut eink = false;
+    let mut power_save = false;
+    let mut aspect = None;
+    let mut filter = None;
+    let mut show_cycle_graph = false;
+    let mut show_input_display = false;
+    let mut keymap_name = None;
+    let mut announce_method = None;
+    let mut quiet = false;
+    let mut frame_limit = None;
+    let mut strict = false;
+    #[cfg(feature = "metrics")]
+    let mut metrics_address = None;
+    #[cfg(feature = "api")]
+    let mut api_address = None;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    // The tools in commands.rs run without a window; anything else plays a ROM, with or without the word `run`
+    match args.first().map(String::as_str) {
+        Some("run") => {
+            args.remove(0);
+        }
+        Some(command) if commands::COMMANDS.contains(&command) => {
+            if let Err(e) = commands::run(command, &args[1..], CPU_HZ as u32) {
+                println!("{}", e);
+                std::process::exit(console::EXIT_ERROR);
+            }
+            return;
+        }
+        _ => (),
+    }
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let name = args.next().unwrap_or_default();
+                quirk_profile = QuirkProfile::from_name(&name).unwrap_or_else(|| {
+                    println!("Unknown quirk profile '{}'; expected one of: modern, vip, schip, xochip, eti660, dream6800", name);
+                    std::process::exit(console::EXIT_ERROR);
+                });
+            }
+            "--auto-quirks" => auto_quirks = true,
+            "--quirk" => {
+                // Individual quirk overrides are applied on top of the profile, e.g. --quirk i-overflow-flag=on
+                let setting = args.next().unwrap_or_default();
+                let (name, value) = setting.split_once('=').unwrap_or((&setting, "on"));
+                quirk_overrides.push((name.to_string(), value != "off"));
+            }
+            "--load-addr" => load_address = Some(parse_address(&arg, args.next())),
+            "--entry" => entry_point = Some(parse_address(&arg, args.next())),
+            "--verify" => verify_hash = args.next(),
+            "--verify-trace" => verify_trace_path = args.next(),
+            "--format" => {
+                let name = args.next().unwrap_or_default();
+                rom_format = Some(RomFormat::from_name(&name).unwrap_or_else(|| {
+                    println!("Unknown ROM format '{}'; expected binary or hex", name);
+                    std::process::exit(console::EXIT_ERROR);
+                }));
+            }
+            "--config" => config_filepath = args.next(),
+            "--no-splash" => show_splash = false,
+            "--heatmap" => show_heat_map = true,
+            "--coverage" => show_coverage = true,
+            "--record-audio" => audio_recording_path = args.next(),
+            "--record-video" => video_recording_path = args.next(),
+            "--record-replay" => replay_recording_path = args.next(),
+            "--play-replay" => replay_playback_path = args.next(),
+            "--embed-rom" => embed_rom = true,
+            "--achievements" => achievements_path = args.next(),
+            "--trap-reserved-writes" => trap_reserved_writes = true,
+            "--devices" => devices = true,
+            "--input-test" => input_test = true,
+            "--attract" => attract_mode = true,
+            "--audio-device" => audio_device = args.next(),
+            "--vip-speaker" => vip_speaker = true,
+            "--debug-repl" => debug_repl = true,
+            "--interpolate" => interpolate = true,
+            "--phosphor" => phosphor = true,
+            "--phosphor-decay" => phosphor_decay = args.next().and_then(|ms| ms.parse::<f32>().ok()),
+            "--phosphor-min" => phosphor_min = args.next().and_then(|percent| percent.parse::<f32>().ok()),
+            "--rotate" => rotate = args.next(),
+            "--mirror" => mirror = args.next(),
+            "--title" => window_title = args.next(),
+            "--borderless" => borderless = true,
+            "--position" => window_position = args.next(),
+            "--topmost" => topmost = true,
+            "--frame-output" => frame_output_path = args.next(),
+            "--palette" => palette = args.next(),
+            "--outlines" => outlines = true,
+            "--eink" => eink = true,
+            "--power-save" => power_save = true,
+            "--aspect" => aspect = args.next(),
+            "--filter" => filter = args.next(),
+            "--cycle-graph" => show_cycle_graph = true,
+            "--input-display" => show_input_display = true,
+            "--keymap" => keymap_name = args.next(),
+            "--announce" => announce_method = args.next(),
+            "--quiet" => quiet = true,
+            "--strict" => strict = true,
+            "--frames" => frame_limit = args.next().and_then(|frames| frames.parse::<u64>().ok()),
+            "--backend" => {
+                let name = args.next().unwrap_or_default();
+                backend = BackendKind::from_name(&name).unwrap_or_else(|| {
+                    println!("Unknown backend '{}'; expected window or drm", name);
+                    std::process::exit(console::EXIT_ERROR);
+                });
+            }
+            #[cfg(feature = "metrics")]
+            "--metrics" => metrics_address = args.next(),
+            #[cfg(feature = "api")]
+            "--api" => api_address = args.next(),
+            "--list-audio-devices" => {
+                let devices = audio::list_devices();
+                if devices.is_empty() {
+                    println!("No audio output devices found");
+                }
+                for name in devices {
+                    println!("{}", name);
+                }
+                return;
+            }
+            "--fuzz-bitflips" => fuzz_bitflips = args.next().and_then(|n| n.parse::<u32>().ok()),
+            _ => rom_filepaths.push(arg),
+        }
+    }
+    console::set_quiet(quiet);
+
+    // Given several ROMs, the first one starts and the rest make a playlist to switch through with PageUp/PageDown
+    let playlist = rom_filepaths.clone();
+    let mut playlist_position = 0;
+    if playlist.len() > 1 {
+        status!("Playlist of {} ROMs; PageUp and PageDown switch between them", playlist.len());
+    }
+    let mut rom_filepath = if let Some(filepath) = rom_filepaths.into_iter().next() {
+        status!("Found program: {}", filepath);
         filepath
+    } else if input_test || attract_mode || replay_playback_path.is_some() {
+        // The keypad test screen doesn't need a ROM, attract mode gets them from its playlist and a replay can
+        // bring its own
+        String::new()
     } else {
         println!(
-            "\nNo arguments provided; please provide a ROM file path using the following syntax:\n\tcargo run -- <path_to_rom>\n"
+            "\nNo arguments provided; please provide a ROM file path using the following syntax:\n\tcargo run -- [run] <path_to_rom> [more ROMs...] [--quirks <profile>] [--auto-quirks] [--quirk <name>=on|off] [--format binary|hex] [--load-addr <hex>] [--entry <hex>] [--verify <sha1|crc32>] [--verify-trace <trace.log>] [--config <path>] [--no-splash] [--heatmap] [--coverage] [--record-audio <out.wav>] [--record-video <out.mp4>] [--record-replay <out.replay> [--embed-rom]] [--play-replay <in.replay>] [--achievements <file.toml>] [--fuzz-bitflips <per_second>] [--trap-reserved-writes] [--devices] [--audio-device <name>] [--vip-speaker] [--backend window|drm] [--debug-repl] [--interpolate] [--phosphor [--phosphor-decay <ms>] [--phosphor-min <percent>]] [--rotate 90|180|270] [--mirror horizontal|vertical|both] [--title <text>] [--borderless] [--position <x>,<y>] [--topmost] [--frame-output <path>] [--palette <name>] [--outlines] [--eink] [--power-save] [--aspect <w>:<h>] [--filter nearest|bilinear] [--cycle-graph] [--input-display] [--keymap <name>] [--announce speech|notification] [--quiet] [--frames <n>] [--strict]\n\tcargo run -- disassemble <rom>\n\tcargo run -- assemble <in.asm> <out.ch8>\n\tcargo run -- info <rom>\n\tcargo run -- scan <directory>\n\tcargo run -- bench <rom> [seconds]\n\tcargo run -- render-replay <in.replay> <out.gif> [rom]\n\tcargo run -- --attract [--config <path>]\n\tcargo run -- --input-test\n\tcargo run -- --list-audio-devices\n"
         );
-        std::process::exit(1);
+        std::process::exit(console::EXIT_ERROR);
     };
+
+    // Print the ROM's checksums, and stop here if it can't be read, doesn't fit or isn't the one asked for
+    if !rom_filepath.is_empty() {
+        let rom_load_address = load_address.unwrap_or(quirk_profile.load_address());
+        let verified = rom::read(&rom_filepath, rom_format).and_then(|rom| {
+            let checksums = Checksums::of(&rom);
+            status!("SHA-1: {}  CRC32: {}", checksums.sha1, checksums.crc32);
+            if rom.len() > 4096 - rom_load_address as usize {
+                let (address, length) = (rom_load_address, rom.len());
+                return Err(format!("{} is too big to load at {:03X} ({} bytes)", rom_filepath, address, length));
+            }
+            verify_hash.as_ref().map_or(Ok(()), |hash| checksums.verify(hash))
+        });
+        if let Err(e) = verified {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ROM_LOAD);
+        }
+    }
     
+    // Load the config file if one was given (or one exists in the default location)
+    let config_path = config_filepath.unwrap_or_else(|| config::DEFAULT_CONFIG_PATH.to_string());
+    let mut config = if Path::new(&config_path).exists() {
+        Config::load(Path::new(&config_path)).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        })
+    } else {
+        Config::new()
+    };
+
+    // Compare the emulator against another one's trace of the ROM instead of playing it
+    if let Some(path) = verify_trace_path {
+        let mut chip8 = Chip8::new();
+        chip8.quirks = quirks_with_overrides(quirk_profile, &quirk_overrides);
+        chip8.load_address = load_address.unwrap_or(quirk_profile.load_address());
+        chip8.entry_point = entry_point.unwrap_or(chip8.load_address);
+        chip8.display_height = quirk_profile.display_height();
+        chip8.reset();
+        rom::load(&mut chip8, &rom_filepath, rom_format);
+        let cpu_hz = cpu_hz_for(&config, &rom_filepath, quirk_profile);
+        match trace::verify(&mut chip8, Path::new(&path), cpu_hz) {
+            Ok(count) => status!("All {} instructions in the trace match", count),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(console::EXIT_ERROR);
+            }
+        }
+        return;
+    }
+
+    // E-ink mode draws in black and white only, so nothing that makes in-between shades (filtering, interpolation,
+    // phosphor fade) is used with it
+    let eink = eink || config.get_or("display", "eink", false);
+
+    // How to stretch the picture, if at all; the command line takes precedence over the config file
+    let aspect = aspect.or_else(|| config.get("display", "aspect").map(str::to_string));
+    let filter = filter.or_else(|| config.get("display", "filter").map(str::to_string)).filter(|_| !eink);
+    let scaling = Scaling {
+        aspect: aspect.map(|text| {
+            scaler::parse_aspect(&text).unwrap_or_else(|| {
+                println!("Invalid aspect ratio '{}'; expected e.g. 4:3 or 1.5, from 1:4 to 8:1", text);
+                std::process::exit(console::EXIT_ERROR);
+            })
+        }),
+        filter: filter.map_or(Filter::Nearest, |name| {
+            Filter::from_name(&name).unwrap_or_else(|| {
+                println!("Unknown filter '{}'; expected nearest or bilinear", name);
+                std::process::exit(console::EXIT_ERROR);
+            })
+        }),
+    };
+
+    // Which way up to show the picture
+    let rotate = rotate.or_else(|| config.get("display", "rotate").map(str::to_string));
+    let mirror = mirror.or_else(|| config.get("display", "mirror").map(str::to_string));
+    let mut orientation = Orientation::default();
+    if let Some(degrees) = rotate {
+        orientation.rotate = match degrees.as_str() {
+            "0" | "90" | "180" | "270" => degrees.parse().unwrap(),
+            _ => {
+                println!("Can't rotate by '{}'; expected 0, 90, 180 or 270", degrees);
+                std::process::exit(console::EXIT_ERROR);
+            }
+        };
+    }
+    if let Some(direction) = mirror {
+        (orientation.mirror_x, orientation.mirror_y) = match direction.as_str() {
+            "none" => (false, false),
+            "horizontal" => (true, false),
+            "vertical" => (false, true),
+            "both" => (true, true),
+            _ => {
+                println!("Unknown mirror direction '{}'; expected horizontal, vertical or both", direction);
+                std::process::exit(console::EXIT_ERROR);
+            }
+        };
+    }
+
+    // Window options, again with the command line first
+    let window_position = window_position.or_else(|| config.get("window", "position").map(str::to_string));
+    let window_settings = WindowSettings {
+        title: window_title
+            .or_else(|| config.get("window", "title").map(str::to_string))
+            .unwrap_or_else(|| "Chip-8 Emulator".to_string()),
+        borderless: borderless || config.get_or("window", "borderless", false),
+        position: window_position.map(|position| {
+            position
+                .split_once(',')
+                .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                .unwrap_or_else(|| {
+                    println!("Invalid window position '{}'; expected <x>,<y>, e.g. 100,50", position);
+                    std::process::exit(console::EXIT_ERROR);
+                })
+        }),
+        topmost: topmost || config.get_or("window", "topmost", false),
+    };
+
     // Create an Interface instance with specified scaling
-    let interface = Interface::new(SCALE_FACTOR);
+    let rows = quirk_profile.display_height();
+    let mut interface = Interface::new(SCALE_FACTOR, backend, scaling, orientation, &window_settings, rows);
     
-    // Create a Chip8 instance with our Interface instance
-    let mut chip8 = Chip8::new(interface);
+    // Create a Chip8 instance; the interface feeds it input and shows its display
+    let mut chip8 = Chip8::new();
+
+    // Where the ROM goes and starts; programs for other machines may not use 0x200. Given only a load address,
+    // the program starts at the beginning of it
+    chip8.load_address = load_address.unwrap_or(quirk_profile.load_address());
+    chip8.entry_point = entry_point.unwrap_or(chip8.load_address);
+    chip8.display_height = quirk_profile.display_height();
     
     // Set the window to update at our desired DISPLAY_HZ
-    chip8.interface.window.set_target_fps(DISPLAY_HZ as usize);
+    interface.window.set_target_fps(DISPLAY_HZ as usize);
 
-    // Define the path to the ROM file and load it into memory
-    let rom_path = Path::new(&rom_filepath);
-    chip8.load_program(rom_path);
-    
-    // Calculate the number of CPU cycles per frame
-    let cycles_per_frame = (CPU_HZ / DISPLAY_HZ) as usize;
-    
-    // Additional settings
-    chip8.interface.muted = MUTED;
-    chip8.interface.debug_mode = DEBUG_MODE;
+    // Display settings; the palette can be changed while playing with F3, which saves it to the config file
+    interface.palette = palette_for(palette.as_deref(), &config, &rom_filepath).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(console::EXIT_ERROR);
+    });
+    interface.outlines = outlines || config.get_or("display", "outlines", false);
+    interface.eink = eink;
+    interface.interpolate = !eink && (interpolate || config.get_or("display", "interpolate", false));
+    let phosphor = phosphor || phosphor_decay.is_some() || phosphor_min.is_some();
+    if !eink && (phosphor || config.get_or("display", "phosphor", false)) {
+        let decay = phosphor_decay.unwrap_or_else(|| config.get_or("display", "phosphor_decay", PHOSPHOR_DECAY_MS));
+        let min = phosphor_min.unwrap_or_else(|| config.get_or("display", "phosphor_min", PHOSPHOR_MIN_PERCENT));
+        interface.phosphor = Some(Phosphor { decay: decay / 1000.0, min_brightness: min / 100.0 });
+    }
+    if let Some(path) = frame_output_path {
+        interface.frame_output = Some(FrameOutput::open(&path).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        }));
+    }
+
+    // Input settings
+    interface.input.sticky_keys = config.get_or("input", "sticky_keys", STICKY_KEYS);
+    interface.input.repeat_delay = config.get_or("input", "repeat_delay", KEY_REPEAT_DELAY);
+    interface.input.repeat_interval = config.get_or("input", "repeat_interval", KEY_REPEAT_INTERVAL);
+    interface.keymap = keymap_for(keymap_name.as_deref(), &config, &rom_filepath).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(console::EXIT_ERROR);
+    });
+    #[cfg(feature = "gamepad")]
+    {
+        interface.gamepad = gamepad::Gamepad::new(&config);
+    }
+    for (host_key, definition) in config.section("macros") {
+        match KeyMacro::parse(host_key, definition) {
+            Ok(key_macro) => interface.input.macros.push(key_macro),
+            Err(e) => {
+                println!("Invalid macro for {} in config file: {}", host_key, e);
+                std::process::exit(console::EXIT_ERROR);
+            }
+        }
+    }
+
+    // The keypad test screen runs instead of the emulator
+    if input_test {
+        input_test::run(&mut interface);
+        return;
+    }
+
+    // Load the replay to play back, if any
+    let mut playback = replay_playback_path.map(|path| {
+        Replay::load(Path::new(&path)).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        })
+    });
+
+    // Replays have to play back on the ROM, quirks and load address they were recorded with (see Replay::check)
+    let setup = Setup {
+        profile: quirk_profile.name(),
+        overrides: &quirk_overrides,
+        quirks: quirks_with_overrides(quirk_profile, &quirk_overrides),
+        load_address: chip8.load_address,
+    };
+
+    // Attract mode starts with the first game in its playlist, once all of its demos check out
+    let mut attract = attract_mode.then(|| {
+        let demos = Attract::load(&config, quirk_profile);
+        demos.and_then(|demos| demos.check(rom_format).map(|()| demos)).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        })
+    });
+    if let Some(demos) = &attract {
+        let (rom, replay) = demos.current();
+        rom_filepath = rom.to_string();
+        playback = Some(replay.clone());
+        match palette_for(palette.as_deref(), &config, &rom_filepath) {
+            Ok(palette) => interface.palette = palette,
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // Check the replay to play back; given no ROM, it plays the one embedded in it
+    let mut embedded_rom = None;
+    if let (Some(replay), None) = (&playback, &attract) {
+        let rom = if rom_filepath.is_empty() {
+            embedded_rom = replay.rom.clone();
+            embedded_rom.clone().ok_or_else(|| "No ROM given, and the replay doesn't have one embedded".to_string())
+        } else {
+            rom::read(&rom_filepath, rom_format)
+        };
+        if let Err(e) = rom.and_then(|rom| replay.check(&setup, &rom)) {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        }
+    }
+    if let Some(demos) = &attract {
+        (quirk_profile, quirk_overrides) = apply_demo_setup(demos, &mut chip8);
+    }
+
+    // Replays have to start from a freshly loaded ROM, so they skip the splash screen
+    if playback.is_some() || replay_recording_path.is_some() {
+        show_splash = false;
+    }
+
+    // Load the ROM file into memory, unless the splash screen gets to run first
+    let mut splash_until = None;
+    if show_splash {
+        // The splash is an ordinary 0x200 program wherever the ROM goes (a new Chip8 starts at 0x200)
+        for (offset, &byte) in splash::SPLASH_ROM.iter().enumerate() {
+            chip8.poke(PROGRAM_START + offset as u16, byte);
+        }
+        splash_until = Some(splash::SPLASH_TICKS); // Ticks count from 0 at startup
+    } else {
+        chip8.reset(); // Start at the entry point
+        match &embedded_rom {
+            Some(rom) => chip8.load_bytes(rom).unwrap_or_else(|e| {
+                println!("{}", e);
+                std::process::exit(console::EXIT_ROM_LOAD);
+            }),
+            None => rom::load(&mut chip8, &rom_filepath, rom_format),
+        }
+    }
+
+    // Instructions per second; a replay is played back at the speed it was recorded at, and otherwise each ROM
+    // runs at the speed last picked for it with [ and ], or the usual speed for its platform (see cpu_hz_for)
+    let mut cpu_hz = match &playback {
+        Some(replay) => replay.cpu_hz,
+        None => cpu_hz_for(&config, &rom_filepath, quirk_profile),
+    };
+    let tick_time = Duration::from_secs_f32(1.0 / TIMER_HZ);
+
+    // Translated blocks are cached across frames
+    #[cfg(feature = "jit")]
+    let mut jit = Jit::new();
+
+    // Open the audio output; the command line takes precedence over the config file
+    let audio_device = audio_device.or_else(|| config.get("audio", "device").map(str::to_string));
+    let mut audio = Audio::open(audio_device.as_deref(), config.get_or("audio", "buffer_ms", AUDIO_BUFFER_MS));
+
+    // Additional settings; the volume and mute settings are saved to the config file when changed with the hotkeys
+    audio.muted = config.get_or("audio", "muted", MUTED);
+    audio.volume = config.get_or("audio", "volume", VOLUME).min(100);
+    let waveform = if vip_speaker { Some("vip") } else { config.get("audio", "waveform") };
+    if let Some(name) = waveform {
+        audio.waveform = Waveform::from_name(name).unwrap_or_else(|| {
+            println!("Unknown waveform '{}'; expected sine or vip", name);
+            std::process::exit(console::EXIT_ERROR);
+        });
+    }
+    chip8.debug_mode = DEBUG_MODE && !quiet;
+    chip8.trap_reserved_writes = trap_reserved_writes;
+
+    // Try the ROM under each quirk profile first if asked to, and start it with whichever ran best
+    if auto_quirks && playback.is_none() {
+        if let Ok(rom) = rom::read(&rom_filepath, rom_format) {
+            let layout = (chip8.load_address, chip8.entry_point);
+            quirk_profile = auto_quirks::detect(&rom, cpu_hz, layout, quirk_profile);
+            cpu_hz = cpu_hz_for(&config, &rom_filepath, quirk_profile);
+        }
+    }
+    chip8.quirks = quirks_with_overrides(quirk_profile, &quirk_overrides);
+    chip8.display_height = quirk_profile.display_height();
     
+    // Start recording audio and/or video if requested
+    let (audio_path, video_path) = (audio_recording_path.as_deref(), video_recording_path.as_deref());
+    let mut recording = Recording::start(audio_path, video_path, chip8.display_height)
+        .unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        });
+    recording.set_sound(audio.volume, audio.waveform);
+
+    // Load the achievements to watch for, if any
+    let mut achievements = achievements_path.map(|path| {
+        Achievements::load(Path::new(&path)).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        })
+    });
+
+    // Start flipping bits if fault injection was requested
+    let mut bit_flipper = fuzz_bitflips.map(|flips_per_second| BitFlipper::new(flips_per_second, rand::random()));
+
+    // Serve metrics if requested
+    #[cfg(feature = "metrics")]
+    let metrics = metrics_address.map(|address| {
+        let metrics = std::sync::Arc::new(metrics::Metrics::default());
+        metrics::serve(&address, metrics.clone()).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        });
+        metrics
+    });
+
+    // Serve the HTTP API if requested
+    #[cfg(feature = "api")]
+    let mut api = api_address.map(|address| {
+        api::Api::serve(&address).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        })
+    });
+
+    // Open the memory heat map window if requested
+    let mut heat_map_view = None;
+    if show_heat_map {
+        heat_map_view = Some(HeatMapView::new());
+        chip8.heat_map = Some(HeatMap::new());
+    }
+    // Count the instructions run, for the coverage report printed on exit
+    if show_coverage {
+        chip8.coverage = Some(Coverage::new());
+    }
+    // Nonstandard memory-mapped devices for homebrew, if asked for (see devices.rs)
+    if devices || config.get_or("devices", "enabled", false) {
+        let base = config.get("devices", "base").map(|base| parse_address("[devices] base", Some(base.to_string())));
+        let devices = Devices::new(base.unwrap_or(devices::DEFAULT_BASE)).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(console::EXIT_ERROR);
+        });
+        status!("Nonstandard devices are mapped at {:03X}-{:03X}", devices.base(), devices.base() + devices::SIZE - 1);
+        chip8.devices = Some(devices);
+    }
+
+    // Debugger commands typed into the terminal, and values to show on screen
+    let mut debugger = debug_repl.then(Debugger::new);
+    if let Some(debugger) = &mut debugger {
+        let rom = embedded_rom.clone().map_or_else(|| rom::read(&rom_filepath, rom_format), Ok);
+        debugger.rom_length = rom.map_or(0, |rom| rom.len());
+    }
+    let mut watches = Watches::load(&config).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(console::EXIT_ERROR);
+    });
+
+    // Game events read out or sent as notifications, if asked for
+    let mut announcer = Announcer::from_config(announce_method.as_deref(), &config).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(console::EXIT_ERROR);
+    });
+
+    // Graph of the instructions and draws in each frame, toggled with F4
+    let mut cycle_graph = show_cycle_graph.then(CycleGraph::new);
+
+    // Keypad and recent presses, for streaming, toggled with F5
+    let mut input_display = show_input_display.then(InputDisplay::new);
+
+    // Frames the game has been held at FX0A for, to say it's waiting for a key rather than frozen
+    let key_wait_indicator = config.get_or("display", "key_wait_indicator", true);
+    let mut key_wait_frames = 0;
+
+    // History of machine states for the paused-mode timeline; timeline_position is Some while paused
+    let mut history = RewindBuffer::new(REWIND_STATES, REWIND_INTERVAL);
+    let mut timeline_position: Option<usize> = None;
+
+    // Save slots for the ROM; slot_picker is the selected slot while the picker is open
+    let mut save_slots = SaveSlots::new(&rom_filepath, quirk_profile.name());
+    let mut slot_picker: Option<usize> = None;
+
+    // Seed the random number generator so the session can be replayed exactly, and start recording the input
+    // While a replay plays back, the one being recorded starts as a copy of it, to be carried on from the end or
+    // from a state loaded part way through (re-recording)
+    let mut replay_recording = replay_recording_path.as_ref().map(|_| {
+        if let (Some(replay), None) = (&playback, &attract) {
+            return replay.clone();
+        }
+        let rom = rom::read(&rom_filepath, rom_format).unwrap_or_default();
+        let setup = Setup {
+            profile: quirk_profile.name(),
+            overrides: &quirk_overrides,
+            quirks: chip8.quirks,
+            load_address: chip8.load_address,
+        };
+        let replay = Replay::record(rand::random(), cpu_hz, &setup, &rom, embed_rom);
+        chip8.seed_rng(replay.seed);
+        replay
+    });
+    if let Some(replay) = &playback {
+        chip8.seed_rng(replay.seed);
+    }
+
+    // Autosave every so often, and offer the autosave back if the last session crashed or when the game faults.
+    // recovery_prompt is Some while asking, with whether it was a fault that prompted it
+    let autosave_ticks = config.get_or("autosave", "interval", AUTOSAVE_SECONDS) * TIMER_HZ as u32;
+    let mut offer_on_fault = config.get_or("autosave", "on_fault", true) && bit_flipper.is_none();
+    let mut ticks_since_autosave = 0;
+    let mut faulted_since_autosave = false;
+    let mut recovery_prompt = None;
+    let replaying = playback.is_some() || replay_recording.is_some();
+    if attract.is_none() && save_slots.begin_session() && save_slots.has_autosave() && !replaying {
+        recovery_prompt = Some(false);
+    }
+
+    // Emulation runs in 60Hz ticks (one timer update each), with the CPU cycles spread evenly over them.
+    // The cycles per tick are counted in whole units of 1/60 cycle, so a tick always runs the same number of
+    // cycles no matter how the frames fall, which is what makes replays exact; the real time still to be caught
+    // up on carries over to the next frame, so CPU_HZ doesn't need to be a multiple of DISPLAY_HZ to stay exact
+    let mut tick: u64 = 0;
+    let mut exit_code = console::EXIT_OK; // The worst fault the game has hit, to exit with (see console.rs)
+    let mut game_frames: u64 = 0; // Frames run since the splash screen, for --frames
+    let mut reported_faults = Vec::new(); // Faults that have had a full diagnostic printed
+    let mut finished = false; // Whether to quit with exit_code at the end of the frame (--frames or --strict)
+    let mut tick_backlog = Duration::ZERO;
+    let mut cycle_units: u32 = 0;
+    let mut last_frame = Instant::now();
+
+    // From here on frames are paced by the limiter rather than the window, and with --power-save, slowed down
+    // while the display isn't changing
+    let mut limiter = FrameLimiter::new(DISPLAY_HZ);
+    limiter.power_save = power_save || config.get_or("display", "power_save", false);
+    interface.window.set_target_fps(0);
+
+    // Holding Tab runs the game several times as fast, for skipping slow title screens and intros
+    let fast_forward_speed = config.get_or("input", "fast_forward", FAST_FORWARD).max(1);
+
     // Main loop; exit if window is closed or Escape is pressed
-    while chip8.interface.window.is_open() && !chip8.interface.window.is_key_down(Key::Escape) {
+    while interface.window.is_open() && !interface.window.is_key_down(Key::Escape) {
+        let busy = chip8.sound_timer() > 0 || !interface.window.get_keys().is_empty();
+        limiter.wait(chip8.display_rows(), busy);
+
+        if let Some(announcer) = &mut announcer {
+            announcer.poll();
+        }
+
+        // Accumulate the time since the last frame. Fast-forwarding counts it several times over, so that many
+        // ticks (timers and all) run in the frame and only the last of them is shown; the sound is left out
+        let now = Instant::now();
+        let elapsed = (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+        let fast_forward = interface.window.is_key_down(Key::Tab) && fast_forward_speed > 1;
+        tick_backlog += if fast_forward { elapsed * fast_forward_speed } else { elapsed };
+
+        // Once the splash screen has been shown for long enough and has finished drawing, swap in the real program
+        let splash_over = splash_until.is_some_and(|until| {
+            let finished = chip8.pc() == splash::SPLASH_END;
+            tick >= until && (finished || tick >= until + splash::SPLASH_GRACE_TICKS)
+        });
+        if splash_over {
+            // A slow CPU may simply not have got that far, which isn't worth a warning
+            if chip8.pc() != splash::SPLASH_END && tick * cpu_hz as u64 / TIMER_HZ as u64 >= splash::SPLASH_CYCLES {
+                eprintln!("Warning: the splash screen did not finish drawing; the emulator core may be broken");
+            }
+            splash_until = None;
+            chip8.reset();
+            rom::load(&mut chip8, &rom_filepath, rom_format);
+            if chip8.coverage.is_some() {
+                chip8.coverage = Some(Coverage::new()); // Only the ROM's instructions count
+            }
+            #[cfg(feature = "jit")]
+            {
+                jit = Jit::new();
+            }
+        }
+
+        // Attract mode: play each game's demo in turn until a key is pressed, then hand that game to the player
+        if let Some(demos) = &mut attract {
+            let key_pressed = !interface.window.get_keys().is_empty();
+            let demo_over = playback.is_none() || tick >= demos.seconds as u64 * 60;
+            if key_pressed || demo_over {
+                if key_pressed {
+                    status!("Starting {}", rom_filepath);
+                    save_slots = SaveSlots::new(&rom_filepath, quirk_profile.name());
+                    if save_slots.begin_session() && save_slots.has_autosave() {
+                        recovery_prompt = Some(false);
+                    }
+                    playback = None;
+                    cpu_hz = cpu_hz_for(&config, &rom_filepath, quirk_profile);
+                    match keymap_for(keymap_name.as_deref(), &config, &rom_filepath) {
+                        Ok(keymap) => interface.keymap = keymap,
+                        Err(e) => println!("{}", e),
+                    }
+                    chip8.seed_rng(rand::random());
+                } else {
+                    demos.advance();
+                    (quirk_profile, _) = apply_demo_setup(demos, &mut chip8);
+                    let (rom, replay) = demos.current();
+                    rom_filepath = rom.to_string();
+                    cpu_hz = replay.cpu_hz;
+                    chip8.seed_rng(replay.seed);
+                    playback = Some(replay.clone());
+                }
+                match palette_for(palette.as_deref(), &config, &rom_filepath) {
+                    Ok(palette) => interface.palette = palette,
+                    Err(e) => println!("{}", e),
+                }
+
+                // Either way, the game starts over from the beginning
+                chip8.reset();
+                rom::load(&mut chip8, &rom_filepath, rom_format);
+                tick = 0;
+                cycle_units = 0;
+                history.clear();
+                #[cfg(feature = "jit")]
+                {
+                    jit = Jit::new();
+                }
+            }
+            if key_pressed {
+                attract = None;
+            }
+        }
+
+        // PageUp and PageDown switch to the previous and next ROM in the playlist, starting it from the beginning;
+        // each ROM keeps its own save slots and speed
+        let window = &interface.window;
+        let step = if playlist.len() < 2 || attract.is_some() {
+            None
+        } else if window.is_key_pressed(Key::PageUp, KeyRepeat::No) {
+            Some(playlist.len() - 1)
+        } else if window.is_key_pressed(Key::PageDown, KeyRepeat::No) {
+            Some(1)
+        } else {
+            None
+        };
+        if let Some(step) = step {
+            if playback.is_some() || replay_recording.is_some() {
+                interface.show_toast("Can't switch ROMs during a replay");
+            } else {
+                playlist_position = (playlist_position + step) % playlist.len();
+                rom_filepath = playlist[playlist_position].clone();
+                status!("Starting {}", rom_filepath);
+                save_slots.end_session();
+                save_slots = SaveSlots::new(&rom_filepath, quirk_profile.name());
+                recovery_prompt = (save_slots.begin_session() && save_slots.has_autosave()).then_some(false);
+                ticks_since_autosave = 0;
+                faulted_since_autosave = false;
+                cpu_hz = cpu_hz_for(&config, &rom_filepath, quirk_profile);
+                match keymap_for(keymap_name.as_deref(), &config, &rom_filepath) {
+                    Ok(keymap) => interface.keymap = keymap,
+                    Err(e) => println!("{}", e),
+                }
+                match palette_for(palette.as_deref(), &config, &rom_filepath) {
+                    Ok(palette) => interface.palette = palette,
+                    Err(e) => println!("{}", e),
+                }
+
+                splash_until = None;
+                chip8.reset();
+                rom::load(&mut chip8, &rom_filepath, rom_format);
+                if let Some(debugger) = &mut debugger {
+                    debugger.rom_length = rom::read(&rom_filepath, rom_format).map_or(0, |rom| rom.len());
+                }
+                tick = 0;
+                cycle_units = 0;
+                history.clear();
+                timeline_position = None;
+                slot_picker = None;
+                audio.clear();
+                let name = Path::new(&rom_filepath).file_name().map(|name| name.to_string_lossy());
+                let name = name.unwrap_or(rom_filepath.as_str().into());
+                interface.show_toast(&format!("{}/{}: {}", playlist_position + 1, playlist.len(), name));
+                #[cfg(feature = "jit")]
+                {
+                    jit = Jit::new();
+                }
+            }
+        }
+
+        // Ask whether to go back to the autosave: Y restores it and N carries on (and stops asking after faults)
+        if let Some(after_fault) = recovery_prompt {
+            let window = &interface.window;
+            if window.is_key_pressed(Key::Y, KeyRepeat::No) {
+                match save_slots.load_autosave() {
+                    Ok(state) => {
+                        chip8.restore(&state);
+                        history.clear(); // The history leading up to now doesn't lead to the restored state
+                        splash_until = None;
+                        interface.show_toast("Restored the autosave");
+                        #[cfg(feature = "jit")]
+                        {
+                            jit = Jit::new();
+                        }
+                    }
+                    Err(e) => {
+                        println!("{}", e);
+                        interface.show_toast("Restoring failed");
+                    }
+                }
+                recovery_prompt = None;
+            } else if window.is_key_pressed(Key::N, KeyRepeat::No) {
+                offer_on_fault &= !after_fault;
+                recovery_prompt = None;
+            } else {
+                let reason = if after_fault { "The game hit a fault." } else { "The last session didn't end cleanly." };
+                interface.draw_display(chip8.display_rows());
+                interface.canvas().draw_caption(&[reason, "Restore the autosave? Y/N"], 0xFFFF80);
+                interface.present();
+                tick_backlog = Duration::ZERO;
+                stop_rumble(&mut interface);
+                continue;
+            }
+        }
+
+        // P pauses and opens the history timeline: Left/Right scrub through it, Enter resumes from the
+        // selected point and P again resumes from where the game was paused. Jumping back in time would
+        // break a replay, so a replay can only be paused
+        if slot_picker.is_none() && interface.window.is_key_pressed(Key::P, KeyRepeat::No) {
+            timeline_position = match timeline_position {
+                Some(_) => None,
+                None => Some(history.len().saturating_sub(1)),
+            };
+            audio.clear();
+        }
+        if let Some(position) = &mut timeline_position {
+            let window = &interface.window;
+            if window.is_key_pressed(Key::Left, KeyRepeat::Yes) {
+                *position = position.saturating_sub(1);
+            }
+            if window.is_key_pressed(Key::Right, KeyRepeat::Yes) && *position + 1 < history.len() {
+                *position += 1;
+            }
+
+            let replaying = playback.is_some() || replay_recording.is_some();
+            if window.is_key_pressed(Key::Enter, KeyRepeat::No) && !history.is_empty() && !replaying {
+                history.restore(*position, &mut chip8);
+                history.truncate_after(*position);
+                timeline_position = None;
+                #[cfg(feature = "jit")]
+                {
+                    jit = Jit::new();
+                }
+            } else {
+                // Show the selected point in history with the timeline along the bottom
+                let preview = history.get(*position).map_or(chip8.display, |state| state.display);
+                interface.draw_display(&preview[..chip8.display_height]);
+                interface.draw_timeline(*position, history.len());
+                interface.present();
+
+                // Time spent paused shouldn't be caught up on afterwards
+                tick_backlog = Duration::ZERO;
+                stop_rumble(&mut interface);
+                continue;
+            }
+        }
+
+        // F2 pauses and opens the save slot picker: the arrow keys pick a slot, S saves the game in it, Enter loads it
+        // and F2 again closes the picker. While a replay plays or records, states are saved with the replay so far,
+        // and loading one carries on recording from there instead of throwing the replay out of sync
+        if interface.window.is_key_pressed(Key::F2, KeyRepeat::No) {
+            slot_picker = match slot_picker {
+                Some(_) => None,
+                None => Some(0),
+            };
+            audio.clear();
+        }
+        if let Some(selected) = &mut slot_picker {
+            let window = &interface.window;
+            if window.is_key_pressed(Key::Left, KeyRepeat::Yes) {
+                *selected = selected.saturating_sub(1);
+            }
+            if window.is_key_pressed(Key::Right, KeyRepeat::Yes) {
+                *selected = (*selected + 1).min(savestate::SLOTS - 1);
+            }
+            if window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+                *selected %= 5;
+            }
+            if window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+                *selected = *selected % 5 + 5;
+            }
+
+            let replaying = playback.is_some() || replay_recording.is_some();
+            if window.is_key_pressed(Key::S, KeyRepeat::No) {
+                // The thumbnail is taken from the game's screen, not the picker drawn over it
+                interface.draw_display(chip8.display_rows());
+                let replay = replay_recording.as_ref().or(playback.as_ref()).filter(|_| attract.is_none()).map(|replay| {
+                    let mut replay = replay.clone();
+                    replay.truncate(tick);
+                    replay
+                });
+                let saved = save_slots
+                    .save(*selected, &chip8.snapshot(), interface.thumbnail())
+                    .and_then(|()| save_slots.save_replay(*selected, replay.as_ref()));
+                match saved {
+                    Ok(()) => interface.show_toast(&format!("Saved slot {}", selected)),
+                    Err(e) => {
+                        println!("{}", e);
+                        interface.show_toast("Saving failed");
+                    }
+                }
+            } else if window.is_key_pressed(Key::Enter, KeyRepeat::No) {
+                // During a replay, a state can only be loaded along with the replay that led up to it, which
+                // becomes the one being recorded
+                let loaded = match (replaying, &replay_recording) {
+                    (false, _) => save_slots.load(*selected).map(|state| (state, None)),
+                    (true, None) => Err("Re-recording needs --record-replay".to_string()),
+                    (true, Some(_)) => save_slots.load_replay(*selected).and_then(|replay| match replay {
+                        Some(replay) => save_slots.load(*selected).map(|state| (state, Some(replay))),
+                        None => Err(format!("Slot {} wasn't saved during a replay", selected)),
+                    }),
+                };
+                match loaded {
+                    Ok((state, replay)) => {
+                        chip8.restore(&state);
+                        history.clear(); // The history leading up to now doesn't lead to the loaded state
+                        splash_until = None;
+                        interface.show_toast(&format!("Loaded slot {}", selected));
+                        if let Some(replay) = replay {
+                            // Pick up the tick count and cycle accounting where the replay left off
+                            tick = replay.end;
+                            cpu_hz = replay.cpu_hz;
+                            cycle_units = (tick * cpu_hz as u64 % TIMER_HZ as u64) as u32;
+                            status!("Re-recording from frame {} (slot {})", tick, selected);
+                            playback = None;
+                            replay_recording = Some(replay);
+                        }
+                        slot_picker = None;
+                        #[cfg(feature = "jit")]
+                        {
+                            jit = Jit::new();
+                        }
+                    }
+                    Err(e) => interface.show_toast(&e),
+                }
+            }
+
+            if let Some(selected) = slot_picker {
+                interface.draw_slot_picker(save_slots.thumbnails(), selected);
+                interface.present();
+                tick_backlog = Duration::ZERO;
+                stop_rumble(&mut interface);
+                continue;
+            }
+        }
+
+        // While the debugger has the game paused, keep showing it but don't run anything
+        if let Some(debugger) = &mut debugger {
+            // Stepping back would throw a replay out of sync, like resuming from the timeline
+            let replaying = playback.is_some() || replay_recording.is_some();
+            if debugger.poll(&mut chip8, (!replaying).then_some(&mut history), &mut watches, &save_slots) {
+                #[cfg(feature = "jit")]
+                {
+                    jit = Jit::new();
+                }
+            }
+            if debugger.paused {
+                interface.draw_display(chip8.display_rows());
+                draw_watches(&mut interface, &watches, &chip8);
+                interface.present();
+                tick_backlog = Duration::ZERO;
+                stop_rumble(&mut interface);
+                continue;
+            }
+        }
+
+        // The HTTP API serves the machine as of each frame, and can pause it too
+        #[cfg(feature = "api")]
+        if let Some(api) = &mut api {
+            api.poll();
+            let [background, foreground, ..] = interface.palette.colors;
+            api.publish(&chip8, [background, foreground]);
+            if api.paused {
+                interface.draw_display(chip8.display_rows());
+                interface.present();
+                tick_backlog = Duration::ZERO;
+                stop_rumble(&mut interface);
+                continue;
+            }
+        }
+
+        // Volume hotkeys: - and = (or the keypad's - and +) turn the volume down and up, and M toggles mute
+        let window = &interface.window;
+        let volume_change = if window.is_key_pressed(Key::Minus, KeyRepeat::Yes)
+            || window.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes)
+        {
+            Some(audio.volume.saturating_sub(VOLUME_STEP))
+        } else if window.is_key_pressed(Key::Equal, KeyRepeat::Yes)
+            || window.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes)
+        {
+            Some((audio.volume + VOLUME_STEP).min(100))
+        } else {
+            None
+        };
+        if let Some(volume) = volume_change {
+            audio.volume = volume;
+            recording.set_sound(volume, audio.waveform);
+            interface.show_toast(&format!("Volume {}%", volume));
+            save_setting(&config_path, "audio", "volume", &volume.to_string());
+        }
+        if interface.window.is_key_pressed(Key::M, KeyRepeat::No) {
+            audio.muted = !audio.muted;
+            audio.clear();
+            interface.show_toast(if audio.muted { "Muted" } else { "Sound on" });
+            save_setting(&config_path, "audio", "muted", &audio.muted.to_string());
+        }
+
+        // F4 shows or hides the cycle budget graph
+        if interface.window.is_key_pressed(Key::F4, KeyRepeat::No) {
+            cycle_graph = match cycle_graph {
+                Some(_) => None,
+                None => Some(CycleGraph::new()),
+            };
+        }
+
+        // F5 shows or hides the input display
+        if interface.window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            input_display = match input_display {
+                Some(_) => None,
+                None => Some(InputDisplay::new()),
+            };
+        }
+
+        // F3 switches to the next palette
+        if interface.window.is_key_pressed(Key::F3, KeyRepeat::No) {
+            interface.palette = interface.palette.next();
+            interface.show_toast(&format!("Palette: {}", interface.palette.name));
+            save_setting(&config_path, "display", "palette", &format!("\"{}\"", interface.palette.name));
+            config.set("display", "palette", interface.palette.name); // So it's still the usual palette on a ROM switch
+        }
+
+        // Speed hotkeys: [ and ] step the CPU speed down and up, and the speed is remembered for the ROM. A replay
+        // runs at the speed it was recorded at, so the speed can't change while one plays or records
+        let window = &interface.window;
+        let slower = window.is_key_pressed(Key::LeftBracket, KeyRepeat::Yes);
+        let faster = window.is_key_pressed(Key::RightBracket, KeyRepeat::Yes);
+        if (slower || faster) && (playback.is_some() || replay_recording.is_some()) {
+            interface.show_toast("Speed is fixed during replays");
+        } else if slower || faster {
+            let step = if faster {
+                CPU_HZ_STEPS.iter().find(|&&step| step > cpu_hz)
+            } else {
+                CPU_HZ_STEPS.iter().rev().find(|&&step| step < cpu_hz)
+            };
+            if let Some(&step) = step {
+                cpu_hz = step;
+                cycle_units = 0;
+                save_setting(&config_path, &config::rom_section(&rom_filepath), "cpu_hz", &cpu_hz.to_string());
+            }
+            interface.show_toast(&format!("Speed {} Hz", cpu_hz));
+        }
+
         // Process user input
-        chip8.interface.process_keys();
+        interface.process_keys();
+        #[cfg(feature = "api")]
+        if let Some(api) = &api {
+            for (key, &held) in interface.keypad.iter_mut().zip(&api.keys) {
+                *key |= held;
+            }
+        }
+
+        // The devices' clock is the host's; changes are logged so stepping back reads the clock as it was
+        if let Some(devices) = &mut chip8.devices {
+            let clock = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            let seconds = (clock.as_secs() % 60) as u8;
+            if seconds != devices.seconds {
+                devices.seconds = seconds;
+                history.log(Event::Clock(seconds));
+            }
+        }
+
+        // Run the ticks that are due
+        while tick_backlog >= tick_time {
+            // Feed in the replay's input, or record ours
+            if let Some(replay) = &playback {
+                interface.set_keypad(replay.input_at(tick));
+            } else if let Some(replay) = &mut replay_recording {
+                replay.record_input(tick, &interface.keypad);
+            }
+            chip8.keypad = interface.keypad;
+            history.log(Event::Keys(chip8.keypad));
+            if let Some(input_display) = &mut input_display {
+                input_display.record(&chip8.keypad);
+            }
+
+            // Process CPU cycles
+            cycle_units += cpu_hz;
+            let cycles_due = cycle_units / TIMER_HZ as u32;
+            cycle_units %= TIMER_HZ as u32;
+            let mut cycles_run = cycles_due;
+            let draws_before = chip8.draws();
+            if let Some(debugger) = debugger.as_mut().filter(|debugger| debugger.is_watching()) {
+                // The debugger checks its conditions after every instruction, which rules out block translation;
+                // if it pauses mid-tick, the rest of the tick's instructions are skipped
+                for cycle in 1..=cycles_due {
+                    chip8.emulate_cycle();
+                    history.log(Event::Cycles(1));
+                    if debugger.check(&chip8, &mut save_slots) {
+                        cycles_run = cycle;
+                        break;
+                    }
+                }
+                #[cfg(feature = "jit")]
+                {
+                    jit = Jit::new();
+                }
+            } else {
+                #[cfg(not(feature = "jit"))]
+                for _ in 0..cycles_due {
+                    chip8.emulate_cycle();
+                }
+                #[cfg(feature = "jit")]
+                jit.run(&mut chip8, cycles_due as usize);
+                history.log(Event::Cycles(cycles_due));
+            }
+            if let Some(cycle_graph) = &mut cycle_graph {
+                cycle_graph.record(cycles_run, (chip8.draws() - draws_before) as u32);
+            }
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &metrics {
+                metrics.instructions.fetch_add(cycles_run as u64, Ordering::Relaxed);
+            }
+
+            // Tick the timers
+            let beeping = chip8.update_timers();
+            history.log(Event::Timers);
+            audio.tick(beeping && !fast_forward);
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepad) = &mut interface.gamepad {
+                gamepad.rumble(beeping, chip8.take_collision());
+            }
+            if splash_until.is_none() && recording.is_active() {
+                recording.tick(chip8.display_rows(), beeping);
+            }
+            if let (Some(announcer), None) = (&mut announcer, splash_until) {
+                announcer.observe(&chip8, beeping, &watches);
+            }
+            tick += 1;
+            tick_backlog -= tick_time;
+
+            // Report faults, and inject some more if fuzzing
+            let mut trapped = false;
+            let mut faulted = false;
+            for fault in chip8.take_faults() {
+                faulted = true;
+                if strict {
+                    // Only the first fault stops the game; any others in the same frame just add to the exit code
+                    if !finished {
+                        print!("{}", console::diagnostic(&fault, &chip8, tick));
+                        println!("Strict mode: stopped at the first fault");
+                    }
+                    finished = true;
+                } else if let Some(bit_flipper) = &mut bit_flipper {
+                    bit_flipper.observe(tick, fault);
+                } else if reported_faults.contains(&fault) {
+                    println!("{}", fault);
+                } else {
+                    // The full report the first time, and just the one line if it happens again
+                    print!("{}", console::diagnostic(&fault, &chip8, tick));
+                    reported_faults.push(fault);
+                }
+                trapped |= matches!(fault, Fault::ReservedWrite { .. });
+                let code = match fault {
+                    Fault::UnknownOpcode { .. } => console::EXIT_UNKNOWN_OPCODE,
+                    _ => console::EXIT_FAULT,
+                };
+                exit_code = exit_code.max(code);
+                if let Some(announcer) = &mut announcer {
+                    announcer.fault(&fault);
+                }
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &metrics {
+                    metrics.faults.fetch_add(1, Ordering::Relaxed);
+                    if matches!(fault, Fault::UnknownOpcode { .. }) {
+                        metrics.unknown_opcodes.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            if let (Some(bit_flipper), None) = (&mut bit_flipper, splash_until) {
+                for (addr, value) in bit_flipper.tick(&mut chip8, tick) {
+                    history.log(Event::Poke(addr, value));
+                    #[cfg(feature = "jit")]
+                    jit.poked(addr);
+                }
+            }
+
+            if finished {
+                break;
+            }
+
+            // With --frames, stop once the game (not counting the splash screen) has run for that long
+            if splash_until.is_none() {
+                game_frames += 1;
+            }
+            if frame_limit.is_some_and(|limit| game_frames >= limit) {
+                finished = true;
+                break;
+            }
+
+            // Autosave, unless the game has faulted since the last one (it's likely broken by then)
+            faulted_since_autosave |= faulted;
+            if autosave_ticks > 0 && splash_until.is_none() && attract.is_none() {
+                ticks_since_autosave += 1;
+                if ticks_since_autosave >= autosave_ticks {
+                    ticks_since_autosave = 0;
+                    if !std::mem::take(&mut faulted_since_autosave) {
+                        if let Err(e) = save_slots.autosave(&chip8.snapshot()) {
+                            println!("{}", e);
+                        }
+                    }
+                }
+            }
+
+            // Announce any achievements that were just unlocked
+            if let (Some(achievements), None) = (&mut achievements, splash_until) {
+                for name in achievements.check(&chip8) {
+                    status!("Achievement unlocked at frame {}: {}", tick, name);
+                    interface.show_toast(&format!("Achievement: {}", name));
+                    if let Some(announcer) = &mut announcer {
+                        announcer.say(&format!("Achievement unlocked: {}", name));
+                    }
+                }
+            }
+
+            // Once the replay is over, hand control back to the player
+            if playback.as_ref().is_some_and(|replay| tick >= replay.end) {
+                status!("Replay finished");
+                playback = None;
+            }
+
+            // A trapped write pauses the game (as if P was pressed) so the state can be looked at
+            if trapped {
+                status!("Paused on write to reserved memory; press P to continue");
+                timeline_position = Some(history.len().saturating_sub(1));
+                audio.clear();
+                tick_backlog = Duration::ZERO;
+                break;
+            }
+
+            // Otherwise a fault pauses the game to offer the autosave, as long as going back won't break a replay
+            let replaying = playback.is_some() || replay_recording.is_some();
+            if faulted && offer_on_fault && splash_until.is_none() && save_slots.has_autosave() && !replaying {
+                status!("Paused after a fault; the autosave can be restored");
+                recovery_prompt = Some(true);
+                audio.clear();
+                tick_backlog = Duration::ZERO;
+                break;
+            }
 
-        // Process CPU cycles
-        for _ in 0..cycles_per_frame {
-            chip8.emulate_cycle();
+            // Likewise when a debugger condition is met
+            if debugger.as_ref().is_some_and(|debugger| debugger.paused) {
+                audio.clear();
+                tick_backlog = Duration::ZERO;
+                break;
+            }
         }
 
-        // Render the display
-        chip8.interface.render_screen();
+        // Pass on what the game wrote to the console device; this is the game's output, so --quiet leaves it be
+        if let Some(devices) = &mut chip8.devices {
+            let output = devices.take_output();
+            if !output.is_empty() {
+                let mut stdout = std::io::stdout();
+                let _ = stdout.write_all(&output).and_then(|()| stdout.flush());
+            }
+        }
+
+        // Render the display, with the captions for any replay notes that are up
+        interface.draw_display(chip8.display_rows());
+        if let Some(replay) = &playback {
+            let notes: Vec<&str> = replay.notes_at(tick).map(|note| note.text.as_str()).collect();
+            if !notes.is_empty() {
+                let mut canvas = interface.canvas();
+                let max_chars = canvas.width / (CHAR_WIDTH * canvas.text_scale) - 1;
+                let lines: Vec<String> = notes.iter().flat_map(|text| wrap_text(text, max_chars)).collect();
+                let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+                canvas.draw_caption(&lines, 0xFFFF80);
+            }
+        }
+        draw_watches(&mut interface, &watches, &chip8);
+        if let Some(cycle_graph) = &cycle_graph {
+            cycle_graph.draw(&mut interface.canvas(), cpu_hz / TIMER_HZ as u32);
+        }
+        if let Some(input_display) = &input_display {
+            input_display.draw(&mut interface.canvas());
+        }
+        key_wait_frames = if chip8.waiting_for_key() && splash_until.is_none() { key_wait_frames + 1 } else { 0 };
+        if key_wait_indicator && key_wait_frames >= KEY_WAIT_FRAMES {
+            draw_key_wait(&mut interface, key_wait_frames);
+        }
+        if fast_forward {
+            interface.canvas().draw_toast(&format!("FAST FORWARD {}X", fast_forward_speed), 0xFFD700);
+        }
+        interface.present();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &metrics {
+            metrics.frames.fetch_add(1, Ordering::Relaxed);
+            metrics.audio_underruns.store(audio.underruns(), Ordering::Relaxed);
+        }
+        if let (Some(view), Some(heat_map)) = (&mut heat_map_view, &mut chip8.heat_map) {
+            view.render(heat_map);
+        }
+
+        // Record history for the timeline (the splash screen isn't worth going back to)
+        if splash_until.is_none() {
+            history.record(&chip8);
+        }
+        if finished {
+            break;
+        }
+    }
+
+    // Finish any recordings so the files are complete, and mark the session as having ended cleanly
+    stop_rumble(&mut interface);
+    recording.finish();
+    save_slots.end_session();
+    if let Some(bit_flipper) = &bit_flipper {
+        bit_flipper.report();
+    }
+    if let Some(coverage) = &chip8.coverage {
+        print!("{}", coverage.report());
+    }
+    if let (Some(replay), Some(path)) = (&replay_recording, &replay_recording_path) {
+        match replay.save(Path::new(path)) {
+            Ok(()) => status!("Saved replay to {}", path),
+            Err(e) => println!("{}", e),
+        }
+    }
+    if finished {
+        std::process::exit(exit_code);
+    }
+}
+
+// Show the watched values in the corner of the screen
+fn draw_watches(interface: &mut Interface, watches: &Watches, chip8: &Chip8) {
+    if watches.is_empty() {
+        return;
+    }
+    let lines = watches.lines(chip8);
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    interface.canvas().draw_panel(&lines, 0x80FF80);
+}
+
+// A small "press a key" in the bottom-right corner, blinking slowly so it reads as waiting rather than stuck
+fn draw_key_wait(interface: &mut Interface, frames: u32) {
+    if frames / KEY_WAIT_FRAMES % 2 == 1 {
+        return;
+    }
+    let mut canvas = interface.canvas();
+    let text = "PRESS A KEY";
+    let margin = canvas.text_scale * 2;
+    let x = canvas.width.saturating_sub(text.len() * CHAR_WIDTH * canvas.text_scale + margin);
+    let y = canvas.height.saturating_sub(CHAR_HEIGHT * canvas.text_scale + margin);
+    canvas.draw_text(x, y, text, 0x808080);
+}
+
+// Stop any controller rumble while the game isn't running; the sound timer's rumble only stops when a tick says so,
+// so pausing mid-beep would otherwise leave it going
+#[cfg_attr(not(feature = "gamepad"), allow(unused_variables))]
+fn stop_rumble(interface: &mut Interface) {
+    #[cfg(feature = "gamepad")]
+    if let Some(gamepad) = &mut interface.gamepad {
+        gamepad.rumble(false, false);
+    }
+}
+
+// Save a setting changed while playing to the config file; failing to save isn't worth stopping the game over
+fn save_setting(config_path: &str, section: &str, key: &str, value: &str) {
+    if let Err(e) = config::save_value(Path::new(config_path), section, key, value) {
+        eprintln!("{}", e);
+    }
+}
+
+// The speed to run a ROM at: the one picked for it with [ and ] (or set by hand as cpu_hz in its section of the
+// config file), or else the usual speed for its platform, going by its `platform` tag in the config file or else
+// the quirk profile, or else CPU_HZ
+fn cpu_hz_for(config: &Config, rom_filepath: &str, quirk_profile: QuirkProfile) -> u32 {
+    let section = config::rom_section(rom_filepath);
+    let platform = match config.get(&section, "platform") {
+        Some(name) => QuirkProfile::from_name(name).unwrap_or_else(|| {
+            println!("Unknown platform '{}' in [{}]; expected one of the quirk profiles", name, section);
+            quirk_profile
+        }),
+        None => quirk_profile,
+    };
+    let usual = platform.instructions_per_frame().map_or(CPU_HZ as u32, |instructions| instructions * TIMER_HZ as u32);
+    config.get_or(&section, "cpu_hz", usual)
+}
+
+// The palette to draw a ROM in: the one given by --palette, or else the ROM's own colors or palette in its section
+// of the config file, or else the usual one from the [display] section
+fn palette_for(name: Option<&str>, config: &Config, rom_filepath: &str) -> Result<Palette, String> {
+    let section = config::rom_section(rom_filepath);
+    if let Some(colors) = config.get(&section, "colors").filter(|_| name.is_none()) {
+        return Palette::from_colors(colors)
+            .map_err(|e| format!("Invalid colors in [{}] section of config file: {}", section, e));
+    }
+    let name = name.or(config.get(&section, "palette")).or(config.get("display", "palette"));
+    name.map_or(Ok(palette::PALETTES[0]), |name| {
+        Palette::from_name(name).ok_or_else(|| {
+            let names: Vec<&str> = palette::PALETTES.iter().map(|palette| palette.name).collect();
+            format!("Unknown palette '{}'; expected one of: {}", name, names.join(", "))
+        })
+    })
+}
+
+// Set the machine up the way the attract-mode demo on screen was recorded, returning its quirk profile and
+// overrides to carry on with
+fn apply_demo_setup(demos: &Attract, chip8: &mut Chip8) -> (QuirkProfile, Vec<(String, bool)>) {
+    let (profile, setup) = demos.setup();
+    chip8.quirks = setup.quirks;
+    chip8.load_address = setup.load_address;
+    chip8.entry_point = setup.load_address;
+    chip8.display_height = profile.display_height();
+    (profile, setup.overrides.to_vec())
+}
+
+// The keymap to play a ROM with: the one given by --keymap, or else the one set for the ROM in the config file,
+// or else the default one from the [input] section
+fn keymap_for(name: Option<&str>, config: &Config, rom_filepath: &str) -> Result<Keymap, String> {
+    let name = name.or(config.get(&config::rom_section(rom_filepath), "keymap")).or(config.get("input", "keymap"));
+    name.map_or(Ok(Keymap::standard()), |name| Keymap::from_name(name, config))
+}
+
+// Parse a memory address given on the command line, in hex with or without 0x
+fn parse_address(flag: &str, text: Option<String>) -> u16 {
+    let text = text.unwrap_or_default();
+    let address = u16::from_str_radix(text.trim_start_matches("0x"), 16).ok().filter(|&address| address < 0x1000);
+    address.unwrap_or_else(|| {
+        println!("Invalid address '{}' for {}; expected 0x000-0xFFF, e.g. 0x600", text, flag);
+        std::process::exit(console::EXIT_ERROR);
+    })
+}
+
+// A profile's quirks with the ones given by --quirk switched on or off
+fn quirks_with_overrides(profile: QuirkProfile, overrides: &[(String, bool)]) -> Quirks {
+    let mut quirks = profile.quirks();
+    for (name, enabled) in overrides {
+        if !quirks.set(name, *enabled) {
+            println!("Unknown quirk '{}'; see quirks.rs for the available quirks", name);
+            std::process::exit(console::EXIT_ERROR);
+        }
     }
+    quirks
 }
@@ -0,0 +1,70 @@
+// Prometheus metrics endpoint (enabled with `cargo run --features metrics -- <rom> --metrics <address>`), for
+// keeping an eye on long-running kiosk and arcade setups. Serves the counters below as plain text at any path:
+//
+//   curl http://127.0.0.1:9184/metrics
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Requests are answered one at a time, so a client that connects and then sends nothing (or stops reading) is
+// dropped after this long rather than holding up the next scrape
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+pub struct Metrics {
+    pub frames: AtomicU64,          // Frames rendered
+    pub instructions: AtomicU64,    // Instructions executed
+    pub unknown_opcodes: AtomicU64, // Unknown opcodes run into (these are also counted as faults)
+    pub faults: AtomicU64,          // All faults (see Fault in chip8.rs)
+    pub audio_underruns: AtomicU64, // Times the sound card ran out of sound to play
+}
+
+impl Metrics {
+    // The counters in Prometheus' text format
+    fn render(&self) -> String {
+        let counters = [
+            ("chip8_frames_total", "Frames rendered.", &self.frames),
+            ("chip8_instructions_total", "Instructions executed.", &self.instructions),
+            ("chip8_unknown_opcodes_total", "Unknown opcodes encountered.", &self.unknown_opcodes),
+            ("chip8_faults_total", "Faults of any kind, including unknown opcodes.", &self.faults),
+            ("chip8_audio_underruns_total", "Times the audio output ran out of samples.", &self.audio_underruns),
+        ];
+
+        let mut text = String::new();
+        for (name, help, counter) in counters {
+            text += &format!("# HELP {} {}\n# TYPE {} counter\n", name, help, name);
+            text += &format!("{} {}\n", name, counter.load(Ordering::Relaxed));
+        }
+        text
+    }
+}
+
+// Serve the metrics from a background thread
+pub fn serve(address: &str, metrics: Arc<Metrics>) -> Result<(), String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("Error starting metrics endpoint on {}: {}", address, e))?;
+    status!("Serving metrics on http://{}/metrics", address);
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let _ = stream.set_read_timeout(Some(TIMEOUT));
+            let _ = stream.set_write_timeout(Some(TIMEOUT));
+
+            // Every request gets the metrics, so there's no need to look at it beyond reading it in
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+
+            let body = metrics.render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    Ok(())
+}
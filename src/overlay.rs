@@ -0,0 +1,195 @@
+// Drawing helpers for things shown on top of the emulated display (timeline, captions, debug info, ...)
+//
+// The window's framebuffer is drawn at a higher resolution than the 64x32 display (see Interface::new),
+// so overlays can use text that is much finer than a CHIP-8 pixel.
+
+// 3x5 pixel font covering ASCII 0x20 (space) to 0x5F (underscore); lowercase letters are drawn as uppercase.
+// Each glyph is 5 rows of 3 bits, with the leftmost pixel in the highest bit.
+const FONT: [[u8; 5]; 64] = [
+    [0, 0, 0, 0, 0], // (space)
+    [2, 2, 2, 0, 2], // !
+    [5, 5, 0, 0, 0], // "
+    [5, 7, 5, 7, 5], // #
+    [3, 6, 2, 3, 6], // $
+    [5, 1, 2, 4, 5], // %
+    [2, 5, 2, 5, 3], // &
+    [2, 2, 0, 0, 0], // '
+    [1, 2, 2, 2, 1], // (
+    [4, 2, 2, 2, 4], // )
+    [0, 5, 2, 5, 0], // *
+    [0, 2, 7, 2, 0], // +
+    [0, 0, 0, 2, 4], // ,
+    [0, 0, 7, 0, 0], // -
+    [0, 0, 0, 0, 2], // .
+    [1, 1, 2, 4, 4], // /
+    [7, 5, 5, 5, 7], // 0
+    [2, 6, 2, 2, 7], // 1
+    [7, 1, 7, 4, 7], // 2
+    [7, 1, 7, 1, 7], // 3
+    [5, 5, 7, 1, 1], // 4
+    [7, 4, 7, 1, 7], // 5
+    [7, 4, 7, 5, 7], // 6
+    [7, 1, 2, 2, 2], // 7
+    [7, 5, 7, 5, 7], // 8
+    [7, 5, 7, 1, 7], // 9
+    [0, 2, 0, 2, 0], // :
+    [0, 2, 0, 2, 4], // ;
+    [1, 2, 4, 2, 1], // <
+    [0, 7, 0, 7, 0], // =
+    [4, 2, 1, 2, 4], // >
+    [7, 1, 2, 0, 2], // ?
+    [7, 5, 7, 4, 7], // @
+    [2, 5, 7, 5, 5], // A
+    [6, 5, 6, 5, 6], // B
+    [3, 4, 4, 4, 3], // C
+    [6, 5, 5, 5, 6], // D
+    [7, 4, 6, 4, 7], // E
+    [7, 4, 6, 4, 4], // F
+    [3, 4, 5, 5, 3], // G
+    [5, 5, 7, 5, 5], // H
+    [7, 2, 2, 2, 7], // I
+    [1, 1, 1, 5, 2], // J
+    [5, 5, 6, 5, 5], // K
+    [4, 4, 4, 4, 7], // L
+    [5, 7, 7, 5, 5], // M
+    [6, 5, 5, 5, 5], // N
+    [2, 5, 5, 5, 2], // O
+    [6, 5, 6, 4, 4], // P
+    [2, 5, 5, 6, 3], // Q
+    [6, 5, 6, 5, 5], // R
+    [3, 4, 2, 1, 6], // S
+    [7, 2, 2, 2, 2], // T
+    [5, 5, 5, 5, 7], // U
+    [5, 5, 5, 5, 2], // V
+    [5, 5, 7, 7, 5], // W
+    [5, 5, 2, 5, 5], // X
+    [5, 5, 2, 2, 2], // Y
+    [7, 1, 2, 4, 7], // Z
+    [3, 2, 2, 2, 3], // [
+    [4, 4, 2, 1, 1], // \
+    [6, 2, 2, 2, 6], // ]
+    [2, 5, 0, 0, 0], // ^
+    [0, 0, 0, 0, 7], // _
+];
+
+// Width and height of a character cell (glyph plus one pixel of spacing) before scaling
+pub const CHAR_WIDTH: usize = 4;
+pub const CHAR_HEIGHT: usize = 6;
+
+// A framebuffer that can be drawn on; everything is clipped to its bounds
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u32],
+    pub width: usize,
+    pub height: usize,
+    pub text_scale: usize, // Size of one font pixel in framebuffer pixels
+}
+
+impl Canvas<'_> {
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        for row in y..(y + height).min(self.height) {
+            let start = row * self.width;
+            let line = &mut self.pixels[start + x.min(self.width)..start + (x + width).min(self.width)];
+            line.fill(color);
+        }
+    }
+
+    // Draw an image scaled to fill a rectangle, with nearest-neighbour sampling
+    pub fn draw_image(&mut self, x: usize, y: usize, width: usize, height: usize, image: &[u32], image_width: usize) {
+        let image_height = image.len() / image_width;
+        for row in 0..height.min(self.height.saturating_sub(y)) {
+            let source = &image[row * image_height / height * image_width..][..image_width];
+            for column in 0..width.min(self.width.saturating_sub(x)) {
+                self.pixels[(y + row) * self.width + x + column] = source[column * image_width / width];
+            }
+        }
+    }
+
+    // Draw a single line of text with its top-left corner at (x, y)
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: u32) {
+        let scale = self.text_scale;
+        for (index, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            let char_x = x + index * CHAR_WIDTH * scale;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.fill_rect(char_x + column * scale, y + row * scale, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // Draw lines of text on a dark box along the bottom of the screen (for captions and messages)
+    pub fn draw_caption(&mut self, lines: &[&str], color: u32) {
+        let line_height = CHAR_HEIGHT * self.text_scale;
+        let padding = self.text_scale * 2;
+        let box_height = lines.len() * line_height + padding * 2;
+        let top = self.height.saturating_sub(box_height);
+
+        self.fill_rect(0, top, self.width, box_height, 0x202020);
+        for (index, line) in lines.iter().enumerate() {
+            self.draw_text(padding, top + padding + index * line_height, line, color);
+        }
+    }
+
+    // Draw lines of text on a dark box in the top-left corner (for debug info)
+    pub fn draw_panel(&mut self, lines: &[&str], color: u32) {
+        let line_height = CHAR_HEIGHT * self.text_scale;
+        let padding = self.text_scale * 2;
+        let longest = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let width = longest * CHAR_WIDTH * self.text_scale + padding * 2;
+        let height = lines.len() * line_height + padding * 2;
+
+        self.fill_rect(0, 0, width, height, 0x202020);
+        for (index, line) in lines.iter().enumerate() {
+            self.draw_text(padding, padding + index * line_height, line, color);
+        }
+    }
+
+    // Draw a one-line message in a box at the top of the screen, for short notifications
+    pub fn draw_toast(&mut self, text: &str, color: u32) {
+        let padding = self.text_scale * 2;
+        let width = text.chars().count() * CHAR_WIDTH * self.text_scale + padding * 2;
+        let height = CHAR_HEIGHT * self.text_scale + padding * 2;
+        let left = self.width.saturating_sub(width) / 2;
+
+        self.fill_rect(left, padding, width, height, 0x202020);
+        self.draw_text(left + padding, padding * 2, text, color);
+    }
+}
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    match c as u32 {
+        0x20..=0x5F => &FONT[c as usize - 0x20],
+        _ => &FONT['?' as usize - 0x20],
+    }
+}
+
+// Split text into lines of at most `max_chars` characters, breaking at spaces where possible
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+
+        // Hard-break words that are too long for a line on their own
+        while let Some((split, _)) = line.char_indices().nth(max_chars) {
+            let rest = line.split_off(split);
+            lines.push(std::mem::replace(&mut line, rest));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
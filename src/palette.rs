@@ -0,0 +1,86 @@
+// Display palettes. A palette has a color for each combination of the two display planes XO-CHIP has (off,
+// first plane, second plane, both); plain CHIP-8 only has the first plane, so it only uses the first two colors.
+//
+// The color-blind palettes use colors from the Okabe-Ito set, and were checked to stay clearly apart (a CIELAB
+// distance of at least 60 between every pair) under simulated deuteranopia and protanopia (Machado et al., 2009)
+// as well as normal vision. The high-contrast palette is pure black and white, and works best with outlines on
+// (see Interface::draw_display), which separate lit pixels so shapes are easier to make out.
+//
+// Besides these, a ROM can be given its own colors in its section of the config file (see config::rom_section),
+// e.g. the ones its author intended:
+//
+//   [rom.superneatboy]
+//   colors = "#000000 #FFFFFF #FF6600 #662200"   # off, first plane, second plane, both (or just the first two)
+//
+// Only the first two are used for now; the others are for when the display gets XO-CHIP's second plane.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub name: &'static str,
+    pub colors: [u32; 4], // 0x00RRGGBB for: off, first plane, second plane, both planes
+}
+
+pub const PALETTES: [Palette; 7] = [
+    Palette { name: "classic", colors: [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555] },
+    Palette { name: "amber", colors: [0x1A1000, 0xFFB000, 0xB37B00, 0x664600] },
+    Palette { name: "green", colors: [0x001A00, 0x33FF33, 0x22AA22, 0x115511] },
+    Palette { name: "octo", colors: [0x996600, 0xFFCC00, 0xFF6600, 0x662200] }, // Octo's default colors
+    Palette { name: "deuteranopia", colors: [0x000000, 0xF0E442, 0x0072B2, 0xFFFFFF] },
+    Palette { name: "protanopia", colors: [0x000000, 0xFFFFFF, 0xE69F00, 0x0072B2] },
+    Palette { name: "high-contrast", colors: [0x000000, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF] },
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        PALETTES.iter().copied().find(|palette| palette.name.eq_ignore_ascii_case(name))
+    }
+
+    // Parse a list of 2 or 4 colors like "#000000 #FFFFFF" into a custom palette; with only 2, the second plane's
+    // colors are filled in halfway between them
+    pub fn from_colors(text: &str) -> Result<Self, String> {
+        let colors = text
+            .split([' ', ','])
+            .filter(|color| !color.is_empty())
+            .map(|color| {
+                let digits = color.strip_prefix('#').unwrap_or(color);
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() == 6)
+                    .ok_or_else(|| format!("Expected a color like #FF8800, found `{}`", color))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        let colors = match colors[..] {
+            [off, on] => [off, on, blend(off, on), blend(off, on)],
+            [off, first, second, both] => [off, first, second, both],
+            _ => return Err(format!("Expected 2 or 4 colors, found {}", colors.len())),
+        };
+        Ok(Palette { name: "custom", colors })
+    }
+
+    // The palette after this one in PALETTES, for cycling through them
+    pub fn next(&self) -> Self {
+        let index = PALETTES.iter().position(|palette| palette.name == self.name);
+        PALETTES[index.map_or(0, |index| (index + 1) % PALETTES.len())]
+    }
+}
+
+// Average of two colors, channel by channel
+pub fn blend(a: u32, b: u32) -> u32 {
+    // The bits both have, plus half of the bits only one has (masked so no channel spills into the next)
+    (a & b) + (((a ^ b) >> 1) & 0x7F7F7F)
+}
+
+// Color part of the way from a to b (0 is all a, 1 all b), channel by channel
+pub fn mix(a: u32, b: u32, amount: f32) -> u32 {
+    let channel = |shift: u32| {
+        let (from, to) = (((a >> shift) & 0xFF) as f32, ((b >> shift) & 0xFF) as f32);
+        ((from + (to - from) * amount).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+// Black or white, whichever a color is closer to in brightness
+pub fn threshold(color: u32) -> u32 {
+    let (r, g, b) = ((color >> 16) & 0xFF, (color >> 8) & 0xFF, color & 0xFF);
+    if r * 299 + g * 587 + b * 114 >= 128 * 1000 { 0xFFFFFF } else { 0x000000 }
+}
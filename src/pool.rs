@@ -0,0 +1,102 @@
+// Many independent headless instances run across threads, for training agents on lots of games at once or
+// hammering a ROM with random input (Monte-Carlo fuzzing)
+//
+// Each instance is a gym::Env with its own seed, so they stay deterministic individually no matter how the
+// threads are scheduled. The core has no global state, so instances never interfere with each other.
+use crate::gym::Env;
+use std::thread;
+
+pub struct Chip8Pool {
+    envs: Vec<Env>,
+    threads: usize, // Number of worker threads the instances are split between
+}
+
+impl Chip8Pool {
+    // `count` instances of the same ROM, seeded with first_seed, first_seed + 1, ...; fails if the ROM doesn't fit
+    // in memory
+    pub fn new(rom: &[u8], cpu_hz: u32, first_seed: u64, count: usize) -> Result<Self, &'static str> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Ok(Chip8Pool {
+            envs: (0..count as u64).map(|n| Env::new(rom, cpu_hz, first_seed + n)).collect::<Result<_, _>>()?,
+            threads,
+        })
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    // Individual instances, e.g. to set max_steps/done_when or read memory
+    pub fn envs(&mut self) -> &mut [Env] {
+        &mut self.envs
+    }
+
+    pub fn reset_all(&mut self) -> Vec<[u64; 32]> {
+        self.envs.iter_mut().map(Env::reset).collect()
+    }
+
+    // Step every instance by one frame, each with its own keys; returns each instance's (display, done)
+    pub fn step(&mut self, keys: &[[bool; 16]]) -> Vec<([u64; 32], bool)> {
+        assert_eq!(keys.len(), self.envs.len(), "need one set of keys per instance");
+
+        let mut results = vec![([0; 32], false); self.envs.len()];
+        let chunk_size = self.chunk_size();
+        thread::scope(|scope| {
+            let chunks = self.envs.chunks_mut(chunk_size).zip(keys.chunks(chunk_size));
+            for ((envs, keys), results) in chunks.zip(results.chunks_mut(chunk_size)) {
+                scope.spawn(move || {
+                    for ((env, keys), result) in envs.iter_mut().zip(keys).zip(results) {
+                        *result = env.step(*keys);
+                    }
+                });
+            }
+        });
+        results
+    }
+
+    // Run every instance for up to `frames` frames (or until its episode ends), asking `policy` for the keys to
+    // hold each frame given the instance's index and display. This only starts the threads once, so it is much
+    // faster than calling step in a loop when the policy doesn't need to see all the instances together.
+    // Returns the number of frames each instance ran for.
+    pub fn run<F>(&mut self, frames: u64, policy: F) -> Vec<u64>
+    where
+        F: Fn(usize, &[u64; 32]) -> [bool; 16] + Sync,
+    {
+        let mut results = vec![0; self.envs.len()];
+        let chunk_size = self.chunk_size();
+        let policy = &policy;
+        thread::scope(|scope| {
+            let chunks = self.envs.chunks_mut(chunk_size).zip(results.chunks_mut(chunk_size));
+            for (chunk, (envs, results)) in chunks.enumerate() {
+                scope.spawn(move || {
+                    for (offset, (env, result)) in envs.iter_mut().zip(results).enumerate() {
+                        let index = chunk * chunk_size + offset;
+                        let mut display = env.display();
+                        for frame in 1..=frames {
+                            let (next, done) = env.step(policy(index, &display));
+                            display = next;
+                            *result = frame;
+                            if done {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        results
+    }
+
+    // Instances per thread, so that every thread gets a share
+    fn chunk_size(&self) -> usize {
+        self.envs.len().div_ceil(self.threads).max(1)
+    }
+}
@@ -0,0 +1,192 @@
+// Quirks are the small behavioral differences between CHIP-8 interpreters over the years;
+// ROMs written for one interpreter often rely on its particular quirks to run correctly.
+// See https://github.com/Timendus/chip8-test-suite#quirks-test for a good overview.
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuirkProfile {
+    Modern,    // Behavior most modern emulators (and this one, originally) default to
+    Vip,       // Original COSMAC VIP interpreter
+    Schip,     // SUPER-CHIP 1.1 on the HP-48
+    XoChip,    // Octo's XO-CHIP
+    Eti660,    // ETI-660, with a 64x48 display and programs that load and start at 0x600 (see load_address)
+    Dream6800, // DREAM 6800 running CHIPOS, the Australian hobby computer's CHIP-8 interpreter
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // When VF is also the destination of 8XY4/8XY5/8XY6/8XY7/8XYE (X == F), decides which write wins.
+    // Every known interpreter writes the result first and the flag last, so VF ends up holding the flag;
+    // setting this makes the result win instead, which some emulators do by accident.
+    //   e.g. VF = 0x80, 8FF4 (VF = VF + VF): flag last => VF = 0x01, result last => VF = 0x00
+    //        VF = 0x01, 8FF5 (VF = VF - VF): flag last => VF = 0x01, result last => VF = 0x00
+    //        VF = 0x02, 8FF6 (VF = VF >> 1): flag last => VF = 0x00, result last => VF = 0x01
+    //        VF = 0xFF, 8FFE (VF = VF << 1): flag last => VF = 0x01, result last => VF = 0xFE
+    pub vf_result_last: bool,
+
+    // FX1E sets VF to 1 when I goes past 0xFFF (and to 0 otherwise), as the Amiga interpreter did;
+    // Spacefight 2091! relies on this. I itself always wraps around within the 4KB address space.
+    pub i_overflow_flag: bool,
+
+    // BNNN is treated as BXNN and jumps to XNN + VX instead of NNN + V0, a mistake in CHIP-48
+    // that SUPER-CHIP inherited and several games of that era came to depend on.
+    pub jump_vx: bool,
+
+    // How far FX55/FX65 move I after storing/loading registers. The COSMAC VIP left I pointing just past
+    // the last register (X + 1), SUPER-CHIP 1.0 was off by one (X) and SUPER-CHIP 1.1 left I alone.
+    pub memory_increment: MemoryIncrement,
+
+    // 8XY1/8XY2/8XY3 (OR/AND/XOR) also reset VF to 0, a side effect of how the COSMAC VIP implemented them.
+    pub vf_reset: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryIncrement {
+    Unchanged,  // I is left untouched
+    ByX,        // I = I + X
+    ByXPlusOne, // I = I + X + 1
+}
+
+impl QuirkProfile {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "modern" => Some(QuirkProfile::Modern),
+            "vip" | "chip8" => Some(QuirkProfile::Vip),
+            "schip" | "superchip" => Some(QuirkProfile::Schip),
+            "xochip" | "xo-chip" => Some(QuirkProfile::XoChip),
+            "eti660" | "eti-660" => Some(QuirkProfile::Eti660),
+            "dream6800" | "dream-6800" | "chipos" => Some(QuirkProfile::Dream6800),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirkProfile::Modern => "modern",
+            QuirkProfile::Vip => "vip",
+            QuirkProfile::Schip => "schip",
+            QuirkProfile::XoChip => "xochip",
+            QuirkProfile::Eti660 => "eti660",
+            QuirkProfile::Dream6800 => "dream6800",
+        }
+    }
+
+    // Where programs for the machine are loaded and start running. The ETI-660 kept its interpreter and display
+    // memory below 0x600, and its programs were written for that address
+    pub fn load_address(self) -> u16 {
+        match self {
+            QuirkProfile::Eti660 => 0x600,
+            _ => crate::PROGRAM_START,
+        }
+    }
+
+    // Rows of the machine's display, which is 64 pixels wide on all of them
+    pub fn display_height(self) -> usize {
+        match self {
+            QuirkProfile::Eti660 => 48,
+            _ => 32,
+        }
+    }
+
+    // Instructions per frame that games for the machine usually expect, for a ROM that hasn't had a speed picked;
+    // None for the modern profile, which isn't any machine in particular
+    pub fn instructions_per_frame(self) -> Option<u32> {
+        match self {
+            QuirkProfile::Modern => None,
+            QuirkProfile::Vip | QuirkProfile::Eti660 | QuirkProfile::Dream6800 => Some(15),
+            QuirkProfile::Schip => Some(30),
+            QuirkProfile::XoChip => Some(1000), // XO-CHIP demos tend to be written for Octo's fastest setting
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirkProfile::Modern => Quirks {
+                vf_result_last: false,
+                i_overflow_flag: false,
+                jump_vx: false,
+                memory_increment: MemoryIncrement::Unchanged,
+                vf_reset: false,
+            },
+            // The ETI-660's interpreter was adapted from the VIP's and behaves the same way
+            QuirkProfile::Vip | QuirkProfile::Eti660 => Quirks {
+                vf_result_last: false,
+                i_overflow_flag: false,
+                jump_vx: false,
+                memory_increment: MemoryIncrement::ByXPlusOne,
+                vf_reset: true,
+            },
+            QuirkProfile::Schip => Quirks {
+                vf_result_last: false,
+                i_overflow_flag: false,
+                jump_vx: true,
+                memory_increment: MemoryIncrement::Unchanged,
+                vf_reset: false,
+            },
+            QuirkProfile::XoChip => Quirks {
+                vf_result_last: false,
+                i_overflow_flag: false,
+                jump_vx: false,
+                memory_increment: MemoryIncrement::ByXPlusOne,
+                vf_reset: false,
+            },
+            // CHIPOS was written from scratch for the 6800 to run the VIP's programs, so FX55/FX65 move I the same
+            // way; OR/AND/XOR leave VF alone, though, since that was a side effect of the VIP's own code
+            QuirkProfile::Dream6800 => Quirks {
+                vf_result_last: false,
+                i_overflow_flag: false,
+                jump_vx: false,
+                memory_increment: MemoryIncrement::ByXPlusOne,
+                vf_reset: false,
+            },
+        }
+    }
+}
+
+impl Quirks {
+    // Override a single quirk by name (e.g. from `--quirk i-overflow-flag=on`); returns false for unknown names
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let quirk = match name {
+            "vf-result-last" => &mut self.vf_result_last,
+            "i-overflow-flag" => &mut self.i_overflow_flag,
+            "jump-vx" => &mut self.jump_vx,
+            "vf-reset" => &mut self.vf_reset,
+            "memory-increment" | "memory-increment-x" => {
+                self.memory_increment = match (enabled, name) {
+                    (false, _) => MemoryIncrement::Unchanged,
+                    (true, "memory-increment") => MemoryIncrement::ByXPlusOne,
+                    (true, _) => MemoryIncrement::ByX,
+                };
+                return true;
+            }
+            _ => return false,
+        };
+        *quirk = enabled;
+        true
+    }
+
+    // Names of the quirks that are on, as set takes them (e.g. to record them in a replay)
+    pub fn names(self) -> Vec<&'static str> {
+        let memory_increment = match self.memory_increment {
+            MemoryIncrement::Unchanged => None,
+            MemoryIncrement::ByX => Some("memory-increment-x"),
+            MemoryIncrement::ByXPlusOne => Some("memory-increment"),
+        };
+        [
+            self.vf_result_last.then_some("vf-result-last"),
+            self.i_overflow_flag.then_some("i-overflow-flag"),
+            self.jump_vx.then_some("jump-vx"),
+            memory_increment,
+            self.vf_reset.then_some("vf-reset"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        QuirkProfile::Modern.quirks()
+    }
+}
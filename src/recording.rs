@@ -0,0 +1,288 @@
+// Recording of the emulator's output to files
+//
+// Both recorders are fed once per 60Hz timer tick of emulated time (one video frame and 1/60s of audio per tick),
+// which keeps them in sync with each other no matter how fast or unevenly the emulator actually runs.
+use crate::audio::{Beeper, Waveform, SAMPLE_RATE};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+// Every recording that was asked for on the command line
+pub struct Recording {
+    audio: Option<AudioRecorder>,
+    video: Option<VideoRecorder>,
+    gif: Option<GifRecorder>, // Instead of video, for a path ending in .gif
+    audio_path: Option<PathBuf>,
+    video_path: Option<PathBuf>,
+}
+
+impl Recording {
+    // rows is the height of the display being recorded (32, or 48 for the ETI-660)
+    pub fn start(audio_path: Option<&str>, video_path: Option<&str>, rows: usize) -> Result<Self, String> {
+        let audio_path = audio_path.map(PathBuf::from);
+        let video_path = video_path.map(PathBuf::from);
+
+        let audio = audio_path.as_deref().map(AudioRecorder::create).transpose()?;
+
+        // When audio is also being recorded, the video goes to a temporary file first and the two are
+        // combined into the requested file at the end. GIFs are written directly, and can't have sound
+        let is_gif = |path: &PathBuf| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+        let gif = video_path.as_ref().filter(|path| is_gif(path)).map(|path| GifRecorder::create(path, rows)).transpose()?;
+        let video = match &video_path {
+            Some(path) if is_gif(path) => None,
+            Some(path) if audio.is_some() => Some(VideoRecorder::create(&video_only_path(path), rows)?),
+            Some(path) => Some(VideoRecorder::create(path, rows)?),
+            None => None,
+        };
+
+        Ok(Recording {
+            audio,
+            video,
+            gif,
+            audio_path,
+            video_path,
+        })
+    }
+
+    // Record the beeper with this volume (percent of full scale) and waveform, like audio.rs plays it
+    pub fn set_sound(&mut self, volume: u8, waveform: Waveform) {
+        if let Some(audio) = &mut self.audio {
+            audio.volume = volume;
+            audio.waveform = waveform;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.audio.is_some() || self.video.is_some() || self.gif.is_some()
+    }
+
+    // Record one 60Hz tick of output
+    pub fn tick(&mut self, display: &[u64], beeping: bool) {
+        if let Some(audio) = &mut self.audio {
+            audio.record_tick(beeping);
+        }
+        if let Some(video) = &mut self.video {
+            video.record_frame(display);
+        }
+        if let Some(gif) = &mut self.gif {
+            gif.record_frame(display);
+        }
+    }
+
+    pub fn finish(self) {
+        let combine = self.audio.is_some() && self.video.is_some();
+        if let Some(audio) = self.audio {
+            audio.finish();
+        }
+        if let Some(video) = self.video {
+            video.finish();
+        }
+        if let Some(gif) = self.gif {
+            gif.finish();
+        }
+
+        if let (true, Some(audio_path), Some(video_path)) = (combine, &self.audio_path, &self.video_path) {
+            let video_only = video_only_path(video_path);
+            combine_audio_and_video(audio_path, &video_only, video_path);
+            let _ = fs::remove_file(video_only);
+        }
+    }
+}
+
+// Captures the beeper to a WAV file (--record-audio out.wav). Audio is generated from emulated time rather
+// than captured from the sound device, so recordings are exact even when muted or running slower than real time.
+pub struct AudioRecorder {
+    writer: WavWriter<BufWriter<File>>,
+    beeper: Beeper,
+    volume: u8, // Percent of full scale
+    waveform: Waveform,
+}
+
+impl AudioRecorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let writer = WavWriter::create(path, spec)
+            .map_err(|e| format!("Error creating audio recording {}: {}", path.display(), e))?;
+
+        Ok(AudioRecorder { writer, beeper: Beeper::new(), volume: 10, waveform: Waveform::Sine })
+    }
+
+    // Record one 60Hz timer tick worth of audio, with the beeper either on or off
+    pub fn record_tick(&mut self, beeping: bool) {
+        let amplitude = self.volume as f32 / 100.0 * i16::MAX as f32;
+        for _ in 0..SAMPLE_RATE / 60 {
+            let sample = self.beeper.sample(self.waveform, beeping) * amplitude;
+            if let Err(e) = self.writer.write_sample(sample as i16) {
+                eprintln!("Error writing audio recording: {}", e);
+                return;
+            }
+        }
+    }
+
+    // Write the WAV header sizes; without this the file is left truncated
+    pub fn finish(self) {
+        if let Err(e) = self.writer.finalize() {
+            eprintln!("Error finishing audio recording: {}", e);
+        }
+    }
+}
+
+// Scale factor applied to video recordings; 64x32 (or 64x48) is too small for most players and encoders
+const VIDEO_SCALE: usize = 10;
+
+// Encodes the display to a video file (--record-video out.mp4) by piping raw frames into ffmpeg,
+// which needs to be installed and on the PATH
+pub struct VideoRecorder {
+    ffmpeg: Child,
+    stdin: ChildStdin,
+    frame: Vec<u8>, // RGB24 pixels of the frame being written
+    rows: usize,    // Height of the video in CHIP-8 pixels
+}
+
+impl VideoRecorder {
+    pub fn create(path: &Path, rows: usize) -> Result<Self, String> {
+        let size = format!("64x{}", rows);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24", "-video_size", &size, "-framerate", "60", "-i", "-"])
+            .args(["-vf", &format!("scale={}:{}:flags=neighbor", 64 * VIDEO_SCALE, rows * VIDEO_SCALE)])
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Error starting ffmpeg for video recording (is it installed?): {}", e))?;
+        let stdin = ffmpeg.stdin.take().unwrap();
+
+        Ok(VideoRecorder {
+            ffmpeg,
+            stdin,
+            frame: vec![0; 64 * rows * 3],
+            rows,
+        })
+    }
+
+    // Rows past the video's height are left out, and missing ones are blank
+    pub fn record_frame(&mut self, display: &[u64]) {
+        for y in 0..self.rows {
+            let row = display.get(y).copied().unwrap_or(0);
+            for x in 0..64 {
+                let value = if row & (0x8000_0000_0000_0000 >> x) != 0 { 0xFF } else { 0 };
+                let index = (y * 64 + x) * 3;
+                self.frame[index..index + 3].fill(value);
+            }
+        }
+
+        if let Err(e) = self.stdin.write_all(&self.frame) {
+            eprintln!("Error writing video recording: {}", e);
+        }
+    }
+
+    // Close ffmpeg's input and wait for it to finish writing the file
+    pub fn finish(mut self) {
+        drop(self.stdin);
+        match self.ffmpeg.wait() {
+            Ok(status) if status.success() => (),
+            Ok(status) => eprintln!("ffmpeg failed to finish the video recording ({})", status),
+            Err(e) => eprintln!("Error finishing video recording: {}", e),
+        }
+    }
+}
+
+// Scale factor for GIFs, which are usually viewed at their actual size
+const GIF_SCALE: usize = 4;
+const GIF_FRAME_TICKS: u64 = 2; // Ticks per GIF frame; viewers slow down frames shorter than 2/100s
+
+// Encodes the display to an animated GIF (--record-video out.gif, or render-replay), without needing ffmpeg.
+// The display is sampled every other tick, and runs of identical frames are stored as one longer frame
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    tick: u64,                        // Ticks recorded so far
+    pending: Option<(Vec<u64>, u64)>, // Frame not written yet, in case the next is the same, and its first tick
+    rows: usize,                      // Height of the GIF in CHIP-8 pixels
+}
+
+impl GifRecorder {
+    pub fn create(path: &Path, rows: usize) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("Error creating GIF recording {}: {}", path.display(), e);
+        let file = File::create(path).map_err(|e| error(&e))?;
+        let (width, height) = ((64 * GIF_SCALE) as u16, (rows * GIF_SCALE) as u16);
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[0, 0, 0, 0xFF, 0xFF, 0xFF])
+            .map_err(|e| error(&e))?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| error(&e))?;
+        Ok(GifRecorder { encoder, tick: 0, pending: None, rows })
+    }
+
+    pub fn record_frame(&mut self, display: &[u64]) {
+        let changed = self.pending.as_ref().is_none_or(|(pending, _)| pending != display);
+        if self.tick.is_multiple_of(GIF_FRAME_TICKS) && changed {
+            if let Some((pending, start)) = self.pending.replace((display.to_vec(), self.tick)) {
+                self.write(&pending, start, self.tick);
+            }
+        }
+        self.tick += 1;
+    }
+
+    // Write a frame shown from one tick up to another; delays are in hundredths of a second, rounded so the
+    // frames add up to the right length
+    fn write(&mut self, display: &[u64], start: u64, end: u64) {
+        let hundredths = |tick: u64| (tick * 100 + 30) / 60;
+        let (width, height) = (64 * GIF_SCALE, self.rows * GIF_SCALE);
+        let mut pixels = vec![0; width * height];
+        for (y, line) in pixels.chunks_mut(width).enumerate() {
+            let row = display.get(y / GIF_SCALE).copied().unwrap_or(0);
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = (row & (0x8000_0000_0000_0000 >> (x / GIF_SCALE)) != 0) as u8;
+            }
+        }
+        let frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            buffer: pixels.into(),
+            delay: (hundredths(end) - hundredths(start)) as u16,
+            ..Default::default()
+        };
+        if let Err(e) = self.encoder.write_frame(&frame) {
+            eprintln!("Error writing GIF recording: {}", e);
+        }
+    }
+
+    pub fn finish(mut self) {
+        if let Some((pending, start)) = self.pending.take() {
+            self.write(&pending, start, self.tick);
+        }
+    }
+}
+
+// Where the silent video goes while audio is recorded separately, e.g. out.mp4 => out.video-only.mp4
+fn video_only_path(path: &Path) -> PathBuf {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    path.with_extension(format!("video-only.{}", extension))
+}
+
+// Mux the finished audio and video recordings into one file; both start at emulated time zero and
+// have exactly one tick of media per tick, so no offsets are needed to keep them in sync
+fn combine_audio_and_video(audio: &Path, video: &Path, output: &Path) {
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(video)
+        .arg("-i")
+        .arg(audio)
+        .args(["-c:v", "copy", "-c:a", "aac", "-shortest"])
+        .arg(output)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("ffmpeg failed to combine the audio and video recordings ({})", status),
+        Err(e) => eprintln!("Error combining the audio and video recordings: {}", e),
+    }
+}
@@ -0,0 +1,48 @@
+// Headless replay rendering (`render-replay <in.replay> <out.gif> [rom]`): plays a replay back without a window,
+// audio or any timing, and encodes what was on screen as an animated GIF. Handy for previewing TAS runs in CI.
+//
+// The ROM comes from the command line or, failing that, the copy embedded in the replay (--embed-rom), and the
+// replay runs under the quirk profile, quirks and load address it was recorded with.
+use crate::recording::Recording;
+use crate::replay::Replay;
+use crate::rom;
+use chip8::quirks::QuirkProfile;
+use chip8::Chip8;
+use std::path::Path;
+
+pub fn render_replay(replay_path: &str, output: &str, rom_path: Option<&str>) -> Result<(), String> {
+    let replay = Replay::load(Path::new(replay_path))?;
+    let rom = match rom_path {
+        Some(path) => rom::read(path, None)?,
+        None => replay.rom.clone().ok_or("No ROM given, and the replay doesn't have one embedded")?,
+    };
+    let (profile, setup) = replay.recorded_setup(QuirkProfile::Modern)?;
+    replay.check(&setup, &rom)?;
+
+    let mut chip8 = Chip8::new();
+    chip8.quirks = setup.quirks;
+    chip8.load_address = setup.load_address;
+    chip8.entry_point = chip8.load_address;
+    chip8.display_height = profile.display_height();
+    chip8.reset();
+    chip8.load_bytes(&rom).map_err(|_| {
+        format!("The ROM is too big to load at {:03X} ({} bytes)", chip8.load_address, rom.len())
+    })?;
+    chip8.seed_rng(replay.seed);
+
+    let mut recording = Recording::start(None, Some(output), chip8.display_height)?;
+    let mut cycle_units = 0;
+    for tick in 0..replay.end {
+        // The same cycle accounting as the tick loop in main.rs
+        chip8.keypad = replay.input_at(tick);
+        cycle_units += replay.cpu_hz;
+        for _ in 0..cycle_units / 60 {
+            chip8.emulate_cycle();
+        }
+        cycle_units %= 60;
+        let beeping = chip8.update_timers();
+        recording.tick(chip8.display_rows(), beeping);
+    }
+    recording.finish();
+    Ok(())
+}
@@ -0,0 +1,317 @@
+// Input replays: the keypad state for every 60Hz tick of a play session, plus the random seed and CPU speed,
+// which is everything needed to play the session back exactly (see the tick loop in main.rs).
+//
+// Replays are plain text so they can be edited by hand, e.g. to add timed annotations ("notes") that are shown
+// as captions while the replay plays back. The version, profile, quirks, load address and ROM hash make sure a
+// replay is played back the way it was recorded (see check); replays from before they were added don't have them
+// and aren't checked.
+//
+//   # CHIP-8 replay
+//   version 0.1.0         # emulator version it was recorded with
+//   recorded 1760612345   # when, in seconds since 1970 (just for reference)
+//   profile vip           # quirk profile
+//   quirk jump-vx=on      # a --quirk override on top of the profile, one line each
+//   quirks memory-increment vf-reset jump-vx   # the quirks that were on in the end
+//   load_address 200      # where the ROM was loaded, in hex
+//   rom_sha1 d8e0ca92d92a1d01b5f47a34e5a401208f88e44e
+//   rom 00E0A22A600C...   # the ROM itself, 32 bytes a line, if recorded with --embed-rom
+//   seed 8317458163470391342
+//   cpu_hz 500
+//   input 0 0000          # tick, keypad keys held as a hex bitmask (bit N = key N); only changes are listed
+//   input 95 0020         # key 5 pressed at tick 95...
+//   input 101 0000        # ...and released 6 ticks later
+//   # tick, duration in ticks, text (which runs to the end of the line, # and all)
+//   note 90 120 Watch the alien on the left
+//   end 1800              # total length in ticks
+use crate::checksum::Checksums;
+use chip8::quirks::{QuirkProfile, Quirks};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, path::Path};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone)]
+pub struct Replay {
+    version: Option<String>,        // Emulator version it was recorded with
+    recorded: Option<u64>,          // Unix time it was recorded at
+    profile: Option<String>,        // Name of the quirk profile it was recorded with
+    overrides: Vec<(String, bool)>, // --quirk overrides it was recorded with
+    quirks: Option<String>,         // Names of the quirks that were on (see Quirks::names), space separated
+    load_address: Option<u16>,      // Where the ROM was loaded
+    rom_sha1: Option<String>,       // Hash of the ROM it was recorded on
+    pub rom: Option<Vec<u8>>,       // The ROM itself, if embedded
+    pub seed: u64,                  // Seed for CXNN's random numbers
+    pub cpu_hz: u32,                // Instructions per second the replay was recorded at
+    inputs: Vec<(u64, u16)>,        // (tick, keypad bitmask) every time the keypad changes, in tick order
+    notes: Vec<Note>,               // Annotations shown during playback
+    pub end: u64,                   // Number of ticks in the replay
+}
+
+// How the machine is set up to play a ROM, beyond the ROM itself
+pub struct Setup<'a> {
+    pub profile: &'a str,                // Name of the quirk profile
+    pub overrides: &'a [(String, bool)], // --quirk overrides on top of it
+    pub quirks: Quirks,                  // The quirks in effect, with the overrides applied
+    pub load_address: u16,
+}
+
+#[derive(Clone)]
+pub struct Note {
+    pub tick: u64,     // Tick the note appears on
+    pub duration: u64, // Number of ticks it stays up for
+    pub text: String,
+}
+
+impl Replay {
+    pub fn new(seed: u64, cpu_hz: u32) -> Self {
+        Replay {
+            version: None,
+            recorded: None,
+            profile: None,
+            overrides: Vec::new(),
+            quirks: None,
+            load_address: None,
+            rom_sha1: None,
+            rom: None,
+            seed,
+            cpu_hz,
+            inputs: Vec::new(),
+            notes: Vec::new(),
+            end: 0,
+        }
+    }
+
+    // A new replay of a ROM, stamped with what's needed to check it when it's played back
+    pub fn record(seed: u64, cpu_hz: u32, setup: &Setup, rom: &[u8], embed_rom: bool) -> Self {
+        let mut replay = Replay::new(seed, cpu_hz);
+        replay.version = Some(VERSION.to_string());
+        replay.recorded = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|time| time.as_secs());
+        replay.profile = Some(setup.profile.to_string());
+        replay.overrides = setup.overrides.to_vec();
+        replay.quirks = Some(setup.quirks.names().join(" "));
+        replay.load_address = Some(setup.load_address);
+        replay.rom_sha1 = Some(Checksums::of(rom).sha1);
+        replay.rom = embed_rom.then(|| rom.to_vec());
+        replay
+    }
+
+    // Make sure the replay will play back the way it was recorded: by this version of the emulator, under the same
+    // quirks, with the ROM at the same address and on the same ROM
+    pub fn check(&self, setup: &Setup, rom: &[u8]) -> Result<(), String> {
+        if let Some(version) = self.version.as_deref().filter(|&version| version != VERSION) {
+            return Err(format!("The replay was recorded with version {} of the emulator, not {}", version, VERSION));
+        }
+        if let Some(recorded) = self.profile.as_deref().filter(|&recorded| recorded != setup.profile) {
+            return Err(format!("The replay was recorded with --quirks {}", recorded));
+        }
+        let mut quirks = setup.quirks.names();
+        quirks.sort_unstable();
+        let differ = |recorded: &&str| {
+            let mut recorded: Vec<&str> = recorded.split_whitespace().collect();
+            recorded.sort_unstable();
+            recorded != quirks
+        };
+        if let Some(recorded) = self.quirks.as_deref().filter(differ) {
+            let mut options = format!("--quirks {}", self.profile.as_deref().unwrap_or(setup.profile));
+            for (name, on) in &self.overrides {
+                options += &format!(" --quirk {}={}", name, on_off(*on));
+            }
+            let recorded = if recorded.is_empty() { "none" } else { recorded };
+            return Err(format!("The replay was recorded with other quirks: {} ({})", recorded, options));
+        }
+        if let Some(address) = self.load_address.filter(|&address| address != setup.load_address) {
+            return Err(format!("The replay was recorded with the ROM loaded at {:03X} (--load-addr)", address));
+        }
+        if let Some(sha1) = &self.rom_sha1 {
+            if *sha1 != Checksums::of(rom).sha1 {
+                return Err(format!("The replay was recorded on a different ROM (SHA-1 {})", sha1));
+            }
+        }
+        Ok(())
+    }
+
+    // The quirk profile and setup the replay was recorded with, to play it back under; a replay from before they
+    // were recorded gets `profile` and its usual load address
+    pub fn recorded_setup(&self, profile: QuirkProfile) -> Result<(QuirkProfile, Setup<'_>), String> {
+        let profile = match self.profile.as_deref() {
+            Some(name) => QuirkProfile::from_name(name).ok_or_else(|| format!("Unknown quirk profile '{}'", name))?,
+            None => profile,
+        };
+        let mut quirks = profile.quirks();
+        for (name, on) in &self.overrides {
+            if !quirks.set(name, *on) {
+                return Err(format!("Unknown quirk '{}'", name));
+            }
+        }
+        let load_address = self.load_address.unwrap_or(profile.load_address());
+        Ok((profile, Setup { profile: profile.name(), overrides: &self.overrides, quirks, load_address }))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Error reading replay {}: {}", path.display(), e))?;
+        Replay::parse(&text).map_err(|e| format!("Error in replay {}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut replay = Replay::new(0, 0);
+
+        for (number, line) in text.lines().enumerate() {
+            // Anything after a # is a comment, except in a note, whose text runs to the end of the line
+            let line = line.trim();
+            let line = if line.starts_with("note ") { line } else { line.split('#').next().unwrap_or("").trim() };
+            if line.is_empty() {
+                continue;
+            }
+
+            // Every line is a keyword followed by numbers, except that notes end with free text
+            let error = || format!("line {}: couldn't understand `{}`", number + 1, line);
+            let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let fields: Vec<&str> = rest.trim().splitn(3, ' ').collect();
+            let numeric = |index: usize| fields.get(index).and_then(|field| field.parse::<u64>().ok()).ok_or_else(error);
+
+            match keyword {
+                "version" => replay.version = Some(rest.trim().to_string()),
+                "recorded" => replay.recorded = Some(numeric(0)?),
+                "profile" => replay.profile = Some(rest.trim().to_string()),
+                "quirk" => {
+                    let (name, value) = rest.trim().split_once('=').ok_or_else(error)?;
+                    let on = match value {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(error()),
+                    };
+                    replay.overrides.push((name.to_string(), on));
+                }
+                "quirks" => replay.quirks = Some(rest.split_whitespace().collect::<Vec<_>>().join(" ")),
+                "load_address" => {
+                    replay.load_address = Some(u16::from_str_radix(rest.trim(), 16).map_err(|_| error())?);
+                }
+                "rom_sha1" => replay.rom_sha1 = Some(rest.trim().to_lowercase()),
+                "rom" => {
+                    let hex = rest.trim();
+                    if hex.len() % 2 != 0 || !hex.is_ascii() {
+                        return Err(error());
+                    }
+                    let rom = replay.rom.get_or_insert_with(Vec::new);
+                    for index in (0..hex.len()).step_by(2) {
+                        rom.push(u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| error())?);
+                    }
+                }
+                "seed" => replay.seed = numeric(0)?,
+                "cpu_hz" => replay.cpu_hz = numeric(0)? as u32,
+                "end" => replay.end = numeric(0)?,
+                "input" => {
+                    let keys = fields.get(1).and_then(|field| u16::from_str_radix(field, 16).ok()).ok_or_else(error)?;
+                    replay.inputs.push((numeric(0)?, keys));
+                }
+                "note" => replay.notes.push(Note {
+                    tick: numeric(0)?,
+                    duration: numeric(1)?,
+                    text: fields.get(2).unwrap_or(&"").to_string(),
+                }),
+                _ => return Err(error()),
+            }
+        }
+
+        if replay.cpu_hz == 0 {
+            return Err("missing cpu_hz".to_string());
+        }
+        replay.inputs.sort_by_key(|&(tick, _)| tick);
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::from("# CHIP-8 replay\n");
+        if let Some(version) = &self.version {
+            text += &format!("version {}\n", version);
+        }
+        if let Some(recorded) = self.recorded {
+            text += &format!("recorded {}\n", recorded);
+        }
+        if let Some(profile) = &self.profile {
+            text += &format!("profile {}\n", profile);
+        }
+        for (name, on) in &self.overrides {
+            text += &format!("quirk {}={}\n", name, on_off(*on));
+        }
+        if let Some(quirks) = &self.quirks {
+            text += format!("quirks {}", quirks).trim_end();
+            text += "\n";
+        }
+        if let Some(address) = self.load_address {
+            text += &format!("load_address {:03X}\n", address);
+        }
+        if let Some(sha1) = &self.rom_sha1 {
+            text += &format!("rom_sha1 {}\n", sha1);
+        }
+        for line in self.rom.iter().flat_map(|rom| rom.chunks(32)) {
+            text += "rom ";
+            text += &line.iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+            text += "\n";
+        }
+        text += &format!("seed {}\n", self.seed);
+        text += &format!("cpu_hz {}\n", self.cpu_hz);
+        for (tick, keys) in &self.inputs {
+            text += &format!("input {} {:04X}\n", tick, keys);
+        }
+        for note in &self.notes {
+            text += &format!("note {} {} {}\n", note.tick, note.duration, note.text);
+        }
+        text += &format!("end {}\n", self.end);
+
+        fs::write(path, text).map_err(|e| format!("Error writing replay {}: {}", path.display(), e))
+    }
+
+    // Record the keypad state for a tick; call once per tick, in order
+    pub fn record_input(&mut self, tick: u64, keypad: &[bool; 16]) {
+        let keys = keypad_to_mask(keypad);
+        if self.inputs.last().map(|&(_, last)| last) != Some(keys) {
+            self.inputs.push((tick, keys));
+        }
+        self.end = tick + 1;
+    }
+
+    // Cut the replay off at a tick, e.g. to carry on recording from a state saved there
+    pub fn truncate(&mut self, end: u64) {
+        self.inputs.retain(|&(tick, _)| tick < end);
+        self.notes.retain(|note| note.tick < end);
+        self.end = self.end.min(end);
+    }
+
+    // Keypad state for a tick during playback
+    pub fn input_at(&self, tick: u64) -> [bool; 16] {
+        let index = self.inputs.partition_point(|&(t, _)| t <= tick);
+        let keys = if index == 0 { 0 } else { self.inputs[index - 1].1 };
+        mask_to_keypad(keys)
+    }
+
+    // Notes that should be on screen at a tick
+    pub fn notes_at(&self, tick: u64) -> impl Iterator<Item = &Note> {
+        self.notes
+            .iter()
+            .filter(move |note| note.tick <= tick && tick < note.tick + note.duration)
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn keypad_to_mask(keypad: &[bool; 16]) -> u16 {
+    keypad
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (key, &pressed)| mask | ((pressed as u16) << key))
+}
+
+fn mask_to_keypad(mask: u16) -> [bool; 16] {
+    let mut keypad = [false; 16];
+    for (key, pressed) in keypad.iter_mut().enumerate() {
+        *pressed = mask & (1 << key) != 0;
+    }
+    keypad
+}
@@ -0,0 +1,163 @@
+// Rewind buffer: a rolling history of machine states, one taken every few frames.
+// The paused-mode timeline (press P) lets you scrub back through it and resume from any point.
+//
+// Along with each state it keeps a log of what happened to the machine after it (keypad changes, instructions,
+// timer ticks and --fuzz-bitflips' flips), so any point in between can be rebuilt exactly by replaying the log; the debugger's
+// step-back uses this to go back one instruction at a time.
+//
+// The --devices state is kept along with the machine's, and the console's output is dropped while replaying the
+// log, since it was already printed the first time around.
+use chip8::devices::DeviceState;
+use chip8::{Chip8, MachineState};
+use std::collections::VecDeque;
+
+type Point = (MachineState, Option<DeviceState>, Vec<Event>);
+
+pub struct RewindBuffer {
+    states: VecDeque<Point>, // Oldest state first, each with the devices' state and everything logged after it
+    capacity: usize,         // Maximum number of states kept
+    interval: u32,           // Frames between recorded states
+    frames_since_record: u32,
+}
+
+// Something the front end did to the machine between states
+#[derive(Clone, Copy)]
+pub enum Event {
+    Keys([bool; 16]), // Keypad set
+    Cycles(u32),      // Instructions run
+    Timers,           // Timers ticked
+    Clock(u8),        // The devices' RTC set to a new second
+    Poke(u16, u8),    // A byte of memory written from outside the CPU (fuzz.rs's bit flips)
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        RewindBuffer {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+            frames_since_record: 0,
+        }
+    }
+
+    // Call once per frame; records the machine state every `interval` frames
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.frames_since_record += 1;
+        if self.frames_since_record < self.interval {
+            return;
+        }
+        self.frames_since_record = 0;
+
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        let devices = chip8.devices.as_ref().map(|devices| devices.state());
+        self.states.push_back((chip8.snapshot(), devices, Vec::new()));
+    }
+
+    // Call for everything the front end does to the machine, in order; nothing is kept before the first state
+    pub fn log(&mut self, event: Event) {
+        let Some((_, _, events)) = self.states.back_mut() else {
+            return;
+        };
+        match (events.last_mut(), event) {
+            (_, Event::Cycles(0)) => (),
+            (Some(Event::Cycles(total)), Event::Cycles(cycles)) => *total += cycles,
+            _ => events.push(event),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&MachineState> {
+        self.states.get(index).map(|(state, _, _)| state)
+    }
+
+    // Put the machine, and its devices, back to a recorded state
+    pub fn restore(&self, index: usize, chip8: &mut Chip8) {
+        if let Some((state, devices, _)) = self.states.get(index) {
+            restore(chip8, state, *devices);
+        }
+    }
+
+    // Resuming from an earlier point starts a new history, so forget everything after it
+    pub fn truncate_after(&mut self, index: usize) {
+        self.states.truncate(index + 1);
+        if let Some((_, _, events)) = self.states.back_mut() {
+            events.clear();
+        }
+        self.frames_since_record = 0;
+    }
+
+    // Forget everything, e.g. when a different game is loaded
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.frames_since_record = 0;
+    }
+
+    // Put the machine back to how it was just before the last instruction it ran, by replaying the log from the
+    // latest state up to that point; returns false once there is no history left to go back through
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        loop {
+            let Some((state, devices, events)) = self.states.back_mut() else {
+                return false;
+            };
+
+            if let Some(last) = events.iter().rposition(|event| matches!(event, Event::Cycles(_))) {
+                // Drop the last instruction, and anything that happened after it
+                match events[last] {
+                    Event::Cycles(cycles) if cycles > 1 => {
+                        events[last] = Event::Cycles(cycles - 1);
+                        events.truncate(last + 1);
+                    }
+                    _ => events.truncate(last),
+                }
+
+                let pending_output = chip8.devices.as_ref().map_or(0, |devices| devices.pending_output());
+                restore(chip8, state, *devices);
+                for event in events.iter() {
+                    match *event {
+                        Event::Keys(keys) => chip8.keypad = keys,
+                        Event::Cycles(cycles) => (0..cycles).for_each(|_| chip8.emulate_cycle()),
+                        Event::Timers => {
+                            chip8.update_timers();
+                        }
+                        Event::Clock(seconds) => {
+                            if let Some(devices) = &mut chip8.devices {
+                                devices.seconds = seconds;
+                            }
+                        }
+                        Event::Poke(addr, value) => chip8.poke(addr, value),
+                    }
+                }
+                // Faults, collisions and console output were already reported the first time around
+                chip8.take_faults();
+                chip8.take_collision();
+                if let Some(devices) = &mut chip8.devices {
+                    devices.discard_output_after(pending_output);
+                }
+                return true;
+            }
+
+            // Nothing ran since this state, so the last instruction is in the previous state's log
+            if self.states.len() == 1 {
+                return false;
+            }
+            self.states.pop_back();
+            self.frames_since_record = 0;
+        }
+    }
+}
+
+fn restore(chip8: &mut Chip8, state: &MachineState, devices: Option<DeviceState>) {
+    chip8.restore(state);
+    if let (Some(chip8_devices), Some(devices)) = (&mut chip8.devices, devices) {
+        chip8_devices.set_state(devices);
+    }
+}
@@ -0,0 +1,75 @@
+// Reading ROM files. Besides plain binaries, ROMs can be hex text, which is handy for snippets copied out of a
+// tutorial or Octo's hex dumps:
+//
+//   0x00E0 0xA22A 0x600C   # words, as tutorials usually list instructions
+//   0x00 0xE0 0xA2 0x2A    ; or single bytes, as Octo writes them out
+//   00E0 A22A, $600C       // prefixes and commas are optional
+//
+// Each number is one or more whole bytes (2, 4, ... hex digits), most significant first. Comments run from #, ;
+// or // to the end of the line. Files ending in .hex or .txt are read as hex, or any file with --format hex.
+use chip8::Chip8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RomFormat {
+    Binary,
+    Hex,
+}
+
+impl RomFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "binary" | "bin" | "ch8" => Some(RomFormat::Binary),
+            "hex" | "text" => Some(RomFormat::Hex),
+            _ => None,
+        }
+    }
+
+    // Guess the format from the file extension
+    fn detect(path: &str) -> Self {
+        let extension = std::path::Path::new(path).extension().and_then(|extension| extension.to_str());
+        match extension.map(str::to_lowercase).as_deref() {
+            Some("hex" | "txt") => RomFormat::Hex,
+            _ => RomFormat::Binary,
+        }
+    }
+}
+
+// Read a ROM, converting it to binary if needed; `format` overrides the guess from the extension
+pub fn read(path: &str, format: Option<RomFormat>) -> Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path, e))?;
+    match format.unwrap_or_else(|| RomFormat::detect(path)) {
+        RomFormat::Binary => Ok(bytes),
+        RomFormat::Hex => parse_hex(&String::from_utf8_lossy(&bytes)).map_err(|e| format!("Error in {}: {}", path, e)),
+    }
+}
+
+// Read a ROM into memory at its load address, reporting (but carrying on after) any error like Chip8::load_program
+pub fn load(chip8: &mut Chip8, path: &str, format: Option<RomFormat>) {
+    match read(path, format) {
+        Ok(rom) => match chip8.load_bytes(&rom) {
+            Ok(()) => status!("File read successfully. Size: {} bytes", rom.len()),
+            Err(_) => eprintln!("{} is too big to load at {:03X} ({} bytes)", path, chip8.load_address, rom.len()),
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split(['#', ';']).next().unwrap_or_default();
+        let line = line.split("//").next().unwrap_or_default();
+
+        for word in line.split([' ', '\t', ',']).filter(|word| !word.is_empty()) {
+            let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word);
+            let digits = digits.strip_prefix('$').unwrap_or(digits);
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+                return Err(format!("line {}: expected hex bytes like 0xA2 or 0xA22A, found `{}`", number + 1, word));
+            }
+            for pair in digits.as_bytes().chunks(2) {
+                rom.push(u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap());
+            }
+        }
+    }
+    Ok(rom)
+}
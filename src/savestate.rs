@@ -0,0 +1,174 @@
+// Save slots: 10 machine states per ROM, kept on disk along with a small picture of the screen at the time, so
+// the slot picker (F2) can show what's in each one. For a ROM called pong.ch8, slot 3 is saved as
+// saves/pong/slot3.state, with its thumbnail in saves/pong/slot3.ppm (a 64x32 image most image viewers can open,
+// or 64x48 under the ETI-660 profile).
+//
+// The same directory holds the autosave (autosave.state), written every so often while the game runs, and a
+// session.lock file that only exists while the emulator is running the ROM; finding it at startup means the last
+// session didn't end cleanly, so the autosave is worth offering back.
+//
+// A state saved while a replay plays or records also gets the replay up to that point (slot3.replay), so it can
+// be loaded to carry on recording from there (re-recording).
+use crate::replay::Replay;
+use chip8::{MachineState, MAX_DISPLAY_HEIGHT};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SLOTS: usize = 10;
+const SAVE_DIRECTORY: &str = "saves";
+pub const THUMBNAIL_WIDTH: usize = 64; // One pixel per CHIP-8 pixel
+pub const THUMBNAIL_HEIGHT: usize = 32; // Or 48 for a game with the ETI-660's display
+
+pub struct SaveSlots {
+    directory: PathBuf,                    // Where this ROM's slots are kept
+    profile: &'static str,                 // Quirk profile the game is running under, saved with each state
+    thumbnails: [Option<Vec<u32>>; SLOTS], // Thumbnail of each slot that has a state saved in it
+}
+
+impl SaveSlots {
+    pub fn new(rom_path: &str, profile: &'static str) -> Self {
+        let name = Path::new(rom_path).file_stem().map_or("untitled".into(), |stem| stem.to_string_lossy());
+        let mut slots = SaveSlots {
+            directory: Path::new(SAVE_DIRECTORY).join(&*name),
+            profile,
+            thumbnails: Default::default(),
+        };
+
+        // A slot whose thumbnail is missing or unreadable still gets a blank one, so it doesn't look empty
+        for slot in 0..SLOTS {
+            if slots.state_path(slot).exists() {
+                let thumbnail = fs::read(slots.thumbnail_path(slot)).ok().and_then(|bytes| read_ppm(&bytes));
+                let blank = || vec![0; THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT];
+                slots.thumbnails[slot] = Some(thumbnail.unwrap_or_else(blank));
+            }
+        }
+        slots
+    }
+
+    // Save a state and its thumbnail (THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT pixels) in a slot
+    pub fn save(&mut self, slot: usize, state: &MachineState, thumbnail: Vec<u32>) -> Result<(), String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| format!("Error creating {}: {}", self.directory.display(), e))?;
+        let path = self.state_path(slot);
+        fs::write(&path, state.to_bytes(self.profile)).map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+        let path = self.thumbnail_path(slot);
+        fs::write(&path, write_ppm(&thumbnail)).map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+        self.thumbnails[slot] = Some(thumbnail);
+        Ok(())
+    }
+
+    pub fn load(&self, slot: usize) -> Result<MachineState, String> {
+        if self.thumbnails[slot].is_none() {
+            return Err(format!("Slot {} is empty", slot));
+        }
+        self.read_state(&self.state_path(slot))
+    }
+
+    // Keep the replay leading up to the state in a slot, or forget any old one if there isn't one now
+    pub fn save_replay(&self, slot: usize, replay: Option<&Replay>) -> Result<(), String> {
+        let path = self.replay_path(slot);
+        match replay {
+            Some(replay) => replay.save(&path),
+            None if path.exists() => {
+                fs::remove_file(&path).map_err(|e| format!("Error removing {}: {}", path.display(), e))
+            }
+            None => Ok(()),
+        }
+    }
+
+    // The replay leading up to the state in a slot, if it was saved during one
+    pub fn load_replay(&self, slot: usize) -> Result<Option<Replay>, String> {
+        let path = self.replay_path(slot);
+        path.exists().then(|| Replay::load(&path)).transpose()
+    }
+
+    pub fn thumbnails(&self) -> &[Option<Vec<u32>>; SLOTS] {
+        &self.thumbnails
+    }
+
+    // Replace the autosave; it's written to a temporary file first so a crash mid-write can't lose the old one
+    pub fn autosave(&self, state: &MachineState) -> Result<(), String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| format!("Error creating {}: {}", self.directory.display(), e))?;
+        let path = self.directory.join("autosave.state");
+        let temporary = self.directory.join("autosave.state.tmp");
+        fs::write(&temporary, state.to_bytes(self.profile))
+            .and_then(|()| fs::rename(&temporary, &path))
+            .map_err(|e| format!("Error writing {}: {}", path.display(), e))
+    }
+
+    pub fn load_autosave(&self) -> Result<MachineState, String> {
+        self.read_state(&self.directory.join("autosave.state"))
+    }
+
+    pub fn has_autosave(&self) -> bool {
+        self.directory.join("autosave.state").exists()
+    }
+
+    // Mark the ROM as running until end_session is called; returns true if the last session never got there
+    pub fn begin_session(&self) -> bool {
+        let path = self.directory.join("session.lock");
+        let unclean = path.exists();
+        if let Err(e) = fs::create_dir_all(&self.directory).and_then(|()| fs::write(&path, "")) {
+            println!("Error writing {}: {}", path.display(), e);
+        }
+        unclean
+    }
+
+    pub fn end_session(&self) {
+        let _ = fs::remove_file(self.directory.join("session.lock"));
+    }
+
+    // A state saved under a different quirk profile still loads, but may not play the same
+    fn read_state(&self, path: &Path) -> Result<MachineState, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        let (state, profile) =
+            MachineState::from_bytes(&bytes).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        if let Some(profile) = profile.filter(|profile| profile != self.profile) {
+            println!("Warning: {} was saved with the {} quirk profile, not {}", path.display(), profile, self.profile);
+        }
+        Ok(state)
+    }
+
+    fn state_path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot{}.state", slot))
+    }
+
+    fn thumbnail_path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot{}.ppm", slot))
+    }
+
+    fn replay_path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot{}.replay", slot))
+    }
+}
+
+// A thumbnail straight from the display, for when there's no framebuffer to capture (e.g. debugger saves)
+pub fn thumbnail_from_display(display: &[u64]) -> Vec<u32> {
+    let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * display.len());
+    for row in display {
+        thumbnail.extend((0..64).map(|x| if row & (0x8000_0000_0000_0000 >> x) != 0 { 0xFFFFFF } else { 0 }));
+    }
+    thumbnail
+}
+
+// Binary PPM: a short text header, then 3 bytes (RGB) per pixel
+fn write_ppm(pixels: &[u32]) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", THUMBNAIL_WIDTH, pixels.len() / THUMBNAIL_WIDTH).into_bytes();
+    for pixel in pixels {
+        bytes.extend_from_slice(&pixel.to_be_bytes()[1..]);
+    }
+    bytes
+}
+
+// Only reads back thumbnails in the form write_ppm writes them
+fn read_ppm(bytes: &[u8]) -> Option<Vec<u32>> {
+    let (pixels, rows) = [THUMBNAIL_HEIGHT, MAX_DISPLAY_HEIGHT].into_iter().find_map(|rows| {
+        let header = format!("P6\n{} {}\n255\n", THUMBNAIL_WIDTH, rows);
+        Some((bytes.strip_prefix(header.as_bytes())?, rows))
+    })?;
+    if pixels.len() != THUMBNAIL_WIDTH * rows * 3 {
+        return None;
+    }
+    Some(pixels.chunks_exact(3).map(|rgb| u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]])).collect())
+}
@@ -0,0 +1,90 @@
+// Aspect-ratio correction: stretches the picture to a different shape on its way to the screen. CHIP-8's 64x32
+// display is drawn with square pixels by default (a 2:1 picture), but on a COSMAC VIP it filled a 4:3 TV screen,
+// so its pixels were half again as tall as they were wide.
+//
+// The picture keeps its width and gets taller or shorter to match the aspect ratio. Nearest-neighbour sampling
+// keeps pixels sharp but makes some rows of pixels a line taller than others; bilinear sampling evens them out at
+// the cost of slightly soft edges.
+// Aspect ratios allowed, from 1:4 to 8:1; anything further out would make a picture only a pixel or two tall (or
+// thousands of lines tall)
+pub const MIN_ASPECT: f32 = 0.25;
+pub const MAX_ASPECT: f32 = 8.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy)]
+pub struct Scaling {
+    pub aspect: Option<f32>, // Width / height of the picture shown, or None to leave it as drawn
+    pub filter: Filter,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" | "linear" => Some(Filter::Bilinear),
+            _ => None,
+        }
+    }
+}
+
+// Parse an aspect ratio written as width:height (e.g. 4:3) or as a single number (e.g. 1.33), between MIN_ASPECT
+// and MAX_ASPECT
+pub fn parse_aspect(text: &str) -> Option<f32> {
+    let aspect = match text.split_once(':') {
+        Some((width, height)) => width.trim().parse::<f32>().ok()? / height.trim().parse::<f32>().ok()?,
+        None => text.trim().parse().ok()?,
+    };
+    (MIN_ASPECT..=MAX_ASPECT).contains(&aspect).then_some(aspect)
+}
+
+impl Scaling {
+    // Size of a width x height picture once stretched
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.aspect {
+            Some(aspect) => (width, ((width as f32 / aspect.clamp(MIN_ASPECT, MAX_ASPECT)).round() as usize).max(1)),
+            None => (width, height),
+        }
+    }
+
+    // Stretch a width x height picture into `out`
+    pub fn apply(&self, pixels: &[u32], width: usize, height: usize, out: &mut Vec<u32>) {
+        let (out_width, out_height) = self.size(width, height);
+        out.resize(out_width * out_height, 0);
+
+        for y in 0..out_height {
+            let line = &mut out[y * out_width..][..out_width];
+            match self.filter {
+                Filter::Nearest => {
+                    let source = y * height / out_height;
+                    line.copy_from_slice(&pixels[source * width..][..width]);
+                }
+                Filter::Bilinear => {
+                    // Only the height changes, so this only needs to blend between two lines
+                    let position = ((y as f32 + 0.5) * height as f32 / out_height as f32 - 0.5).max(0.0);
+                    let above = (position as usize).min(height - 1);
+                    let below = (above + 1).min(height - 1);
+                    let weight = ((position - above as f32) * 256.0) as u32;
+                    let (above, below) = (&pixels[above * width..][..width], &pixels[below * width..][..width]);
+                    for (pixel, (&a, &b)) in line.iter_mut().zip(above.iter().zip(below)) {
+                        *pixel = interpolate(a, b, weight);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Blend two colors channel by channel, with weight (0-256) of the second; palette::mix does the same with a
+// 0-1 weight
+fn interpolate(a: u32, b: u32, weight: u32) -> u32 {
+    let channel = |shift: u32| {
+        let (a, b) = ((a >> shift) & 0xFF, (b >> shift) & 0xFF);
+        ((a * (256 - weight) + b * weight) >> 8) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,11 @@
+// Something that can show the CHIP-8 display: a window, a small LCD or OLED, a terminal, ...
+//
+// The core keeps the display as rows of 64 bits (Chip8::display_rows, with the leftmost pixel in the top bit):
+// 32 of them, or 48 under the ETI-660 profile. A front end only has to turn those into pixels on its own hardware;
+// see examples/ssd1306.rs.
+pub trait Screen {
+    type Error;
+
+    // Show a complete frame, top row first
+    fn show(&mut self, display: &[u64]) -> Result<(), Self::Error>;
+}
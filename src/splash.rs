@@ -0,0 +1,44 @@
+// Built-in boot splash: a tiny CHIP-8 program that spells out "CHIP-8" one letter at a time.
+// It runs on the emulated CPU like any other ROM, so seeing it draw correctly is also a quick
+// smoke-test that the core (drawing, timers, jumps and skips) works before the real ROM loads.
+
+// How long the splash screen is shown before the loaded program starts, in 60Hz ticks of emulated time, so that
+// pausing or the debugger doesn't cut it short
+pub const SPLASH_TICKS: u64 = 90;
+
+// Extra ticks a slow CPU (a low cpu_hz) gets to finish drawing after that, up to 10 seconds in all
+pub const SPLASH_GRACE_TICKS: u64 = 510;
+
+// Instructions that are plenty to draw the whole splash; one that ran this many and still didn't finish points
+// to a broken core rather than a slow one
+pub const SPLASH_CYCLES: u64 = 1000;
+
+// Address of the final "jump to self" instruction; the splash has finished drawing once PC gets here
+pub const SPLASH_END: u16 = 0x222;
+
+pub const SPLASH_ROM: [u8; 66] = [
+    0x00, 0xE0, // 0x200: CLS
+    0x60, 0x0E, // 0x202: LD V0, 14       (x position of the first letter)
+    0x61, 0x0D, // 0x204: LD V1, 13       (y position)
+    0x62, 0x00, // 0x206: LD V2, 0        (letters drawn so far)
+    0x63, 0x05, // 0x208: LD V3, 5        (bytes per letter sprite)
+    0xA2, 0x24, // 0x20A: LD I, 0x224     (letter sprites)
+    0xD0, 0x15, // 0x20C: DRW V0, V1, 5   (draw the next letter)
+    0x70, 0x06, // 0x20E: ADD V0, 6
+    0xF3, 0x1E, // 0x210: ADD I, V3
+    0x64, 0x08, // 0x212: LD V4, 8
+    0xF4, 0x15, // 0x214: LD DT, V4       (pause for 8 frames between letters)
+    0xF4, 0x07, // 0x216: LD V4, DT
+    0x34, 0x00, // 0x218: SE V4, 0
+    0x12, 0x16, // 0x21A: JP 0x216
+    0x72, 0x01, // 0x21C: ADD V2, 1
+    0x32, 0x06, // 0x21E: SE V2, 6
+    0x12, 0x0C, // 0x220: JP 0x20C
+    0x12, 0x22, // 0x222: JP 0x222        (done; wait here)
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // 0x224: C
+    0x90, 0x90, 0xF0, 0x90, 0x90, // H
+    0xE0, 0x40, 0x40, 0x40, 0xE0, // I
+    0xF0, 0x90, 0xF0, 0x80, 0x80, // P
+    0x00, 0x00, 0xF0, 0x00, 0x00, // -
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+];
@@ -0,0 +1,223 @@
+// Save state files: a MachineState along with the quirk profile it was running under, in a chunked container
+// that later versions can add to without breaking the states saved before them (or being unreadable to older
+// versions, as far as possible).
+//
+// All numbers are big-endian. A file is a header followed by any number of chunks:
+//
+//   Header  "C8STATE\0"                  magic (8 bytes)
+//           u16 version                  only changes if the layout of the header or a chunk changes
+//           u8 length, then that many    the quirk profile the game was running under, e.g. "modern" or "vip"
+//           bytes of ASCII
+//   Chunk   4-byte ASCII tag
+//           u32 length                   of the data that follows
+//           data
+//
+// Chunks can be in any order. Tags starting with an uppercase letter are critical: a reader that doesn't know
+// one can't load the state properly and gives up. Any other unknown chunk is skipped, so new chunks that a state
+// can do without are given lowercase tags. A chunk's data can also grow at the end; readers ignore the extra.
+//
+//   "CPU "  v: 16 bytes, i: u16, pc: u16, stack: 16 x u16, sp: u8, delay timer: u8, sound timer: u8   (required)
+//   "MEM "  memory; if shorter than 4KB the rest is zeroed, and more than 4KB is an error            (required)
+//   "DISP"  the display, 32 x u64 rows with the leftmost pixel in the top bit, or 48 rows if any     (required)
+//           of the ETI-660's extra 16 are lit
+//   "rng "  CXNN's generator (ChaCha12): seed: 32 bytes, stream: u64, word position: u128
+//           (without it, the generator starts over from seed 0)
+//   "stat"  draws: u64, the number of DXYN instructions run (0 without it)
+//
+// Future additions like XO-CHIP's second display plane or extended memory would be new chunks (or a longer MEM).
+//
+// Version 1, from before the container, still loads (see read_v1): the magic, a single version byte of 1, then
+// memory, v, i, pc, stack, sp, the timers, the generator, draws and the display's 32 rows, in that order. It
+// didn't record the quirk profile.
+use crate::{MachineState, MAX_DISPLAY_HEIGHT};
+use alloc::{format, string::String, vec::Vec};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+const MAGIC: &[u8; 8] = b"C8STATE\0";
+const VERSION: u16 = 2;
+
+impl MachineState {
+    pub fn to_bytes(&self, profile: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4500);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_be_bytes());
+        let profile = &profile.as_bytes()[..profile.len().min(255)];
+        bytes.push(profile.len() as u8);
+        bytes.extend_from_slice(profile);
+
+        let mut cpu = Vec::with_capacity(55);
+        cpu.extend_from_slice(&self.v);
+        cpu.extend_from_slice(&self.i.to_be_bytes());
+        cpu.extend_from_slice(&self.pc.to_be_bytes());
+        for entry in self.stack {
+            cpu.extend_from_slice(&entry.to_be_bytes());
+        }
+        cpu.extend_from_slice(&[self.sp, self.delay_timer, self.sound_timer]);
+        write_chunk(&mut bytes, b"CPU ", &cpu);
+
+        write_chunk(&mut bytes, b"MEM ", &self.memory);
+
+        // Most games only ever use 32 rows, so the chunk only grows for the ETI-660's 64x48 when it needs to
+        let rows = if self.display[32..].iter().any(|&row| row != 0) { MAX_DISPLAY_HEIGHT } else { 32 };
+        let display: Vec<u8> = self.display[..rows].iter().flat_map(|row| row.to_be_bytes()).collect();
+        write_chunk(&mut bytes, b"DISP", &display);
+
+        let mut rng = Vec::with_capacity(56);
+        rng.extend_from_slice(&self.rng.get_seed());
+        rng.extend_from_slice(&self.rng.get_stream().to_be_bytes());
+        rng.extend_from_slice(&self.rng.get_word_pos().to_be_bytes());
+        write_chunk(&mut bytes, b"rng ", &rng);
+
+        write_chunk(&mut bytes, b"stat", &self.draws.to_be_bytes());
+        bytes
+    }
+
+    // Returns the state and the quirk profile it was saved under (unknown for version 1 states)
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, Option<String>), String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a save state".into());
+        }
+        // Version 1 had a version byte where later versions have a u16, whose first byte has been 0 so far
+        if reader.bytes.first() == Some(&1) {
+            reader.take(1)?;
+            return Ok((read_v1(&mut reader)?, None));
+        }
+        let version = u16::from_be_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(format!("unsupported save state version {}", version));
+        }
+        let [length] = reader.array()?;
+        let profile = String::from_utf8_lossy(reader.take(length as usize)?).into_owned();
+
+        let mut state = MachineState {
+            memory: [0; 4096],
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: [0; 16],
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            rng: ChaCha12Rng::seed_from_u64(0),
+            draws: 0,
+            display: [0; MAX_DISPLAY_HEIGHT],
+        };
+        let mut required = [(*b"CPU ", false), (*b"MEM ", false), (*b"DISP", false)];
+
+        while !reader.bytes.is_empty() {
+            let tag: [u8; 4] = reader.array()?;
+            let length = u32::from_be_bytes(reader.array()?);
+            let mut chunk = Reader { bytes: reader.take(length as usize)? };
+
+            match &tag {
+                b"CPU " => {
+                    state.v = chunk.array()?;
+                    state.i = u16::from_be_bytes(chunk.array()?) & 0xFFF;
+                    state.pc = u16::from_be_bytes(chunk.array()?) & 0xFFF;
+                    for entry in &mut state.stack {
+                        *entry = u16::from_be_bytes(chunk.array()?);
+                    }
+                    let [sp, delay_timer, sound_timer] = chunk.array()?;
+                    state.sp = sp.min(16);
+                    state.delay_timer = delay_timer;
+                    state.sound_timer = sound_timer;
+                }
+                b"MEM " => {
+                    if chunk.bytes.len() > state.memory.len() {
+                        return Err(format!("save state has {} bytes of memory; only 4096 are supported", length));
+                    }
+                    state.memory[..chunk.bytes.len()].copy_from_slice(chunk.bytes);
+                }
+                b"DISP" => {
+                    let rows = if chunk.bytes.len() >= MAX_DISPLAY_HEIGHT * 8 { MAX_DISPLAY_HEIGHT } else { 32 };
+                    for row in &mut state.display[..rows] {
+                        *row = u64::from_be_bytes(chunk.array()?);
+                    }
+                }
+                b"rng " => {
+                    state.rng = ChaCha12Rng::from_seed(chunk.array()?);
+                    state.rng.set_stream(u64::from_be_bytes(chunk.array()?));
+                    state.rng.set_word_pos(u128::from_be_bytes(chunk.array()?));
+                }
+                b"stat" => state.draws = u64::from_be_bytes(chunk.array()?),
+                _ if tag[0].is_ascii_uppercase() => {
+                    let name = String::from_utf8_lossy(&tag).into_owned();
+                    return Err(format!("save state needs a newer emulator (unknown chunk `{}`)", name.trim_end()));
+                }
+                _ => continue,
+            }
+            if let Some((_, seen)) = required.iter_mut().find(|(required, _)| *required == tag) {
+                *seen = true;
+            }
+        }
+
+        if let Some((tag, _)) = required.iter().find(|(_, seen)| !seen) {
+            let name = String::from_utf8_lossy(tag).into_owned();
+            return Err(format!("save state is missing its `{}` chunk", name.trim_end()));
+        }
+        Ok((state, Some(profile)))
+    }
+}
+
+// The rest of a version 1 state, after its version byte
+fn read_v1(reader: &mut Reader) -> Result<MachineState, String> {
+    let memory = reader.array()?;
+    let v = reader.array()?;
+    let i = u16::from_be_bytes(reader.array()?);
+    let pc = u16::from_be_bytes(reader.array()?);
+    let mut stack = [0; 16];
+    for entry in &mut stack {
+        *entry = u16::from_be_bytes(reader.array()?);
+    }
+    let [sp, delay_timer, sound_timer] = reader.array()?;
+    let mut rng = ChaCha12Rng::from_seed(reader.array()?);
+    rng.set_stream(u64::from_be_bytes(reader.array()?));
+    rng.set_word_pos(u128::from_be_bytes(reader.array()?));
+    let draws = u64::from_be_bytes(reader.array()?);
+    let mut display = [0; MAX_DISPLAY_HEIGHT];
+    for row in &mut display[..32] {
+        *row = u64::from_be_bytes(reader.array()?);
+    }
+
+    Ok(MachineState {
+        memory,
+        v,
+        i: i & 0xFFF,
+        pc: pc & 0xFFF,
+        stack,
+        sp: sp.min(16),
+        delay_timer,
+        sound_timer,
+        rng,
+        draws,
+        display,
+    })
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(tag);
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+// Reads a save state (or one of its chunks) front to back
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < length {
+            return Err("save state is cut short".into());
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
@@ -0,0 +1,161 @@
+// Trace verification (--verify-trace <file>): runs the ROM without a window and compares the machine after every
+// instruction against a trace written by another emulator, stopping at the first difference. A good way to find
+// out exactly where two emulators part ways on a ROM.
+//
+// A trace is a text file with one line per instruction, giving the state just after it ran:
+//
+//   # Comments start with #
+//   PC=0202 I=0000 V=00000000000000000000000000000000 SP=0 DT=00 ST=00
+//   PC=0204 I=022A V0=0C
+//
+// Each field is NAME=value with the value in hex; names are case-insensitive and any field can be left out, in
+// which case it isn't checked. The fields are:
+//
+//   PC       program counter
+//   OP       the opcode that just ran (checked against memory at the previous PC)
+//   I        index register
+//   V        all 16 registers as 32 hex digits, V0 first
+//   V0-VF    a single register
+//   SP       stack pointer (number of entries on the stack)
+//   DT, ST   delay and sound timers
+//
+// CXNN's random numbers can't match another emulator's, so after one the register it set is taken from the trace
+// rather than compared. The timers count down once every cpu_hz / 60 instructions, as while playing, so only
+// compare them against emulators that run at the same speed the same way.
+use chip8::disasm::disassemble;
+use chip8::Chip8;
+use std::path::Path;
+
+// Run the trace's worth of instructions, returning how many there were, or where the emulators disagree
+pub fn verify(chip8: &mut Chip8, path: &Path, cpu_hz: u32) -> Result<usize, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    let mut count = 0;
+    let mut cycle_units = 0;
+    let mut cycles_left = 0;
+    let mut running = false; // Whether the first frame has started
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let expected = parse_line(line).map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+
+        // Timers tick between frames, the same as in the tick loop in main.rs
+        while cycles_left == 0 {
+            if running {
+                chip8.update_timers();
+            }
+            running = true;
+            cycle_units += cpu_hz.max(1);
+            cycles_left = cycle_units / 60;
+            cycle_units %= 60;
+        }
+        let pc = chip8.pc();
+        let opcode = (chip8.peek(pc) as u16) << 8 | chip8.peek(pc + 1) as u16;
+        chip8.emulate_cycle();
+        cycles_left -= 1;
+        count += 1;
+
+        if opcode & 0xF000 == 0xC000 {
+            let x = (opcode as usize >> 8) & 0xF;
+            if let Some((_, value)) = expected.iter().find(|(field, _)| *field == Field::V(x)) {
+                chip8.set_v(x, *value as u8);
+            }
+        }
+
+        let differences: Vec<String> = expected
+            .iter()
+            .filter(|&&(field, value)| actual(chip8, field, opcode) != value)
+            .map(|&(field, value)| {
+                format!("{}: expected {:X}, got {:X}", field.name(), value, actual(chip8, field, opcode))
+            })
+            .collect();
+        if !differences.is_empty() {
+            return Err(format!(
+                "Instruction {} (line {}) differs after {:03X}: {:04X} {}\n  {}",
+                count,
+                number + 1,
+                pc,
+                opcode,
+                disassemble(opcode),
+                differences.join("\n  ")
+            ));
+        }
+    }
+    Ok(count)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Pc,
+    Op,
+    I,
+    V(usize),
+    Sp,
+    Dt,
+    St,
+}
+
+impl Field {
+    fn name(self) -> String {
+        match self {
+            Field::Pc => "PC".into(),
+            Field::Op => "OP".into(),
+            Field::I => "I".into(),
+            Field::V(x) => format!("V{:X}", x),
+            Field::Sp => "SP".into(),
+            Field::Dt => "DT".into(),
+            Field::St => "ST".into(),
+        }
+    }
+}
+
+fn actual(chip8: &Chip8, field: Field, opcode: u16) -> u16 {
+    match field {
+        Field::Pc => chip8.pc(),
+        Field::Op => opcode,
+        Field::I => chip8.i(),
+        Field::V(x) => chip8.v(x) as u16,
+        Field::Sp => chip8.sp() as u16,
+        Field::Dt => chip8.delay_timer() as u16,
+        Field::St => chip8.sound_timer() as u16,
+    }
+}
+
+fn parse_line(line: &str) -> Result<Vec<(Field, u16)>, String> {
+    let mut fields = Vec::new();
+    for pair in line.split_whitespace() {
+        let (name, value) = pair.split_once('=').ok_or_else(|| format!("Expected NAME=value, found `{}`", pair))?;
+        let name = name.to_uppercase();
+        let value = value.strip_prefix("0x").unwrap_or(value);
+        let invalid = || format!("Invalid value for {}: `{}`", name, value);
+
+        // All the registers at once
+        if name == "V" {
+            if value.len() != 32 || !value.is_ascii() {
+                return Err(invalid());
+            }
+            for x in 0..16 {
+                let register = u8::from_str_radix(&value[x * 2..x * 2 + 2], 16).map_err(|_| invalid())?;
+                fields.push((Field::V(x), register as u16));
+            }
+            continue;
+        }
+
+        let field = match name.as_str() {
+            "PC" => Field::Pc,
+            "OP" => Field::Op,
+            "I" => Field::I,
+            "SP" => Field::Sp,
+            "DT" => Field::Dt,
+            "ST" => Field::St,
+            _ => match name.strip_prefix('V').and_then(|x| u8::from_str_radix(x, 16).ok()) {
+                Some(x) if x < 16 && name.len() == 2 => Field::V(x as usize),
+                _ => return Err(format!("Unknown field `{}`", name)),
+            },
+        };
+        fields.push((field, u16::from_str_radix(value, 16).map_err(|_| invalid())?));
+    }
+    Ok(fields)
+}
@@ -0,0 +1,61 @@
+// Watched values: named expressions shown in the corner of the screen every frame, handy for working out
+// where a game keeps things while reverse-engineering it. They come from the [watch] section of the config
+// file, and the debugger's `watch` and `unwatch` commands add and remove them while the game runs:
+//
+//   [watch]
+//   score = "mem[0x3A0] * 10 + mem[0x3A1]"   # See expr.rs for what expressions can contain
+//   lives = "v7"
+use crate::config::Config;
+use chip8::expr::Expr;
+use chip8::Chip8;
+
+pub struct Watches {
+    list: Vec<(String, Expr)>, // Name and expression, in the order they were added
+}
+
+impl Watches {
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let mut watches = Watches { list: Vec::new() };
+        for (name, expr) in config.section("watch") {
+            let expr = Expr::parse(expr).map_err(|e| format!("Error in watch `{}`: {}", name, e))?;
+            watches.list.push((name.clone(), expr));
+        }
+        Ok(watches)
+    }
+
+    // Add a watch from a definition like `score = mem[0x3A0]`, replacing any watch with the same name
+    pub fn add(&mut self, definition: &str) -> Result<(), String> {
+        let (name, expr) = definition
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <name> = <expr>, found `{}`", definition))?;
+        let (name, expr) = (name.trim(), Expr::parse(expr)?);
+        match self.list.iter_mut().find(|(existing, _)| existing == name) {
+            Some(watch) => watch.1 = expr,
+            None => self.list.push((name.to_string(), expr)),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.list.len();
+        self.list.retain(|(existing, _)| existing != name);
+        self.list.len() < count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // The current value of the watch with the given name, if there is one
+    pub fn value(&self, name: &str, chip8: &Chip8) -> Option<i64> {
+        self.list.iter().find(|(existing, _)| existing == name).map(|(_, expr)| expr.eval(chip8))
+    }
+
+    // One line per watch with its current value, for the overlay
+    pub fn lines(&self, chip8: &Chip8) -> Vec<String> {
+        self.list
+            .iter()
+            .map(|(name, expr)| format!("{} {}", name, expr.eval(chip8)))
+            .collect()
+    }
+}